@@ -0,0 +1,14 @@
+//! A report of the changes between two revisions of an IDL document, see
+//! [`crate::pidl::compare`]
+
+use super::report_table;
+use crate::pidl::Change;
+
+/// Generate a human readable table of changes, one per line
+pub fn generate_compat_report(changes: &[Change]) -> String {
+    let rows: Vec<[String; 3]> = changes
+        .iter()
+        .map(|c| [c.severity.to_string(), c.path.clone(), c.message.clone()])
+        .collect();
+    report_table(rows)
+}
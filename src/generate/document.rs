@@ -0,0 +1,492 @@
+//! The protocol document shared by the [Typst](super::typst) and
+//! [Markdown](super::markdown) generators, which only differ in their
+//! [`Markup`]
+//!
+//! Every type gets a section with its fields or variants, how each is
+//! encoded, and its largest encoded size, followed by every endpoint, topic
+//! and protocol with their postcard-rpc keys. Types are linked to from
+//! everywhere they are used.
+
+use core::fmt::Write;
+
+use postcard_schema::schema::owned::{
+    OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue,
+};
+
+use crate::pidl::{builtin_name, is_array, is_user_ty};
+
+use super::keys::hex;
+use crate::{
+    pidl::{max_size, sample, Bounds, MaxSize, TopicDirection},
+    Pidl,
+};
+
+/// The markup of a document, which everything but raw text goes through
+///
+/// Raw text is written between backticks, which both Typst and Markdown
+/// read the same way.
+pub(crate) trait Markup {
+    /// Whether every type is followed by a sample value and its encoding
+    const SAMPLES: bool;
+
+    /// Write what comes before the title, such as the generated header
+    fn preamble(&self, out: &mut String, p: &Pidl);
+
+    /// Write a heading, labelled as `target` if given so that it can be
+    /// linked to
+    fn heading(&self, out: &mut String, level: Level, title: &str, target: Option<(Kind, &str)>);
+
+    /// Write a table with a header row
+    fn table<const N: usize>(&self, out: &mut String, header: [&str; N], rows: Vec<[String; N]>);
+
+    /// A link to the section of a type, endpoint, topic or protocol, with
+    /// its name as raw text
+    fn link(&self, kind: Kind, name: &str) -> String;
+
+    /// Text with anything that would be read as markup escaped, except for
+    /// balanced backticks, which are kept as raw text
+    fn text(&self, s: &str) -> String;
+}
+
+/// How deep a heading is
+#[derive(Clone, Copy)]
+pub(crate) enum Level {
+    Title,
+    /// Types, endpoints, topics and protocols
+    Section,
+    /// A type, endpoint, topic or protocol
+    Item,
+    /// A struct variant of an enum
+    Member,
+}
+
+/// What a label or anchor is for, as a type, an endpoint, a topic and a
+/// protocol may all have the same name
+#[derive(Clone, Copy)]
+pub(crate) enum Kind {
+    Type,
+    Endpoint,
+    Topic,
+    Protocol,
+}
+
+/// A unique name usable as a label or an anchor, e.g.
+/// `type-telemetry-Status`
+///
+/// Names keep their case, as names that only differ in case are different.
+pub(crate) fn anchor(kind: Kind, name: &str) -> String {
+    let kind = match kind {
+        Kind::Type => "type",
+        Kind::Endpoint => "endpoint",
+        Kind::Topic => "topic",
+        Kind::Protocol => "protocol",
+    };
+    format!("{kind}-{}", name.replace("::", "-"))
+}
+
+/// How postcard encodes values, which the rest of the document refers to
+const ENCODING_NOTES: &str = "\
+Messages are encoded with postcard. Values are written one after another, \
+with no padding, names or type information. Unsigned integers wider than a \
+byte are varints: 7 bits at a time, least significant first, with the top \
+bit set on every byte but the last. Signed integers are zigzag encoded \
+first, so that small negative numbers stay short. Strings, byte arrays, \
+sequences and maps start with their length as a varint.";
+
+/// Generate a document describing everything in `p`
+pub(crate) fn generate<M: Markup>(m: &M, p: &Pidl) -> String {
+    let mut out = String::new();
+    m.preamble(&mut out, p);
+    m.heading(&mut out, Level::Title, "Protocol reference", None);
+    writeln!(out).unwrap();
+    writeln!(out, "{}", m.text(ENCODING_NOTES)).unwrap();
+
+    if !p.types.is_empty() {
+        m.heading(&mut out, Level::Section, "Types", None);
+    }
+    for ty in p.types.iter() {
+        generate_type(&mut out, m, p, ty);
+    }
+    generate_endpoints(&mut out, m, p);
+    generate_topics(&mut out, m, p);
+    generate_protocols(&mut out, m, p);
+    out
+}
+
+fn generate_type<M: Markup>(out: &mut String, m: &M, p: &Pidl, ty: &OwnedNamedType) {
+    let name = &ty.name;
+    m.heading(
+        out,
+        Level::Item,
+        &format!("`{name}`"),
+        Some((Kind::Type, name)),
+    );
+    writeln!(out).unwrap();
+    if let Some(doc) = p.docs.get(name) {
+        writeln!(out, "{}", m.text(doc)).unwrap();
+        writeln!(out).unwrap();
+    }
+    if let Some(target) = p.aliases.get(name) {
+        writeln!(out, "The same type as {}.", m.link(Kind::Type, target)).unwrap();
+        return;
+    }
+    write!(out, "{}", m.text(description(ty))).unwrap();
+    if let OwnedDataModelType::NewtypeStruct(ont) = &ty.ty {
+        write!(out, " The contents are {}.", type_link(m, p, ont)).unwrap();
+    }
+    if !is_user_ty(ty) {
+        let mut body = String::new();
+        write_body(&mut body, m, p, ty);
+        let encoding = m.text(encoding(ty));
+        write!(
+            out,
+            " It stands for {body}, which is encoded as {encoding}."
+        )
+        .unwrap();
+    }
+    if let Some(bounds) = p.bounds.get(name) {
+        write!(out, " Lengths are limited to {}.", bounds_text(bounds)).unwrap();
+    }
+    writeln!(out, " Largest encoded size: {}.", m.text(&size_text(p, ty))).unwrap();
+
+    match &ty.ty {
+        OwnedDataModelType::TupleStruct(onts) => {
+            let rows = onts
+                .iter()
+                .enumerate()
+                .map(|(i, ont)| [i.to_string(), type_link(m, p, ont), m.text(encoding(ont))])
+                .collect();
+            m.table(out, ["Item", "Type", "Encoding"], rows);
+        }
+        OwnedDataModelType::Struct(fields) => generate_fields(out, m, p, name, fields),
+        OwnedDataModelType::Enum(variants) => {
+            let mut rows = vec![];
+            for (index, v) in variants.iter().enumerate() {
+                let path = format!("{name}.{}", v.name);
+                let contents = match &v.ty {
+                    OwnedDataModelVariant::UnitVariant => "nothing".into(),
+                    OwnedDataModelVariant::NewtypeVariant(ont) => type_link(m, p, ont),
+                    OwnedDataModelVariant::TupleVariant(onts) => onts
+                        .iter()
+                        .map(|ont| type_link(m, p, ont))
+                        .collect::<Vec<_>>()
+                        .join(", then "),
+                    OwnedDataModelVariant::StructVariant(_) => "the fields below".into(),
+                };
+                rows.push([
+                    index.to_string(),
+                    format!("`{}`", v.name),
+                    contents,
+                    member_notes(m, p, &path),
+                ]);
+            }
+            m.table(out, ["Index", "Variant", "Contents", "Description"], rows);
+            for v in variants {
+                let OwnedDataModelVariant::StructVariant(fields) = &v.ty else {
+                    continue;
+                };
+                m.heading(out, Level::Member, &format!("`{name}::{}`", v.name), None);
+                generate_fields(out, m, p, &format!("{name}.{}", v.name), fields);
+            }
+        }
+        _ => {}
+    }
+
+    if !M::SAMPLES {
+        return;
+    }
+    if let Some(sample) = sample(p, ty) {
+        let bytes: Vec<String> = sample.bytes.iter().map(|b| format!("{b:02x}")).collect();
+        writeln!(out).unwrap();
+        if bytes.is_empty() {
+            writeln!(
+                out,
+                "Sample: `{}`, which is encoded as no bytes.",
+                sample.value
+            )
+            .unwrap();
+            return;
+        }
+        writeln!(out, "Sample: `{}`, which is encoded as:", sample.value).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "```text").unwrap();
+        writeln!(out, "{}", bytes.join(" ")).unwrap();
+        writeln!(out, "```").unwrap();
+    }
+}
+
+/// A table of the fields of a struct or struct variant, whose path is
+/// `owner`
+fn generate_fields<M: Markup>(
+    out: &mut String,
+    m: &M,
+    p: &Pidl,
+    owner: &str,
+    fields: &[OwnedNamedValue],
+) {
+    let rows = fields
+        .iter()
+        .map(|f| {
+            let path = format!("{owner}.{}", f.name);
+            [
+                format!("`{}`", f.name),
+                type_link(m, p, &f.ty),
+                m.text(encoding(&f.ty)),
+                member_notes(m, p, &path),
+            ]
+        })
+        .collect();
+    m.table(out, ["Field", "Type", "Encoding", "Description"], rows);
+}
+
+/// The length limits and documentation of a member
+fn member_notes<M: Markup>(m: &M, p: &Pidl, path: &str) -> String {
+    let mut notes = vec![];
+    if let Some(bounds) = p.bounds.get(path) {
+        notes.push(format!("Length {}.", bounds_text(bounds)));
+    }
+    if let Some(doc) = p.docs.get(path) {
+        notes.push(m.text(doc));
+    }
+    notes.join(" ")
+}
+
+fn generate_endpoints<M: Markup>(out: &mut String, m: &M, p: &Pidl) {
+    if p.endpoints.is_empty() {
+        return;
+    }
+    m.heading(out, Level::Section, "Endpoints", None);
+    for ep in p.endpoints.iter() {
+        let title = format!("`{}`", ep.name);
+        m.heading(out, Level::Item, &title, Some((Kind::Endpoint, &ep.name)));
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "Requests to `{}` are {}, and are answered with {}.",
+            ep.path,
+            type_link(m, p, &ep.req),
+            type_link(m, p, &ep.resp)
+        )
+        .unwrap();
+        let rows = vec![
+            [
+                "Request".into(),
+                m.text(&size_text(p, &ep.req)),
+                key_text(ep.req_key()),
+            ],
+            [
+                "Response".into(),
+                m.text(&size_text(p, &ep.resp)),
+                key_text(ep.resp_key()),
+            ],
+        ];
+        m.table(out, ["Message", "Largest size", "Key"], rows);
+    }
+}
+
+fn generate_topics<M: Markup>(out: &mut String, m: &M, p: &Pidl) {
+    if p.topics.is_empty() {
+        return;
+    }
+    m.heading(out, Level::Section, "Topics", None);
+    for tp in p.topics.iter() {
+        let direction = match tp.direction {
+            TopicDirection::ToServer => "from the client to the server",
+            TopicDirection::ToClient => "from the server to the client",
+        };
+        let title = format!("`{}`", tp.name);
+        m.heading(out, Level::Item, &title, Some((Kind::Topic, &tp.name)));
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "Messages on `{}` are {}, and are sent {direction}. Largest encoded size: {}. \
+             Key: {}.",
+            tp.path,
+            type_link(m, p, &tp.ty),
+            m.text(&size_text(p, &tp.ty)),
+            key_text(tp.key())
+        )
+        .unwrap();
+    }
+}
+
+fn generate_protocols<M: Markup>(out: &mut String, m: &M, p: &Pidl) {
+    if p.protocols.is_empty() {
+        return;
+    }
+    m.heading(out, Level::Section, "Protocols", None);
+    for proto in p.protocols.iter() {
+        let title = format!("`{}`", proto.name);
+        m.heading(
+            out,
+            Level::Item,
+            &title,
+            Some((Kind::Protocol, &proto.name)),
+        );
+        writeln!(out).unwrap();
+        let links = |kind, names: Vec<&str>| match names.is_empty() {
+            true => "none".to_string(),
+            false => names
+                .iter()
+                .map(|name| m.link(kind, name))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let endpoints = proto.endpoints.iter().map(|ep| ep.name.as_str()).collect();
+        let topics = proto.topics.iter().map(|tp| tp.name.as_str()).collect();
+        writeln!(out, "- Endpoints: {}", links(Kind::Endpoint, endpoints)).unwrap();
+        writeln!(out, "- Topics: {}", links(Kind::Topic, topics)).unwrap();
+    }
+}
+
+/// A type reference in IDL syntax, with links to every user type and alias
+fn type_link<M: Markup>(m: &M, p: &Pidl, ont: &OwnedNamedType) -> String {
+    let mut out = String::new();
+    write_type(&mut out, m, p, ont);
+    out
+}
+
+fn key_text(key: postcard_schema::key::Key) -> String {
+    format!("`{}`", hex(key))
+}
+
+/// Write a type reference in IDL syntax, with user types and aliases
+/// linked to, and everything else as raw text
+fn write_type<M: Markup>(out: &mut String, m: &M, p: &Pidl, ont: &OwnedNamedType) {
+    match is_user_ty(ont) || p.types.iter().any(|t| t.name == ont.name) {
+        true => out.push_str(&m.link(Kind::Type, &ont.name)),
+        false => write_body(out, m, p, ont),
+    }
+}
+
+/// Write the body of a type, which for an alias is the type it stands for,
+/// see [`write_type`]
+fn write_body<M: Markup>(out: &mut String, m: &M, p: &Pidl, ont: &OwnedNamedType) {
+    match &ont.ty {
+        OwnedDataModelType::Option(inner) => {
+            raw(out, "option<");
+            write_type(out, m, p, inner);
+            raw(out, ">");
+        }
+        OwnedDataModelType::Seq(inner) => {
+            raw(out, "[");
+            write_type(out, m, p, inner);
+            raw(out, "]");
+        }
+        OwnedDataModelType::Tuple(onts) if is_array(ont) => {
+            raw(out, "[");
+            write_type(out, m, p, &onts[0]);
+            raw(out, &format!("; {}]", onts.len()));
+        }
+        OwnedDataModelType::Tuple(onts) => {
+            raw(out, "(");
+            for (i, ont) in onts.iter().enumerate() {
+                if i > 0 {
+                    raw(out, ", ");
+                }
+                write_type(out, m, p, ont);
+            }
+            raw(out, if onts.len() == 1 { ",)" } else { ")" });
+        }
+        OwnedDataModelType::Map { key, val } => {
+            raw(out, "map<");
+            write_type(out, m, p, key);
+            raw(out, ", ");
+            write_type(out, m, p, val);
+            raw(out, ">");
+        }
+        OwnedDataModelType::Schema => raw(out, "schema"),
+        ty => raw(
+            out,
+            builtin_name(ty).expect("user types are written by `write_type`"),
+        ),
+    }
+}
+
+/// Write raw text, joined to any raw text just before it, so that nothing
+/// reads as an empty raw span
+fn raw(out: &mut String, s: &str) {
+    if out.ends_with('`') {
+        out.pop();
+    } else {
+        out.push('`');
+    }
+    out.push_str(s);
+    out.push('`');
+}
+
+/// What a definition is, and how it is encoded
+fn description(ty: &OwnedNamedType) -> &'static str {
+    match &ty.ty {
+        OwnedDataModelType::UnitStruct => "A unit struct, which is encoded as nothing.",
+        OwnedDataModelType::NewtypeStruct(_) => {
+            "A newtype struct, which is encoded as its contents."
+        }
+        OwnedDataModelType::TupleStruct(_) => {
+            "A tuple struct, which is encoded as its items in order."
+        }
+        OwnedDataModelType::Struct(_) => {
+            "A struct, which is encoded as its fields in order, without their names."
+        }
+        OwnedDataModelType::Enum(_) => {
+            "An enum, which is encoded as the index of the variant as a varint, followed by \
+             the contents of the variant."
+        }
+        _ => "An alias.",
+    }
+}
+
+/// How a value of a type is encoded, briefly
+fn encoding(ont: &OwnedNamedType) -> &'static str {
+    match &ont.ty {
+        OwnedDataModelType::Bool => "1 byte, 0 or 1",
+        OwnedDataModelType::U8 => "1 byte",
+        OwnedDataModelType::I8 => "1 byte, two's complement",
+        OwnedDataModelType::U16
+        | OwnedDataModelType::U32
+        | OwnedDataModelType::U64
+        | OwnedDataModelType::U128
+        | OwnedDataModelType::Usize => "varint",
+        OwnedDataModelType::I16
+        | OwnedDataModelType::I32
+        | OwnedDataModelType::I64
+        | OwnedDataModelType::I128
+        | OwnedDataModelType::Isize => "zigzag varint",
+        OwnedDataModelType::F32 => "4 bytes, little endian",
+        OwnedDataModelType::F64 => "8 bytes, little endian",
+        OwnedDataModelType::Char | OwnedDataModelType::String => {
+            "varint length in bytes, then UTF-8"
+        }
+        OwnedDataModelType::ByteArray => "varint length, then the bytes",
+        OwnedDataModelType::Option(_) => "1 byte, 0 for none, or 1 followed by the value",
+        OwnedDataModelType::Seq(_) => "varint length, then each item",
+        OwnedDataModelType::Tuple(_) => "each item in order",
+        OwnedDataModelType::Map { .. } => "varint length, then each key and its value",
+        OwnedDataModelType::Unit => "nothing",
+        OwnedDataModelType::Schema => "a postcard-schema schema",
+        OwnedDataModelType::UnitStruct
+        | OwnedDataModelType::NewtypeStruct(_)
+        | OwnedDataModelType::TupleStruct(_)
+        | OwnedDataModelType::Struct(_)
+        | OwnedDataModelType::Enum(_) => "see the type",
+    }
+}
+
+/// Length limits, e.g. `1 to 16`
+fn bounds_text(bounds: &Bounds) -> String {
+    match (bounds.min, bounds.max) {
+        (Some(min), Some(max)) => format!("{min} to {max}"),
+        (Some(min), None) => format!("at least {min}"),
+        (None, Some(max)) => format!("at most {max}"),
+        (None, None) => "unlimited".into(),
+    }
+}
+
+/// The largest encoded size of a type, in bytes
+fn size_text(p: &Pidl, ty: &OwnedNamedType) -> String {
+    match max_size(p, ty) {
+        MaxSize::Bounded(1) => "1 byte".into(),
+        MaxSize::Bounded(size) => format!("{size} bytes"),
+        size => size.to_string(),
+    }
+}
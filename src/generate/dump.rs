@@ -0,0 +1,84 @@
+//! The exported form of a [`Pidl`] as JSON or YAML, see
+//! [`crate::pidl::export`]
+
+use serde_json::{Map, Value};
+
+use crate::{pidl::export::export, Pidl};
+
+/// Generate the exported form as pretty printed JSON
+pub fn generate_json(p: &Pidl) -> String {
+    serde_json::to_string_pretty(&export(p)).expect("the export always serializes to JSON")
+}
+
+/// Generate the exported form as YAML, with the same contents as
+/// [`generate_json`]
+///
+/// Keys are sorted, and strings are always quoted, so nothing is read back
+/// as a number or a boolean.
+pub fn generate_yaml(p: &Pidl) -> String {
+    let value = serde_json::to_value(export(p)).expect("the export always serializes to JSON");
+    let mut out = String::new();
+    match &value {
+        Value::Object(map) if !map.is_empty() => write_entries(&mut out, map, 0, false),
+        value => write_child(&mut out, value, 0),
+    }
+    out
+}
+
+/// Write the entries of a map, one per line. If `inline`, the first entry
+/// follows a `- ` that has already been written.
+fn write_entries(out: &mut String, map: &Map<String, Value>, indent: usize, inline: bool) {
+    for (i, (key, value)) in map.iter().enumerate() {
+        if i > 0 || !inline {
+            out.push_str(&" ".repeat(indent));
+        }
+        out.push_str(&yaml_key(key));
+        out.push(':');
+        write_child(out, value, indent + 2);
+    }
+}
+
+fn write_items(out: &mut String, items: &[Value], indent: usize) {
+    for item in items {
+        out.push_str(&" ".repeat(indent));
+        out.push('-');
+        match item {
+            Value::Object(map) if !map.is_empty() => {
+                out.push(' ');
+                write_entries(out, map, indent + 2, true);
+            }
+            item => write_child(out, item, indent + 2),
+        }
+    }
+}
+
+/// Write a value after a `key:` or `-`, on the same line if it is a scalar
+fn write_child(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            write_entries(out, map, indent, false);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_items(out, items, indent);
+        }
+        Value::Object(_) => out.push_str(" {}\n"),
+        Value::Array(_) => out.push_str(" []\n"),
+        // JSON strings are valid double quoted YAML strings
+        scalar => {
+            out.push(' ');
+            out.push_str(&scalar.to_string());
+            out.push('\n');
+        }
+    }
+}
+
+fn yaml_key(key: &str) -> String {
+    let plain = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match plain {
+        true => key.to_string(),
+        false => Value::String(key.into()).to_string(),
+    }
+}
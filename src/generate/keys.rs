@@ -0,0 +1,50 @@
+//! A report of the postcard-rpc keys of all endpoints and topics
+//!
+//! This is meant for clients that aren't written in Rust, which need the keys
+//! but can't compute them with `Key::for_path`.
+
+use postcard_schema::key::Key;
+
+use super::report_table;
+use crate::{pidl::TopicDirection, Pidl};
+
+/// Generate a human readable table of all keys, one per line
+pub fn generate_key_report(p: &Pidl) -> String {
+    let mut rows: Vec<[String; 5]> = vec![];
+    for ep in p.endpoints.iter() {
+        rows.push([
+            "endpoint".into(),
+            ep.name.clone(),
+            ep.path.clone(),
+            "req".into(),
+            hex(ep.req_key()),
+        ]);
+        rows.push([
+            String::new(),
+            String::new(),
+            String::new(),
+            "resp".into(),
+            hex(ep.resp_key()),
+        ]);
+    }
+    for tp in p.topics.iter() {
+        let direction = match tp.direction {
+            TopicDirection::ToServer => "in",
+            TopicDirection::ToClient => "out",
+        };
+        rows.push([
+            "topic".into(),
+            tp.name.clone(),
+            tp.path.clone(),
+            direction.into(),
+            hex(tp.key()),
+        ]);
+    }
+
+    report_table(rows)
+}
+
+/// Format a key as hex bytes
+pub(super) fn hex(key: Key) -> String {
+    key.to_bytes().iter().map(|b| format!("{b:02x}")).collect()
+}
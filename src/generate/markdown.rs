@@ -0,0 +1,86 @@
+//! A protocol reference in Markdown, for docs sites and READMEs
+//!
+//! This has the same contents as the [Typst document](super::typst), with
+//! an anchor for every type, endpoint and topic, and a sample value of every
+//! type with its encoding in hex.
+
+use core::fmt::Write;
+
+use super::document::{self, anchor, Kind, Level, Markup};
+use crate::Pidl;
+
+/// Generate a Markdown reference for everything in `p`
+pub fn generate_markdown(p: &Pidl) -> String {
+    document::generate(&Markdown, p)
+}
+
+struct Markdown;
+
+impl Markup for Markdown {
+    const SAMPLES: bool = true;
+
+    fn preamble(&self, out: &mut String, p: &Pidl) {
+        writeln!(out, "<!-- @generated by pidl, do not edit -->").unwrap();
+        writeln!(out, "<!-- source hash: {:016x} -->", p.source_hash).unwrap();
+    }
+
+    /// A heading with an explicit anchor, as generated anchors differ
+    /// between renderers
+    fn heading(&self, out: &mut String, level: Level, title: &str, target: Option<(Kind, &str)>) {
+        let marks = match level {
+            Level::Title => "#",
+            Level::Section => "##",
+            Level::Item => "###",
+            Level::Member => "####",
+        };
+        writeln!(out).unwrap();
+        if let Some((kind, name)) = target {
+            writeln!(out, "<a id=\"{}\"></a>", anchor(kind, name)).unwrap();
+            writeln!(out).unwrap();
+        }
+        writeln!(out, "{marks} {title}").unwrap();
+    }
+
+    fn table<const N: usize>(&self, out: &mut String, header: [&str; N], rows: Vec<[String; N]>) {
+        writeln!(out).unwrap();
+        writeln!(out, "| {} |", header.join(" | ")).unwrap();
+        writeln!(out, "|{}", " --- |".repeat(N)).unwrap();
+        for row in rows {
+            let cells: Vec<String> = row.iter().map(|cell| cell.replace('\n', "<br>")).collect();
+            writeln!(out, "| {} |", cells.join(" | ")).unwrap();
+        }
+    }
+
+    fn link(&self, kind: Kind, name: &str) -> String {
+        format!("[`{name}`](#{})", anchor(kind, name))
+    }
+
+    fn text(&self, s: &str) -> String {
+        text(s)
+    }
+}
+
+/// Text with anything Markdown would treat as markup escaped, except for
+/// balanced backticks, which are kept as code spans
+fn text(s: &str) -> String {
+    let keep_code = s.matches('`').count().is_multiple_of(2);
+    let mut out = String::new();
+    let mut in_code = false;
+    for c in s.chars() {
+        match c {
+            '`' if keep_code => {
+                in_code = !in_code;
+                out.push(c);
+            }
+            // Pipes end table cells even within code spans
+            '|' => out.push_str("\\|"),
+            _ if in_code => out.push(c),
+            '\\' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '`' | '&' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
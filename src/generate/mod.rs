@@ -1 +1,32 @@
+pub mod compat;
+mod document;
+pub mod dump;
+pub mod keys;
+pub mod markdown;
+pub mod rust_heapless;
 pub mod rust_std;
+pub mod sizes;
+pub mod typst;
+
+use core::fmt::Write;
+
+/// Lay out the rows of a report as aligned columns, one row per line
+fn report_table<const N: usize>(rows: Vec<[String; N]>) -> String {
+    let mut widths = [0; N];
+    for row in rows.iter() {
+        for (w, cell) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, w)| format!("{cell:<w$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(&mut out, "{}", line.trim_end()).unwrap();
+    }
+    out
+}
@@ -0,0 +1,270 @@
+//! A Rust generator for `no_std` targets without an allocator
+//!
+//! This writes the same types as [`super::rust_std`], except that strings,
+//! sequences and maps are `heapless::String<N>`, `heapless::Vec<T, N>` and
+//! `heapless::FnvIndexMap<K, V, N>`. The capacities come from the `max=`
+//! bounds in the IDL, see [`crate::pidl::Bounds`].
+//! Any `min=` bounds are checked by the `within_bounds` methods of the
+//! types, as with `rust_std`.
+//!
+//! The generated code needs the `serde` feature of `heapless` 0.8, and the
+//! `heapless-v0_8` feature of `postcard-schema`. `postcard-schema` has no
+//! `Schema` for maps, so types containing them don't derive it, and can't
+//! be sent by endpoints or topics.
+
+use std::collections::BTreeMap;
+
+use miette::Diagnostic;
+use postcard_schema::schema::owned::{
+    OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue,
+};
+use thiserror::Error;
+
+use super::rust_std::{
+    contains_map, generate_header, generate_imports, generate_keys, generate_rpc, generate_std_ty,
+    nest_modules, reaches, split_path, type_meta, Bounded, Output, Recursion, Refr,
+};
+use crate::{pidl::BoundsScope, Pidl};
+
+/// Errors for IDL documents that can't be written as `heapless` types
+#[derive(Error, Debug, Diagnostic)]
+pub enum Error {
+    #[error("No capacity for the {kind} in {location}")]
+    #[diagnostic(help(
+        "set one with `max=`, e.g. `tags \"[Tag]\" max=8`; the items of sequences and maps \
+         need an alias with its own `max=`, e.g. `alias \"Tag\" \"string\" max=16`"
+    ))]
+    MissingCapacity {
+        /// What needs a capacity, e.g. `string`
+        kind: &'static str,
+        /// The member or endpoint it is part of, e.g. ``field `label` of `Status` ``
+        location: String,
+    },
+
+    #[error("`{name}` is recursive")]
+    #[diagnostic(help("recursive types need a `Box` or `Vec`, which need an allocator"))]
+    Recursive { name: String },
+
+    #[error("No `heapless` type for the schema in {location}")]
+    #[diagnostic(help("schemas are written as `OwnedNamedType`, which needs an allocator"))]
+    Schema {
+        /// The member or endpoint it is part of, e.g. ``field `label` of `Status` ``
+        location: String,
+    },
+
+    #[error("No `Schema` for {location}, as it contains a map")]
+    #[diagnostic(help(
+        "postcard-rpc needs a `Schema` for every message, and postcard-schema has none for \
+         `heapless` maps; use a sequence of key and value pairs instead"
+    ))]
+    MapInMessage {
+        /// The endpoint or topic, e.g. ``the response of endpoint `GetStatus` ``
+        location: String,
+    },
+}
+
+/// Options for [`generate_rust_heapless`]
+#[derive(Default, Debug, Clone)]
+pub struct Options {
+    /// Extra traits to derive for every generated type, e.g. `Debug`
+    pub derives: Vec<String>,
+    /// Extra outer attributes for every generated type, e.g.
+    /// `#[non_exhaustive]`
+    pub attrs: Vec<String>,
+}
+
+/// What the shared Rust writers need to know to write `heapless` types
+pub(crate) struct Heapless<'a> {
+    /// The user types that contain a map, directly or through other types
+    maps: Vec<&'a str>,
+}
+
+impl<'a> Heapless<'a> {
+    fn new(types: &'a [OwnedNamedType], recursion: &Recursion<'a>) -> Self {
+        let direct: Vec<&str> = types
+            .iter()
+            .filter(|t| contains_map(t))
+            .map(|t| t.name.as_str())
+            .collect();
+        let maps = types
+            .iter()
+            .map(|t| t.name.as_str())
+            .filter(|name| reaches(&recursion.all, name, |n| direct.contains(&n)))
+            .collect();
+        Self { maps }
+    }
+
+    /// Can `Schema` be derived for this type?
+    pub(crate) fn has_schema(&self, name: &str) -> bool {
+        !self.maps.contains(&name)
+    }
+}
+
+/// Generate `no_std` Rust types, along with any endpoints and topics
+///
+/// Fails if any string, sequence or map has no capacity, if any type is
+/// recursive or contains a schema, or if any endpoint or topic sends a map.
+pub fn generate_rust_heapless(p: &Pidl, options: &Options) -> Result<Output, Error> {
+    let recursion = Recursion::new(&p.types);
+    check_types(p, &recursion)?;
+    let heapless = Heapless::new(&p.types, &recursion);
+    let bounded = Bounded::new(p, &recursion, true);
+
+    let base = Refr {
+        recursion: &recursion,
+        module: "",
+        within: None,
+        borrows: None,
+        bounds: BoundsScope::new(&p.bounds),
+        heapless: Some(&heapless),
+        bounded: &bounded,
+        docs: &p.docs,
+        aliases: &p.aliases,
+    };
+    let mut modules: BTreeMap<String, Output> = BTreeMap::new();
+    let mut warnings = vec![];
+    for t in p.types.iter() {
+        let (module, _) = split_path(&t.name);
+        let meta = type_meta(p, t, &options.derives, &options.attrs, &mut warnings);
+        let cx = Refr { module, ..base };
+        generate_std_ty(modules.entry(module.to_string()).or_default(), t, cx, &meta);
+    }
+    let mut out = nest_modules(modules);
+    out.warnings = warnings;
+    generate_rpc(&mut out, p, base);
+    generate_keys(&mut out, p);
+    generate_header(&mut out, p);
+    generate_imports(&mut out, p, &recursion, Some(&heapless));
+    Ok(out)
+}
+
+/// Make sure every type can be written, before writing anything
+fn check_types(p: &Pidl, recursion: &Recursion<'_>) -> Result<(), Error> {
+    let scope = BoundsScope::new(&p.bounds);
+    // Aliases of user types are checked as that type
+    for t in p.types.iter().filter(|t| !p.aliases.contains_key(&t.name)) {
+        if recursion.is_recursive(&t.name) {
+            return Err(Error::Recursive {
+                name: t.name.clone(),
+            });
+        }
+        let name = &t.name;
+        match &t.ty {
+            OwnedDataModelType::UnitStruct => {}
+            OwnedDataModelType::NewtypeStruct(ont) => {
+                check_capacities(ont, scope.member(name), &format!("`{name}`"))?
+            }
+            OwnedDataModelType::TupleStruct(onts) => {
+                for ont in onts {
+                    check_capacities(ont, scope.member(name), &format!("`{name}`"))?;
+                }
+            }
+            OwnedDataModelType::Struct(onvs) => {
+                check_fields(onvs, name, &format!("`{name}`"), scope)?
+            }
+            OwnedDataModelType::Enum(variants) => {
+                for v in variants {
+                    let path = format!("{name}.{}", v.name);
+                    let location = format!("variant `{}` of `{name}`", v.name);
+                    match &v.ty {
+                        OwnedDataModelVariant::UnitVariant => {}
+                        OwnedDataModelVariant::NewtypeVariant(ont) => {
+                            check_capacities(ont, scope.member(&path), &location)?
+                        }
+                        OwnedDataModelVariant::TupleVariant(onts) => {
+                            for ont in onts {
+                                check_capacities(ont, scope.member(&path), &location)?;
+                            }
+                        }
+                        OwnedDataModelVariant::StructVariant(onvs) => {
+                            check_fields(onvs, &path, &location, scope)?
+                        }
+                    }
+                }
+            }
+            // Aliases
+            _ => check_capacities(t, scope.member(name), &format!("`{name}`"))?,
+        }
+    }
+
+    let rpc_tys = p
+        .endpoints
+        .iter()
+        .flat_map(|ep| {
+            [
+                (&ep.req, format!("the request of endpoint `{}`", ep.name)),
+                (&ep.resp, format!("the response of endpoint `{}`", ep.name)),
+            ]
+        })
+        .chain(
+            p.topics
+                .iter()
+                .map(|tp| (&tp.ty, format!("the message of topic `{}`", tp.name))),
+        );
+    for (ont, location) in rpc_tys {
+        check_capacities(ont, scope, &location)?;
+        // Messages are resolved in full, so this includes the maps of any
+        // user types without a `Schema`, see `Heapless::has_schema`
+        if contains_map(ont) {
+            return Err(Error::MapInMessage { location });
+        }
+    }
+    Ok(())
+}
+
+/// Check the fields of a struct or struct variant, whose path is `owner`
+fn check_fields(
+    fields: &[OwnedNamedValue],
+    owner: &str,
+    of: &str,
+    scope: BoundsScope<'_>,
+) -> Result<(), Error> {
+    for f in fields {
+        let path = format!("{owner}.{}", f.name);
+        let location = format!("field `{}` of {of}", f.name);
+        check_capacities(&f.ty, scope.member(&path), &location)?;
+    }
+    Ok(())
+}
+
+/// Make sure every string, sequence and map in a type has a capacity, not
+/// counting the contents of other user types
+fn check_capacities(
+    ont: &OwnedNamedType,
+    scope: BoundsScope<'_>,
+    location: &str,
+) -> Result<(), Error> {
+    let scope = scope.enter(ont);
+    let kind = match &ont.ty {
+        OwnedDataModelType::String => "string",
+        OwnedDataModelType::ByteArray => "byte array",
+        OwnedDataModelType::Seq(ont) => {
+            check_capacities(ont, scope.items(), location)?;
+            "sequence"
+        }
+        OwnedDataModelType::Map { key, val } => {
+            check_capacities(key, scope.items(), location)?;
+            check_capacities(val, scope.items(), location)?;
+            "map"
+        }
+        OwnedDataModelType::Option(ont) => return check_capacities(ont, scope, location),
+        OwnedDataModelType::Schema => {
+            return Err(Error::Schema {
+                location: location.to_string(),
+            })
+        }
+        OwnedDataModelType::Tuple(onts) => {
+            return onts
+                .iter()
+                .try_for_each(|ont| check_capacities(ont, scope, location))
+        }
+        _ => return Ok(()),
+    };
+    match scope.get().max {
+        Some(_) => Ok(()),
+        None => Err(Error::MissingCapacity {
+            kind,
+            location: location.to_string(),
+        }),
+    }
+}
@@ -5,115 +5,1186 @@ use postcard_schema::schema::owned::{
     OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue, OwnedNamedVariant,
 };
 
-use crate::Pidl;
+use super::rust_heapless::Heapless;
+use crate::{
+    pidl::{
+        is_array, is_user_ty, BoundsScope, Endpoint, Protocol, Topic, TopicDirection, TypeMeta,
+    },
+    Pidl,
+};
 use core::fmt::Write;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 #[derive(Default, Debug)]
 pub struct Output {
+    /// The "generated, do not edit" comment, with the hash of the source
+    pub header: String,
+    /// The `use` statements needed by the other sections
+    pub imports: String,
     pub aliases: String,
+    /// Types, followed by any nested modules
     pub types: String,
+    /// The `borrowed` module, if enabled with [`Options::borrowed`]
+    pub borrowed: String,
+    /// The `postcard_rpc::endpoints!` invocation, if there are any endpoints
+    pub endpoints: String,
+    /// The `postcard_rpc::topics!` invocations, one for each direction with
+    /// any topics
+    pub topics: String,
+    /// A module for each protocol, containing its endpoints and topics
+    pub protocols: String,
+    /// The postcard-rpc keys of all endpoints and topics, as constants
+    pub keys: String,
+    /// Anything asked for that couldn't be generated, which isn't part of
+    /// the generated code
+    pub warnings: Vec<String>,
+}
+
+impl core::fmt::Display for Output {
+    /// All sections, as a single file
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let sections = [
+            &self.header,
+            &self.imports,
+            &self.aliases,
+            &self.types,
+            &self.borrowed,
+            &self.endpoints,
+            &self.topics,
+            &self.protocols,
+            &self.keys,
+        ];
+        let mut first = true;
+        for section in sections.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            if !first {
+                writeln!(f)?;
+            }
+            writeln!(f, "{section}")?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// Options for [`generate_rust_std`]
+#[derive(Default, Debug, Clone)]
+pub struct Options {
+    /// Extra traits to derive for every generated type, e.g. `Debug`
+    pub derives: Vec<String>,
+    /// Extra outer attributes for every generated type, e.g.
+    /// `#[non_exhaustive]`
+    pub attrs: Vec<String>,
+    /// Also generate borrowed versions of the types in a `borrowed` module,
+    /// e.g. `borrowed::Status<'a>`, which use `&'a str` and `&'a [u8]` so
+    /// they can be deserialized without allocating.
+    ///
+    /// Types without any borrowed data are re-exported as they are. All
+    /// types derive `Clone`, which the conversions between the owned and
+    /// borrowed versions need.
+    pub borrowed: bool,
 }
 
-pub fn generate_rust_std(p: &Pidl) -> Output {
-    let mut out = Output::default();
+/// The module containing the borrowed versions of all types
+pub(crate) const BORROWED: &str = "borrowed";
+
+pub fn generate_rust_std(p: &Pidl, options: &Options) -> Output {
+    let recursion = Recursion::new(&p.types);
+    let borrows = Borrows::new(&p.types, &recursion);
+    let bounded = Bounded::new(p, &recursion, false);
+    let mut modules: BTreeMap<String, Output> = BTreeMap::new();
+    let mut borrowed: BTreeMap<String, Output> = BTreeMap::new();
+    let mut warnings = vec![];
+    let base = Refr {
+        recursion: &recursion,
+        module: "",
+        within: None,
+        borrows: None,
+        bounds: BoundsScope::new(&p.bounds),
+        heapless: None,
+        bounded: &bounded,
+        docs: &p.docs,
+        aliases: &p.aliases,
+    };
     for t in p.types.iter() {
-        generate_std_ty(&mut out, t);
+        let (module, _) = split_path(&t.name);
+        let mut meta = type_meta(p, t, &options.derives, &options.attrs, &mut warnings);
+        if options.borrowed && !meta.derives.iter().any(|d| d == "Clone") {
+            meta.derives.push("Clone".into());
+        }
+
+        let cx = Refr { module, ..base };
+        let owned = modules.entry(module.to_string()).or_default();
+        generate_std_ty(owned, t, cx, &meta);
+        if options.borrowed {
+            let module = match module {
+                "" => BORROWED.to_string(),
+                module => format!("{BORROWED}::{module}"),
+            };
+            let cx = Refr {
+                module: &module,
+                borrows: Some(&borrows),
+                ..cx
+            };
+            generate_borrowed_ty(borrowed.entry(module.clone()).or_default(), t, cx, &meta);
+        }
+    }
+    let mut out = nest_modules(modules);
+    out.warnings = warnings;
+    if options.borrowed {
+        out.borrowed = nest_modules(borrowed).types;
     }
+    generate_rpc(&mut out, p, base);
+    generate_keys(&mut out, p);
+    generate_header(&mut out, p);
+    generate_imports(&mut out, p, &recursion, None);
     out
 }
 
-fn generate_std_ty(out: &mut Output, ty: &OwnedNamedType) {
+/// The derives and attributes of a type, with the ones from the options
+/// first, then anything set in the IDL
+///
+/// `Default` is derived for enums with `#[default]` on the first unit
+/// variant, so it is dropped for enums without one, with a warning.
+pub(crate) fn type_meta(
+    p: &Pidl,
+    ty: &OwnedNamedType,
+    derives: &[String],
+    attrs: &[String],
+    warnings: &mut Vec<String>,
+) -> TypeMeta {
+    let mut meta = TypeMeta {
+        derives: derives.to_vec(),
+        attrs: attrs.to_vec(),
+    };
+    if let Some(extra) = p.meta.get(&ty.name) {
+        meta.derives.extend(extra.derives.iter().cloned());
+        meta.attrs.extend(extra.attrs.iter().cloned());
+    }
+    let is_default = |d: &String| d == "Default";
+    if let OwnedDataModelType::Enum(variants) = &ty.ty {
+        // Aliases are written as the type they alias
+        if default_variant(variants).is_none()
+            && meta.derives.iter().any(is_default)
+            && !p.aliases.contains_key(&ty.name)
+        {
+            warnings.push(format!(
+                "`Default` is not derived for `{}`, it has no unit variant to be the default",
+                ty.name
+            ));
+            meta.derives.retain(|d| !is_default(d));
+        }
+    }
+    meta
+}
+
+/// The variant of an enum marked `#[default]` when deriving `Default`
+fn default_variant(variants: &[OwnedNamedVariant]) -> Option<&str> {
+    variants
+        .iter()
+        .find(|v| matches!(v.ty, OwnedDataModelVariant::UnitVariant))
+        .map(|v| v.name.as_str())
+}
+
+pub(crate) fn generate_header(out: &mut Output, p: &Pidl) {
+    writeln!(&mut out.header, "// @generated by pidl, do not edit").unwrap();
+    writeln!(&mut out.header, "// source hash: {:016x}", p.source_hash).unwrap();
+}
+
+/// Write a `use` for everything the generated code refers to by name
+pub(crate) fn generate_imports(
+    out: &mut Output,
+    p: &Pidl,
+    recursion: &Recursion<'_>,
+    heapless: Option<&Heapless<'_>>,
+) {
+    let is_user_ty = |t: &&OwnedNamedType| {
+        matches!(
+            t.ty,
+            OwnedDataModelType::UnitStruct
+                | OwnedDataModelType::NewtypeStruct(_)
+                | OwnedDataModelType::TupleStruct(_)
+                | OwnedDataModelType::Struct(_)
+                | OwnedDataModelType::Enum(_)
+        )
+    };
+    let user_tys: Vec<&OwnedNamedType> = p.types.iter().filter(is_user_ty).collect();
+    let rpc_tys = p
+        .endpoints
+        .iter()
+        .flat_map(|ep| [&ep.req, &ep.resp])
+        .chain(p.topics.iter().map(|tp| &tp.ty));
+    let uses_map = p.types.iter().chain(rpc_tys.clone()).any(contains_map);
+    let uses_schema = p.types.iter().chain(rpc_tys).any(contains_schema);
+
+    let mut imports = vec![];
+    // Heapless types are written with their full path
+    if uses_map && heapless.is_none() {
+        imports.push("use std::collections::HashMap;".to_string());
+    }
+    if uses_schema {
+        imports.push("use postcard_schema::schema::owned::OwnedNamedType;".to_string());
+    }
+    let has_schema = |t: &&OwnedNamedType| {
+        !recursion.is_recursive(&t.name) && heapless.is_none_or(|h| h.has_schema(&t.name))
+    };
+    if user_tys.iter().any(has_schema) {
+        imports.push("use postcard_schema::Schema;".to_string());
+    }
+    let macros: Vec<&str> = [
+        ("endpoints", !p.endpoints.is_empty()),
+        ("topics", !p.topics.is_empty()),
+    ]
+    .into_iter()
+    .filter_map(|(name, used)| used.then_some(name))
+    .collect();
+    match macros.as_slice() {
+        [] => {}
+        [one] => imports.push(format!("use postcard_rpc::{one};")),
+        many => imports.push(format!("use postcard_rpc::{{{}}};", many.join(", "))),
+    }
+    if !user_tys.is_empty() {
+        imports.push("use serde::{Deserialize, Serialize};".to_string());
+    }
+
+    // In the order rustfmt would put them
+    imports.sort();
+    for import in imports {
+        writeln!(&mut out.imports, "{import}").unwrap();
+    }
+}
+
+/// Does this type contain a map anywhere, not counting other user types?
+pub(crate) fn contains_map(ont: &OwnedNamedType) -> bool {
+    contains(ont, |ty| matches!(ty, OwnedDataModelType::Map { .. }))
+}
+
+/// Does this type contain a schema anywhere, not counting other user types?
+pub(crate) fn contains_schema(ont: &OwnedNamedType) -> bool {
+    contains(ont, |ty| matches!(ty, OwnedDataModelType::Schema))
+}
+
+/// Does this type contain anything matching `is`?
+fn contains(ont: &OwnedNamedType, is: fn(&OwnedDataModelType) -> bool) -> bool {
+    let any = |onts: &[OwnedNamedType]| onts.iter().any(|ont| contains(ont, is));
+    let any_field = |onvs: &[OwnedNamedValue]| onvs.iter().any(|v| contains(&v.ty, is));
+    if is(&ont.ty) {
+        return true;
+    }
+    match &ont.ty {
+        OwnedDataModelType::Option(ont)
+        | OwnedDataModelType::Seq(ont)
+        | OwnedDataModelType::NewtypeStruct(ont) => contains(ont, is),
+        OwnedDataModelType::Map { key, val } => contains(key, is) || contains(val, is),
+        OwnedDataModelType::Tuple(onts) | OwnedDataModelType::TupleStruct(onts) => any(onts),
+        OwnedDataModelType::Struct(onvs) => any_field(onvs),
+        OwnedDataModelType::Enum(onvs) => onvs.iter().any(|v| match &v.ty {
+            OwnedDataModelVariant::NewtypeVariant(ont) => contains(ont, is),
+            OwnedDataModelVariant::TupleVariant(onts) => any(onts),
+            OwnedDataModelVariant::StructVariant(onvs) => any_field(onvs),
+            OwnedDataModelVariant::UnitVariant => false,
+        }),
+        _ => false,
+    }
+}
+
+/// Split a type name into its module path and the name itself, e.g.
+/// `telemetry::Status` into `telemetry` and `Status`
+pub(crate) fn split_path(name: &str) -> (&str, &str) {
+    name.rsplit_once("::").unwrap_or(("", name))
+}
+
+/// Write every module into its parent as a `pub mod`, returning the root
+pub(crate) fn nest_modules(mut modules: BTreeMap<String, Output>) -> Output {
+    // Parents may not contain any types themselves
+    let mut paths: BTreeSet<String> = BTreeSet::new();
+    for path in modules.keys() {
+        let mut path = path.as_str();
+        while !path.is_empty() {
+            paths.insert(path.to_string());
+            path = split_path(path).0;
+        }
+    }
+
+    // Children sort after their parents, so go in reverse
+    for path in paths.into_iter().rev() {
+        let module = modules.remove(&path).unwrap_or_default();
+        let (parent, name) = split_path(&path);
+        let parent = modules.entry(parent.to_string()).or_default();
+        writeln!(&mut parent.types, "pub mod {name} {{").unwrap();
+        writeln!(&mut parent.types, "    use super::*;").unwrap();
+        writeln!(&mut parent.types).unwrap();
+        let body = [module.aliases.trim(), module.types.trim()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        for line in body.lines() {
+            if line.is_empty() {
+                writeln!(&mut parent.types).unwrap();
+            } else {
+                writeln!(&mut parent.types, "    {line}").unwrap();
+            }
+        }
+        writeln!(&mut parent.types, "}}").unwrap();
+        writeln!(&mut parent.types).unwrap();
+    }
+    modules.remove("").unwrap_or_default()
+}
+
+/// The path to the type `to`, from within the module `from`
+pub(crate) fn relative_path(from: &str, to: &str) -> String {
+    let from: Vec<&str> = from.split("::").filter(|s| !s.is_empty()).collect();
+    let to: Vec<&str> = to.split("::").collect();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count()
+        // the last segment of `to` is the type name
+        .min(to.len() - 1);
+    let mut path = vec!["super"; from.len() - common];
+    path.extend(&to[common..]);
+    path.join("::")
+}
+
+/// The references between user types, used to find recursive types
+pub(crate) struct Recursion<'a> {
+    /// References to types contained by value
+    by_value: HashMap<&'a str, Vec<&'a str>>,
+    /// All references, including ones behind a `Vec` or `HashMap`
+    pub(crate) all: HashMap<&'a str, Vec<&'a str>>,
+}
+
+impl<'a> Recursion<'a> {
+    pub(crate) fn new(types: &'a [OwnedNamedType]) -> Self {
+        let mut by_value = HashMap::new();
+        let mut all = HashMap::new();
+        for t in types {
+            let mut contents: Vec<&OwnedNamedType> = vec![];
+            match &t.ty {
+                OwnedDataModelType::NewtypeStruct(ont) => contents.push(ont),
+                OwnedDataModelType::TupleStruct(onts) => contents.extend(onts),
+                OwnedDataModelType::Struct(onvs) => contents.extend(onvs.iter().map(|v| &v.ty)),
+                OwnedDataModelType::Enum(variants) => {
+                    for v in variants {
+                        match &v.ty {
+                            OwnedDataModelVariant::UnitVariant => {}
+                            OwnedDataModelVariant::NewtypeVariant(ont) => contents.push(ont),
+                            OwnedDataModelVariant::TupleVariant(onts) => contents.extend(onts),
+                            OwnedDataModelVariant::StructVariant(onvs) => {
+                                contents.extend(onvs.iter().map(|v| &v.ty))
+                            }
+                        }
+                    }
+                }
+                _ => continue,
+            }
+            let mut vrefs = vec![];
+            let mut arefs = vec![];
+            for ont in contents {
+                user_ty_refs(ont, false, &mut vrefs);
+                user_ty_refs(ont, true, &mut arefs);
+            }
+            by_value.insert(t.name.as_str(), vrefs);
+            all.insert(t.name.as_str(), arefs);
+        }
+        Self { by_value, all }
+    }
+
+    /// Does a by-value reference from `from` to `to` need a `Box`? This is
+    /// the case when `to` (eventually) contains `from` by value.
+    fn needs_box(&self, from: &str, to: &str) -> bool {
+        reaches(&self.by_value, to, |n| n == from)
+    }
+
+    /// Is this type recursive, or does it contain a recursive type?
+    ///
+    /// `Schema` can't be derived for these types, as the schema would be
+    /// infinitely large.
+    pub(crate) fn is_recursive(&self, name: &str) -> bool {
+        reaches(&self.all, name, |n| {
+            self.all
+                .get(n)
+                .is_some_and(|refs| refs.iter().any(|r| reaches(&self.all, r, |m| m == n)))
+        })
+    }
+}
+
+/// The user types that contain borrowed data in their borrowed versions, and
+/// so need a lifetime
+pub(crate) struct Borrows<'a> {
+    names: Vec<&'a str>,
+}
+
+impl<'a> Borrows<'a> {
+    fn new(types: &'a [OwnedNamedType], recursion: &Recursion<'a>) -> Self {
+        let direct: Vec<&str> = types
+            .iter()
+            .filter(|t| contains_borrowed(t))
+            .map(|t| t.name.as_str())
+            .collect();
+        // References to recursive types may only be stubs, so follow the
+        // references by name
+        let names = types
+            .iter()
+            .map(|t| t.name.as_str())
+            .filter(|name| reaches(&recursion.all, name, |n| direct.contains(&n)))
+            .collect();
+        Self { names }
+    }
+
+    /// Does a reference to this type contain borrowed data?
+    fn ty_borrows(&self, ont: &OwnedNamedType) -> bool {
+        match &ont.ty {
+            OwnedDataModelType::String | OwnedDataModelType::ByteArray => true,
+            OwnedDataModelType::Seq(ont) if ont.ty == OwnedDataModelType::U8 => true,
+            OwnedDataModelType::Option(ont) | OwnedDataModelType::Seq(ont) => self.ty_borrows(ont),
+            OwnedDataModelType::Tuple(onts) => onts.iter().any(|ont| self.ty_borrows(ont)),
+            OwnedDataModelType::Map { key, val } => self.ty_borrows(key) || self.ty_borrows(val),
+            OwnedDataModelType::UnitStruct
+            | OwnedDataModelType::NewtypeStruct(_)
+            | OwnedDataModelType::TupleStruct(_)
+            | OwnedDataModelType::Struct(_)
+            | OwnedDataModelType::Enum(_) => self.names.contains(&ont.name.as_str()),
+            _ => false,
+        }
+    }
+}
+
+/// Does this type contain a string or bytes anywhere, which are borrowed in
+/// the borrowed version of the type?
+fn contains_borrowed(ont: &OwnedNamedType) -> bool {
+    match &ont.ty {
+        OwnedDataModelType::String | OwnedDataModelType::ByteArray => true,
+        OwnedDataModelType::Seq(ont) if ont.ty == OwnedDataModelType::U8 => true,
+        OwnedDataModelType::Option(ont)
+        | OwnedDataModelType::Seq(ont)
+        | OwnedDataModelType::NewtypeStruct(ont) => contains_borrowed(ont),
+        OwnedDataModelType::Tuple(onts) | OwnedDataModelType::TupleStruct(onts) => {
+            onts.iter().any(contains_borrowed)
+        }
+        OwnedDataModelType::Map { key, val } => contains_borrowed(key) || contains_borrowed(val),
+        OwnedDataModelType::Struct(onvs) => onvs.iter().any(|v| contains_borrowed(&v.ty)),
+        OwnedDataModelType::Enum(onvs) => onvs.iter().any(|v| match &v.ty {
+            OwnedDataModelVariant::NewtypeVariant(ont) => contains_borrowed(ont),
+            OwnedDataModelVariant::TupleVariant(onts) => onts.iter().any(contains_borrowed),
+            OwnedDataModelVariant::StructVariant(onvs) => {
+                onvs.iter().any(|v| contains_borrowed(&v.ty))
+            }
+            OwnedDataModelVariant::UnitVariant => false,
+        }),
+        _ => false,
+    }
+}
+
+/// The user types with lengths to check against their [`crate::pidl::Bounds`],
+/// directly or through other types, which get a `within_bounds` method
+///
+/// Maximums that are the capacity of a `heapless` type are already enforced
+/// by the type, so aren't checked.
+pub(crate) struct Bounded<'a> {
+    names: Vec<&'a str>,
+    heapless: bool,
+}
+
+impl<'a> Bounded<'a> {
+    pub(crate) fn new(p: &'a Pidl, recursion: &Recursion<'a>, heapless: bool) -> Self {
+        let mut bounded = Self {
+            names: vec![],
+            heapless,
+        };
+        let scope = BoundsScope::new(&p.bounds);
+        let direct: Vec<&str> = p
+            .types
+            .iter()
+            .filter(|t| bounded.check_ty(t, scope).is_some())
+            .map(|t| t.name.as_str())
+            .collect();
+        bounded.names = p
+            .types
+            .iter()
+            .map(|t| t.name.as_str())
+            .filter(|name| reaches(&recursion.all, name, |n| direct.contains(&n)))
+            .collect();
+        bounded
+    }
+
+    /// The body of `within_bounds` for a user type, if there is anything to
+    /// check
+    fn check_ty(&self, ty: &OwnedNamedType, scope: BoundsScope<'_>) -> Option<String> {
+        let name = &ty.name;
+        let scope = scope.member(name);
+        let conds: Vec<String> = match &ty.ty {
+            OwnedDataModelType::NewtypeStruct(ont) => {
+                self.check(ont, "self.0", scope, 0).into_iter().collect()
+            }
+            OwnedDataModelType::TupleStruct(onts) => onts
+                .iter()
+                .enumerate()
+                .filter_map(|(i, ont)| self.check(ont, &format!("self.{i}"), scope, 0))
+                .collect(),
+            OwnedDataModelType::Struct(onvs) => onvs
+                .iter()
+                .filter_map(|v| {
+                    let path = format!("{name}.{}", v.name);
+                    self.check(&v.ty, &format!("self.{}", v.name), scope.member(&path), 0)
+                })
+                .collect(),
+            OwnedDataModelType::Enum(variants) => {
+                return self.check_variants(name, variants, scope)
+            }
+            _ => vec![],
+        };
+        (!conds.is_empty()).then(|| conds.join("\n            && "))
+    }
+
+    /// A `match` checking the variants of an enum, if there is anything to
+    /// check
+    fn check_variants(
+        &self,
+        name: &str,
+        variants: &[OwnedNamedVariant],
+        scope: BoundsScope<'_>,
+    ) -> Option<String> {
+        let mut arms = vec![];
+        for v in variants {
+            let path = format!("{name}.{}", v.name);
+            let scope = scope.member(&path);
+            let arm = match &v.ty {
+                OwnedDataModelVariant::UnitVariant => None,
+                OwnedDataModelVariant::NewtypeVariant(ont) => self
+                    .check(ont, "v0", scope, 0)
+                    .map(|cond| format!("Self::{}(v0) => {cond},", v.name)),
+                OwnedDataModelVariant::TupleVariant(onts) => {
+                    let conds: Vec<_> = onts
+                        .iter()
+                        .enumerate()
+                        .map(|(i, ont)| self.check(ont, &format!("v{i}"), scope, 0))
+                        .collect();
+                    let binds: Vec<_> = conds
+                        .iter()
+                        .enumerate()
+                        .map(|(i, cond)| match cond {
+                            Some(_) => format!("v{i}"),
+                            None => "_".to_string(),
+                        })
+                        .collect();
+                    all(conds.into_iter())
+                        .map(|cond| format!("Self::{}({}) => {cond},", v.name, binds.join(", ")))
+                }
+                OwnedDataModelVariant::StructVariant(onvs) => {
+                    let mut binds = vec![];
+                    let cond = all(onvs.iter().map(|f| {
+                        let path = format!("{path}.{}", f.name);
+                        let cond = self.check(&f.ty, &f.name, scope.member(&path), 0);
+                        if cond.is_some() {
+                            binds.push(f.name.as_str());
+                        }
+                        cond
+                    }));
+                    if binds.len() < onvs.len() {
+                        binds.push("..");
+                    }
+                    cond.map(|cond| {
+                        format!("Self::{} {{ {} }} => {cond},", v.name, binds.join(", "))
+                    })
+                }
+            };
+            arms.extend(arm);
+        }
+        if arms.is_empty() {
+            return None;
+        }
+        if arms.len() < variants.len() {
+            arms.push("_ => true,".to_string());
+        }
+        let mut body = String::from("match self {\n");
+        for arm in arms {
+            writeln!(&mut body, "            {arm}").unwrap();
+        }
+        body.push_str("        }");
+        Some(body)
+    }
+
+    /// An expression checking the value `e` of type `ont`, if there is
+    /// anything to check. `depth` numbers the closure arguments.
+    fn check(
+        &self,
+        ont: &OwnedNamedType,
+        e: &str,
+        scope: BoundsScope<'_>,
+        depth: usize,
+    ) -> Option<String> {
+        let scope = scope.enter(ont);
+        let x = format!("x{depth}");
+        match &ont.ty {
+            OwnedDataModelType::String | OwnedDataModelType::ByteArray => {
+                self.check_len(e, scope, false)
+            }
+            OwnedDataModelType::Seq(item) => {
+                let items = self
+                    .check(item, &x, scope.items(), depth + 1)
+                    .map(|cond| format!("{e}.iter().all(|{x}| {cond})"));
+                all([self.check_len(e, scope, false), items].into_iter())
+            }
+            OwnedDataModelType::Map { key, val } => {
+                let keys = self
+                    .check(key, &x, scope.items(), depth + 1)
+                    .map(|cond| format!("{e}.keys().all(|{x}| {cond})"));
+                let vals = self
+                    .check(val, &x, scope.items(), depth + 1)
+                    .map(|cond| format!("{e}.values().all(|{x}| {cond})"));
+                all([self.check_len(e, scope, true), keys, vals].into_iter())
+            }
+            OwnedDataModelType::Option(ont) => self
+                .check(ont, &x, scope, depth + 1)
+                .map(|cond| format!("{e}.iter().all(|{x}| {cond})")),
+            OwnedDataModelType::Tuple(onts) => {
+                let array = is_array(ont);
+                all(onts.iter().enumerate().map(|(i, ont)| {
+                    let e = match array {
+                        true => format!("{e}[{i}]"),
+                        false => format!("{e}.{i}"),
+                    };
+                    self.check(ont, &e, scope, depth)
+                }))
+            }
+            _ if is_user_ty(ont) && self.names.contains(&ont.name.as_str()) => {
+                Some(format!("{e}.within_bounds()"))
+            }
+            _ => None,
+        }
+    }
+
+    /// An expression checking the length of `e`, if it has any bounds
+    fn check_len(&self, e: &str, scope: BoundsScope<'_>, is_map: bool) -> Option<String> {
+        let bounds = scope.get();
+        let min = bounds.min.filter(|min| *min > 0);
+        // `heapless` maps round their capacity up to a power of two
+        let max = bounds.max.filter(|_| !self.heapless || is_map);
+        match (min, max) {
+            (None, None) => None,
+            (Some(min), Some(max)) if min == max => Some(format!("{e}.len() == {min}")),
+            (Some(min), Some(max)) => Some(format!("({min}..={max}).contains(&{e}.len())")),
+            (Some(1), None) => Some(format!("!{e}.is_empty()")),
+            (Some(min), None) => Some(format!("{e}.len() >= {min}")),
+            (None, Some(0)) => Some(format!("{e}.is_empty()")),
+            (None, Some(max)) => Some(format!("{e}.len() <= {max}")),
+        }
+    }
+}
+
+/// All of the given conditions, if there are any
+fn all(conds: impl Iterator<Item = Option<String>>) -> Option<String> {
+    let conds: Vec<String> = conds.flatten().collect();
+    (!conds.is_empty()).then(|| conds.join(" && "))
+}
+
+/// Write a `within_bounds` method for a user type, if it has anything to
+/// check
+fn generate_within_bounds(out: &mut Output, ty: &OwnedNamedType, cx: Refr<'_>) {
+    let Some(body) = cx.bounded.check_ty(ty, cx.bounds) else {
+        return;
+    };
+    let (_, name) = split_path(&ty.name);
+    writeln!(&mut out.types, "impl {name} {{").unwrap();
+    writeln!(
+        &mut out.types,
+        "    /// Are all lengths within the limits set in the IDL?"
+    )
+    .unwrap();
+    writeln!(&mut out.types, "    pub fn within_bounds(&self) -> bool {{").unwrap();
+    writeln!(&mut out.types, "        {body}").unwrap();
+    writeln!(&mut out.types, "    }}").unwrap();
+    writeln!(&mut out.types, "}}").unwrap();
+    writeln!(&mut out.types).unwrap();
+}
+
+/// Can any type matching `pred` be reached from `start`, including `start`?
+pub(crate) fn reaches<'a>(
+    edges: &HashMap<&'a str, Vec<&'a str>>,
+    start: &'a str,
+    pred: impl Fn(&'a str) -> bool,
+) -> bool {
+    let mut seen = vec![];
+    let mut todo = vec![start];
+    while let Some(next) = todo.pop() {
+        if pred(next) {
+            return true;
+        }
+        if seen.contains(&next) {
+            continue;
+        }
+        seen.push(next);
+        if let Some(refs) = edges.get(next) {
+            todo.extend(refs.iter().copied());
+        }
+    }
+    false
+}
+
+/// Collect the user types referenced by the given type. Types behind a `Vec`
+/// or `HashMap` are only collected if `heap` is set.
+fn user_ty_refs<'a>(ont: &'a OwnedNamedType, heap: bool, out: &mut Vec<&'a str>) {
+    match &ont.ty {
+        OwnedDataModelType::UnitStruct
+        | OwnedDataModelType::NewtypeStruct(_)
+        | OwnedDataModelType::TupleStruct(_)
+        | OwnedDataModelType::Struct(_)
+        | OwnedDataModelType::Enum(_) => out.push(&ont.name),
+        OwnedDataModelType::Option(ont) => user_ty_refs(ont, heap, out),
+        OwnedDataModelType::Tuple(onts) => onts.iter().for_each(|ont| user_ty_refs(ont, heap, out)),
+        OwnedDataModelType::Seq(ont) if heap => user_ty_refs(ont, heap, out),
+        OwnedDataModelType::Map { key, val } if heap => {
+            user_ty_refs(key, heap, out);
+            user_ty_refs(val, heap, out);
+        }
+        _ => {}
+    }
+}
+
+/// Context for writing a reference to a type
+#[derive(Clone, Copy)]
+pub(crate) struct Refr<'a> {
+    pub(crate) recursion: &'a Recursion<'a>,
+    /// The module the reference is written in
+    pub(crate) module: &'a str,
+    /// The user type containing this reference by value, if any
+    pub(crate) within: Option<&'a str>,
+    /// Set when writing the borrowed versions of types
+    pub(crate) borrows: Option<&'a Borrows<'a>>,
+    /// The length limits of the type being written
+    pub(crate) bounds: BoundsScope<'a>,
+    /// Set when writing `heapless` types, see [`super::rust_heapless`]
+    pub(crate) heapless: Option<&'a Heapless<'a>>,
+    /// The user types with lengths to check
+    pub(crate) bounded: &'a Bounded<'a>,
+    /// Documentation, by member path, see [`Pidl::docs`]
+    pub(crate) docs: &'a BTreeMap<String, String>,
+    /// Aliases of user types, see [`Pidl::aliases`]
+    pub(crate) aliases: &'a BTreeMap<String, String>,
+}
+
+impl<'a> Refr<'a> {
+    /// `#[serde(borrow)]` for fields of borrowed types that need it
+    fn serde_borrow(&self, ont: &OwnedNamedType) -> &'static str {
+        match self.borrows {
+            Some(borrows) if borrows.ty_borrows(ont) => "#[serde(borrow)] ",
+            _ => "",
+        }
+    }
+
+    /// Context for references that are stored on the heap, or for heapless
+    /// types, in the buffer of a sequence or map
+    fn on_heap(self) -> Self {
+        Self {
+            within: None,
+            bounds: self.bounds.items(),
+            ..self
+        }
+    }
+
+    /// Context for the type of a member, see [`Pidl::bounds`]
+    fn member<'b>(self, path: &'b str) -> Refr<'b>
+    where
+        'a: 'b,
+    {
+        Refr {
+            bounds: self.bounds.member(path),
+            ..self
+        }
+    }
+
+    /// The capacity of a `heapless` string, sequence or map
+    fn capacity(&self) -> usize {
+        self.bounds
+            .get()
+            .max
+            .expect("capacities are checked before generating")
+    }
+}
+
+pub(crate) fn generate_std_ty(
+    out: &mut Output,
+    ty: &OwnedNamedType,
+    cx: Refr<'_>,
+    meta: &TypeMeta,
+) {
+    let (_, name) = split_path(&ty.name);
+    // Borrowed types only get here if they have a lifetime
+    let name = &match cx.borrows {
+        Some(_) => format!("{name}<'a>"),
+        None => name.to_string(),
+    };
+    // Aliases of user types are written as aliases, which have the
+    // `within_bounds` of the type
+    if let Some(target) = cx.aliases.get(&ty.name) {
+        generate_doc(&mut out.aliases, cx, &ty.name, "");
+        let mut path = String::new();
+        write_user_ty_refr(&mut path, target, cx).unwrap();
+        generate_alias(out, name, &path);
+        return;
+    }
+    // The contents of aliases, newtype and tuple structs are bounded by
+    // the type itself
+    let cx = cx.member(&ty.name);
+    let within = Refr {
+        within: Some(&ty.name),
+        ..cx
+    };
+    let docs = match is_user_ty(ty) {
+        true => &mut out.types,
+        false => &mut out.aliases,
+    };
+    generate_doc(docs, cx, &ty.name, "");
     match &ty.ty {
+        // Heapless types need their capacity, which the generic aliases
+        // below don't know about
+        OwnedDataModelType::String
+        | OwnedDataModelType::ByteArray
+        | OwnedDataModelType::Seq(_)
+        | OwnedDataModelType::Map { .. }
+            if cx.heapless.is_some() =>
+        {
+            let mut refr = String::new();
+            write_ty_refr(&mut refr, ty, cx);
+            generate_alias(out, name, &refr)
+        }
+
         //
         // Primitive type definitions (at the top level) are always aliases
         //
-        OwnedDataModelType::Bool => generate_alias(out, &ty.name, "bool"),
-        OwnedDataModelType::I8 => generate_alias(out, &ty.name, "i8"),
-        OwnedDataModelType::U8 => generate_alias(out, &ty.name, "u8"),
-        OwnedDataModelType::I16 => generate_alias(out, &ty.name, "i16"),
-        OwnedDataModelType::I32 => generate_alias(out, &ty.name, "i32"),
-        OwnedDataModelType::I64 => generate_alias(out, &ty.name, "i64"),
-        OwnedDataModelType::I128 => generate_alias(out, &ty.name, "i128"),
-        OwnedDataModelType::U16 => generate_alias(out, &ty.name, "u16"),
-        OwnedDataModelType::U32 => generate_alias(out, &ty.name, "u32"),
-        OwnedDataModelType::U64 => generate_alias(out, &ty.name, "u64"),
-        OwnedDataModelType::U128 => generate_alias(out, &ty.name, "u128"),
-        OwnedDataModelType::Usize => generate_alias(out, &ty.name, "usize"),
-        OwnedDataModelType::Isize => generate_alias(out, &ty.name, "isize"),
-        OwnedDataModelType::F32 => generate_alias(out, &ty.name, "f32"),
-        OwnedDataModelType::F64 => generate_alias(out, &ty.name, "f64"),
-        OwnedDataModelType::Char => generate_alias(out, &ty.name, "char"),
-        OwnedDataModelType::String => generate_alias(out, &ty.name, "String"),
-        OwnedDataModelType::ByteArray => generate_alias(out, &ty.name, "Vec<u8>"),
-        OwnedDataModelType::Unit => generate_alias(out, &ty.name, "()"),
+        OwnedDataModelType::Bool => generate_alias(out, name, "bool"),
+        OwnedDataModelType::I8 => generate_alias(out, name, "i8"),
+        OwnedDataModelType::U8 => generate_alias(out, name, "u8"),
+        OwnedDataModelType::I16 => generate_alias(out, name, "i16"),
+        OwnedDataModelType::I32 => generate_alias(out, name, "i32"),
+        OwnedDataModelType::I64 => generate_alias(out, name, "i64"),
+        OwnedDataModelType::I128 => generate_alias(out, name, "i128"),
+        OwnedDataModelType::U16 => generate_alias(out, name, "u16"),
+        OwnedDataModelType::U32 => generate_alias(out, name, "u32"),
+        OwnedDataModelType::U64 => generate_alias(out, name, "u64"),
+        OwnedDataModelType::U128 => generate_alias(out, name, "u128"),
+        OwnedDataModelType::Usize => generate_alias(out, name, "usize"),
+        OwnedDataModelType::Isize => generate_alias(out, name, "isize"),
+        OwnedDataModelType::F32 => generate_alias(out, name, "f32"),
+        OwnedDataModelType::F64 => generate_alias(out, name, "f64"),
+        OwnedDataModelType::Char => generate_alias(out, name, "char"),
+        OwnedDataModelType::String | OwnedDataModelType::ByteArray if cx.borrows.is_some() => {
+            let mut refr = String::new();
+            write_ty_refr(&mut refr, ty, cx);
+            generate_alias(out, name, &refr)
+        }
+        OwnedDataModelType::String => generate_alias(out, name, "String"),
+        OwnedDataModelType::ByteArray => generate_alias(out, name, "Vec<u8>"),
+        OwnedDataModelType::Unit => generate_alias(out, name, "()"),
 
         //
         // Non-Primitive builtins (at the top level) are always aliases
         //
         OwnedDataModelType::Option(owned_named_type) => {
-            generate_option_alias(out, &ty.name, owned_named_type)
+            generate_option_alias(out, name, owned_named_type, cx)
         }
         OwnedDataModelType::Seq(owned_named_type) => {
-            generate_seq_alias(out, &ty.name, owned_named_type)
+            generate_seq_alias(out, name, owned_named_type, cx)
         }
         OwnedDataModelType::Tuple(owned_named_types) => {
-            generate_tuple_alias(out, &ty.name, owned_named_types)
+            generate_tuple_alias(out, name, ty, owned_named_types, cx)
         }
-        OwnedDataModelType::Map { key, val } => generate_map_alias(out, &ty.name, key, val),
-        OwnedDataModelType::Schema => generate_schema_alias(out, &ty.name),
+        OwnedDataModelType::Map { key, val } => generate_map_alias(out, name, key, val, cx),
+        OwnedDataModelType::Schema => generate_schema_alias(out, name),
 
         //
         // These are new type declarations/custom user types
         //
-        OwnedDataModelType::UnitStruct => generate_unit_struct(out, &ty.name),
+        OwnedDataModelType::UnitStruct => generate_unit_struct(out, name, within, meta),
         OwnedDataModelType::NewtypeStruct(owned_named_type) => {
-            generate_newtype_struct(out, &ty.name, owned_named_type)
+            generate_newtype_struct(out, name, owned_named_type, within, meta)
         }
         OwnedDataModelType::TupleStruct(owned_named_types) => {
-            generate_tuple_struct(out, &ty.name, owned_named_types)
+            generate_tuple_struct(out, name, owned_named_types, within, meta)
         }
         OwnedDataModelType::Struct(owned_named_values) => {
-            generate_struct(out, &ty.name, owned_named_values)
+            generate_struct(out, name, owned_named_values, within, meta)
         }
         OwnedDataModelType::Enum(owned_named_variants) => {
-            generate_enum(out, &ty.name, owned_named_variants)
+            generate_enum(out, name, owned_named_variants, within, meta)
+        }
+    }
+    if cx.borrows.is_none() {
+        generate_within_bounds(out, ty, cx);
+    }
+}
+
+/// Write the `endpoints!` and `topics!` tables.
+///
+/// Each protocol gets a module with its own tables, any endpoints and topics
+/// that aren't part of a protocol are written at the top level.
+pub(crate) fn generate_rpc(out: &mut Output, p: &Pidl, cx: Refr<'_>) {
+    // Rows for every endpoint and topic, by name
+    let mut ep_rows: HashMap<&str, Vec<String>> = HashMap::new();
+    for ep in p.endpoints.iter() {
+        let req = single_token_refr(out, &ep.name, "Request", &ep.req, cx);
+        let resp = single_token_refr(out, &ep.name, "Response", &ep.resp, cx);
+        let row = vec![ep.name.clone(), req, resp, format!("{:?}", ep.path)];
+        ep_rows.insert(&ep.name, row);
+    }
+    let mut tp_rows: HashMap<&str, Vec<String>> = HashMap::new();
+    for tp in p.topics.iter() {
+        let ty = single_token_refr(out, &tp.name, "Message", &tp.ty, cx);
+        tp_rows.insert(
+            &tp.name,
+            vec![tp.name.clone(), ty, format!("{:?}", tp.path)],
+        );
+    }
+
+    let in_protocol = |name: &str| {
+        p.protocols.iter().any(|proto| {
+            proto.endpoints.iter().any(|e| e.name == name)
+                || proto.topics.iter().any(|t| t.name == name)
+        })
+    };
+    let endpoints: Vec<&Endpoint> = p
+        .endpoints
+        .iter()
+        .filter(|e| !in_protocol(&e.name))
+        .collect();
+    let topics: Vec<&Topic> = p.topics.iter().filter(|t| !in_protocol(&t.name)).collect();
+    generate_endpoints(&mut out.endpoints, &endpoints, &ep_rows);
+    generate_topics(&mut out.topics, &topics, &tp_rows);
+
+    for proto in p.protocols.iter() {
+        generate_protocol(&mut out.protocols, proto, &ep_rows, &tp_rows);
+    }
+}
+
+fn generate_protocol(
+    out: &mut String,
+    proto: &Protocol,
+    ep_rows: &HashMap<&str, Vec<String>>,
+    tp_rows: &HashMap<&str, Vec<String>>,
+) {
+    let mut body = String::new();
+    let endpoints: Vec<&Endpoint> = proto.endpoints.iter().collect();
+    let topics: Vec<&Topic> = proto.topics.iter().collect();
+    generate_endpoints(&mut body, &endpoints, ep_rows);
+    generate_topics(&mut body, &topics, tp_rows);
+
+    if !out.is_empty() {
+        writeln!(out).unwrap();
+    }
+    writeln!(out, "/// The `{}` protocol", proto.name).unwrap();
+    writeln!(out, "pub mod {} {{", snake_case(&proto.name)).unwrap();
+    writeln!(out, "    use super::*;").unwrap();
+    writeln!(out).unwrap();
+    for line in body.lines() {
+        if line.is_empty() {
+            writeln!(out).unwrap();
+        } else {
+            writeln!(out, "    {line}").unwrap();
+        }
+    }
+    writeln!(out, "}}").unwrap();
+}
+
+/// Write a `[u8; 8]` constant for each key of each endpoint and topic
+pub(crate) fn generate_keys(out: &mut Output, p: &Pidl) {
+    let eps = p.endpoints.iter().flat_map(|ep| {
+        [
+            (&ep.name, &ep.path, "REQ_KEY", "request", ep.req_key()),
+            (&ep.name, &ep.path, "RESP_KEY", "response", ep.resp_key()),
+        ]
+    });
+    let tps = p
+        .topics
+        .iter()
+        .map(|tp| (&tp.name, &tp.path, "KEY", "message", tp.key()));
+    for (name, path, suffix, what, key) in eps.chain(tps) {
+        let konst = key_const(name, suffix);
+        let bytes = key
+            .to_bytes()
+            .iter()
+            .map(|b| format!("0x{b:02X}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(&mut out.keys, "/// Key of the `{name}` {what}, at {path:?}").unwrap();
+        writeln!(&mut out.keys, "pub const {konst}: [u8; 8] = [{bytes}];").unwrap();
+    }
+}
+
+/// The name of the constant for a key of an endpoint or topic, e.g.
+/// `GET_STATUS_REQ_KEY` for the `REQ_KEY` of `GetStatus`
+pub(crate) fn key_const(name: &str, suffix: &str) -> String {
+    format!("{}_{suffix}", snake_case(name).to_uppercase())
+}
+
+/// Convert a `CamelCase` name to `snake_case`, e.g. `FirmwareV2` to
+/// `firmware_v2`
+pub(crate) fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if prev_lower {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+    }
+    out
+}
+
+/// Write the `endpoints!` table for the given endpoints
+fn generate_endpoints(
+    out: &mut String,
+    endpoints: &[&Endpoint],
+    rows: &HashMap<&str, Vec<String>>,
+) {
+    if endpoints.is_empty() {
+        return;
+    }
+    let rows = endpoints
+        .iter()
+        .map(|e| rows[e.name.as_str()].clone())
+        .collect();
+
+    writeln!(out, "endpoints! {{").unwrap();
+    writeln!(out, "    list = ENDPOINT_LIST;").unwrap();
+    write_rpc_table(
+        out,
+        &["EndpointTy", "RequestTy", "ResponseTy", "Path"],
+        rows,
+    );
+    writeln!(out, "}}").unwrap();
+}
+
+/// Write a `topics!` table for each direction that has topics
+fn generate_topics(out: &mut String, topics: &[&Topic], rows: &HashMap<&str, Vec<String>>) {
+    let directions = [
+        (TopicDirection::ToServer, "TOPICS_IN_LIST", "ToServer"),
+        (TopicDirection::ToClient, "TOPICS_OUT_LIST", "ToClient"),
+    ];
+    for (direction, list, variant) in directions {
+        let rows: Vec<Vec<String>> = topics
+            .iter()
+            .filter(|t| t.direction == direction)
+            .map(|t| rows[t.name.as_str()].clone())
+            .collect();
+        if rows.is_empty() {
+            continue;
+        }
+        if !out.is_empty() {
+            writeln!(out).unwrap();
+        }
+        writeln!(out, "topics! {{").unwrap();
+        writeln!(out, "    list = {list};").unwrap();
+        writeln!(
+            out,
+            "    direction = postcard_rpc::TopicDirection::{variant};"
+        )
+        .unwrap();
+        write_rpc_table(out, &["TopicTy", "MessageTy", "Path"], rows);
+        writeln!(out, "}}").unwrap();
+    }
+}
+
+/// Write a reference to a type for use in an `endpoints!` or `topics!` table.
+///
+/// The macros only accept a single token for each type, so other types get
+/// an alias, named after the endpoint or topic, e.g. `GetStatusRequest`.
+/// Types are referred to from the root module.
+fn single_token_refr(
+    out: &mut Output,
+    name: &str,
+    suffix: &str,
+    ont: &OwnedNamedType,
+    cx: Refr<'_>,
+) -> String {
+    let mut ty = String::new();
+    write_ty_refr(&mut ty, ont, cx);
+    let is_ident = ty.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_ident || ty == "()" {
+        ty
+    } else {
+        let alias = format!("{name}{suffix}");
+        writeln!(&mut out.aliases, "pub type {alias} = {ty};").unwrap();
+        alias
+    }
+}
+
+/// Write the rows of an `endpoints!` or `topics!` table, aligning the columns
+fn write_rpc_table(out: &mut String, header: &[&str], mut rows: Vec<Vec<String>>) {
+    rows.insert(0, header.iter().map(|h| h.to_string()).collect());
+    let mut widths = vec![0; header.len()];
+    for row in rows.iter() {
+        for (w, cell) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(cell.len());
         }
     }
+    rows.insert(1, widths.iter().map(|w| "-".repeat(*w)).collect());
+
+    for row in rows {
+        write!(out, "    |").unwrap();
+        for (w, cell) in widths.iter().zip(row.iter()) {
+            write!(out, " {cell:<w$} |").unwrap();
+        }
+        writeln!(out).unwrap();
+    }
 }
 
 fn generate_alias(out: &mut Output, name: &str, ty: &str) {
     writeln!(&mut out.aliases, "pub type {} = {};", name, ty).unwrap();
 }
 
-fn generate_option_alias(out: &mut Output, name: &str, ont: &OwnedNamedType) {
+fn generate_option_alias(out: &mut Output, name: &str, ont: &OwnedNamedType, cx: Refr<'_>) {
     write!(&mut out.aliases, "pub type {} = Option<", name).unwrap();
-    write_ty_refr(&mut out.aliases, ont);
+    write_ty_refr(&mut out.aliases, ont, cx);
     writeln!(&mut out.aliases, ">;").unwrap();
 }
 
-fn generate_tuple_alias(out: &mut Output, name: &str, owned_named_types: &[OwnedNamedType]) {
+fn generate_tuple_alias(
+    out: &mut Output,
+    name: &str,
+    ont: &OwnedNamedType,
+    owned_named_types: &[OwnedNamedType],
+    cx: Refr<'_>,
+) {
     write!(&mut out.aliases, "pub type {} = ", name).unwrap();
     write!(
         &mut out.aliases,
         "{}",
-        tuple_or_array_refr(owned_named_types)
+        tuple_or_array_refr(ont, owned_named_types, cx)
     )
     .unwrap();
     writeln!(&mut out.aliases, ";").unwrap();
 }
 
-fn generate_seq_alias(out: &mut Output, name: &str, owned_named_type: &OwnedNamedType) {
+fn generate_seq_alias(
+    out: &mut Output,
+    name: &str,
+    owned_named_type: &OwnedNamedType,
+    cx: Refr<'_>,
+) {
     write!(&mut out.aliases, "pub type {} = Vec<", name).unwrap();
-    write_ty_refr(&mut out.aliases, owned_named_type);
+    write_ty_refr(&mut out.aliases, owned_named_type, cx.on_heap());
     writeln!(&mut out.aliases, ">;").unwrap();
 }
 
-fn generate_map_alias(out: &mut Output, name: &str, key: &OwnedNamedType, val: &OwnedNamedType) {
+fn generate_map_alias(
+    out: &mut Output,
+    name: &str,
+    key: &OwnedNamedType,
+    val: &OwnedNamedType,
+    cx: Refr<'_>,
+) {
     // todo: always hashmap?
     write!(&mut out.aliases, "pub type {} = HashMap<", name).unwrap();
-    write_ty_refr(&mut out.aliases, key);
+    write_ty_refr(&mut out.aliases, key, cx.on_heap());
     write!(&mut out.aliases, ", ").unwrap();
-    write_ty_refr(&mut out.aliases, val);
+    write_ty_refr(&mut out.aliases, val, cx.on_heap());
     writeln!(&mut out.aliases, ">;").unwrap();
 }
 
@@ -121,25 +1192,76 @@ fn generate_schema_alias(out: &mut Output, name: &str) {
     writeln!(&mut out.aliases, "pub type {} = OwnedNamedType;", name).unwrap();
 }
 
-fn generate_enum(out: &mut Output, name: &str, owned_named_variants: &[OwnedNamedVariant]) {
-    writeln!(&mut out.types, "#[derive(Serialize, Deserialize, Schema)]").unwrap();
+/// Write the documentation of the member at `path` as `///` comments, if it
+/// has any
+fn generate_doc(out: &mut String, cx: Refr<'_>, path: &str, indent: &str) {
+    let Some(doc) = cx.docs.get(path) else {
+        return;
+    };
+    for line in doc.lines() {
+        match line {
+            "" => writeln!(out, "{indent}///").unwrap(),
+            line => writeln!(out, "{indent}/// {line}").unwrap(),
+        }
+    }
+}
+
+/// Write the derives of a type, followed by any extra attributes
+fn generate_derives(out: &mut Output, cx: Refr<'_>, meta: &TypeMeta) {
+    let mut derives = vec!["Serialize", "Deserialize"];
+    match (cx.within, cx.heapless) {
+        // Recursive types have no finite schema
+        (Some(name), _) if cx.recursion.is_recursive(name) => {}
+        (Some(name), Some(heapless)) if !heapless.has_schema(name) => {}
+        _ => derives.push("Schema"),
+    }
+    for derive in meta.derives.iter() {
+        if !derives.contains(&derive.as_str()) {
+            derives.push(derive);
+        }
+    }
+    writeln!(&mut out.types, "#[derive({})]", derives.join(", ")).unwrap();
+    for attr in meta.attrs.iter() {
+        writeln!(&mut out.types, "{attr}").unwrap();
+    }
+}
+
+fn generate_enum(
+    out: &mut Output,
+    name: &str,
+    owned_named_variants: &[OwnedNamedVariant],
+    cx: Refr<'_>,
+    meta: &TypeMeta,
+) {
+    generate_derives(out, cx, meta);
     writeln!(&mut out.types, "pub enum {name} {{").unwrap();
+    let owner = cx.within.expect("user types are written within themselves");
+    let default = match meta.derives.iter().any(|d| d == "Default") {
+        true => default_variant(owned_named_variants),
+        false => None,
+    };
     for v in owned_named_variants {
+        let path = format!("{owner}.{}", v.name);
+        generate_doc(&mut out.types, cx, &path, "    ");
+        if default == Some(v.name.as_str()) {
+            writeln!(&mut out.types, "    #[default]").unwrap();
+        }
         write!(&mut out.types, "    {}", v.name).unwrap();
+        let cx = cx.member(&path);
         match &v.ty {
             OwnedDataModelVariant::UnitVariant => {
                 writeln!(&mut out.types, ",").unwrap();
             }
             OwnedDataModelVariant::NewtypeVariant(owned_named_type) => {
-                write!(&mut out.types, "(").unwrap();
-                write_ty_refr(&mut out.types, owned_named_type);
+                write!(&mut out.types, "({}", cx.serde_borrow(owned_named_type)).unwrap();
+                write_ty_refr(&mut out.types, owned_named_type, cx);
                 writeln!(&mut out.types, "),").unwrap();
             }
             OwnedDataModelVariant::TupleVariant(owned_named_types) => {
                 let mut items = vec![];
                 for v in owned_named_types {
-                    let mut s = String::new();
-                    write_ty_refr(&mut s, v);
+                    let mut s = String::from(cx.serde_borrow(v));
+                    write_ty_refr(&mut s, v, cx);
                     items.push(s);
                 }
                 let all = items.join(", ");
@@ -147,9 +1269,14 @@ fn generate_enum(out: &mut Output, name: &str, owned_named_variants: &[OwnedName
             }
             OwnedDataModelVariant::StructVariant(owned_named_values) => {
                 writeln!(&mut out.types, " {{").unwrap();
-                for v in owned_named_values {
-                    write!(&mut out.types, "        {}: ", v.name).unwrap();
-                    write_ty_refr(&mut out.types, &v.ty);
+                for f in owned_named_values {
+                    let path = format!("{path}.{}", f.name);
+                    generate_doc(&mut out.types, cx, &path, "        ");
+                    if !cx.serde_borrow(&f.ty).is_empty() {
+                        writeln!(&mut out.types, "        #[serde(borrow)]").unwrap();
+                    }
+                    write!(&mut out.types, "        {}: ", f.name).unwrap();
+                    write_ty_refr(&mut out.types, &f.ty, cx.member(&path));
                     writeln!(&mut out.types, ",").unwrap();
                 }
                 writeln!(&mut out.types, "    }},").unwrap();
@@ -160,39 +1287,404 @@ fn generate_enum(out: &mut Output, name: &str, owned_named_variants: &[OwnedName
     writeln!(&mut out.types).unwrap();
 }
 
-fn generate_unit_struct(out: &mut Output, name: &str) {
-    writeln!(&mut out.types, "#[derive(Serialize, Deserialize, Schema)]").unwrap();
+fn generate_unit_struct(out: &mut Output, name: &str, cx: Refr<'_>, meta: &TypeMeta) {
+    generate_derives(out, cx, meta);
     writeln!(&mut out.types, "pub struct {name};").unwrap();
     writeln!(&mut out.types).unwrap();
 }
 
-fn generate_newtype_struct(out: &mut Output, name: &str, _ont: &OwnedNamedType) {
-    writeln!(&mut out.types, "#[derive(Serialize, Deserialize, Schema)]").unwrap();
-    // TODO: Load-bearing name! Name includes the newtype parts
-    writeln!(&mut out.types, "pub struct {name};").unwrap();
+fn generate_newtype_struct(
+    out: &mut Output,
+    name: &str,
+    ont: &OwnedNamedType,
+    cx: Refr<'_>,
+    meta: &TypeMeta,
+) {
+    generate_derives(out, cx, meta);
+    write!(
+        &mut out.types,
+        "pub struct {name}({}pub ",
+        cx.serde_borrow(ont)
+    )
+    .unwrap();
+    write_ty_refr(&mut out.types, ont, cx);
+    writeln!(&mut out.types, ");").unwrap();
     writeln!(&mut out.types).unwrap();
 }
 
-fn generate_tuple_struct(out: &mut Output, name: &str, _onvs: &[OwnedNamedType]) {
-    writeln!(&mut out.types, "#[derive(Serialize, Deserialize, Schema)]").unwrap();
-    // TODO: Load-bearing name! Name includes the newtype parts
-    writeln!(&mut out.types, "pub struct {name};").unwrap();
+fn generate_tuple_struct(
+    out: &mut Output,
+    name: &str,
+    onts: &[OwnedNamedType],
+    cx: Refr<'_>,
+    meta: &TypeMeta,
+) {
+    generate_derives(out, cx, meta);
+    let mut items = vec![];
+    for t in onts {
+        let mut s = format!("{}pub ", cx.serde_borrow(t));
+        write_ty_refr(&mut s, t, cx);
+        items.push(s);
+    }
+    let all = items.join(", ");
+    writeln!(&mut out.types, "pub struct {name}({all});").unwrap();
     writeln!(&mut out.types).unwrap();
 }
 
-fn generate_struct(out: &mut Output, name: &str, fields: &[OwnedNamedValue]) {
-    writeln!(&mut out.types, "#[derive(Serialize, Deserialize, Schema)]").unwrap();
+fn generate_struct(
+    out: &mut Output,
+    name: &str,
+    fields: &[OwnedNamedValue],
+    cx: Refr<'_>,
+    meta: &TypeMeta,
+) {
+    generate_derives(out, cx, meta);
     writeln!(&mut out.types, "pub struct {name} {{").unwrap();
+    let owner = cx.within.expect("user types are written within themselves");
     for f in fields {
+        let path = format!("{owner}.{}", f.name);
+        generate_doc(&mut out.types, cx, &path, "    ");
+        if !cx.serde_borrow(&f.ty).is_empty() {
+            writeln!(&mut out.types, "    #[serde(borrow)]").unwrap();
+        }
         write!(&mut out.types, "    pub {}: ", f.name).unwrap();
-        write_ty_refr(&mut out.types, &f.ty);
+        write_ty_refr(&mut out.types, &f.ty, cx.member(&path));
         writeln!(&mut out.types, ",").unwrap();
     }
     writeln!(&mut out.types, "}}").unwrap();
     writeln!(&mut out.types).unwrap();
 }
 
-fn write_ty_refr(out: &mut String, ont: &OwnedNamedType) {
+/// Write the borrowed version of a type, along with conversions to and from
+/// the owned version
+fn generate_borrowed_ty(out: &mut Output, ty: &OwnedNamedType, cx: Refr<'_>, meta: &TypeMeta) {
+    let borrows = cx.borrows.expect("only called for borrowed types");
+    if !borrows.names.contains(&ty.name.as_str()) {
+        // Types without borrowed data are the same as the owned ones
+        let path = relative_path(cx.module, &ty.name);
+        writeln!(&mut out.aliases, "pub use {path};").unwrap();
+        return;
+    }
+    generate_std_ty(out, ty, cx, meta);
+    if cx.aliases.contains_key(&ty.name) {
+        return;
+    }
+
+    let cx = Refr {
+        within: Some(&ty.name),
+        ..cx
+    };
+    let (to_borrowed, to_owned) = match &ty.ty {
+        OwnedDataModelType::NewtypeStruct(ont) => (
+            format!("Self({})", borrow_expr(ont, "value.0", false, cx)),
+            format!("Self({})", own_expr(ont, "value.0", cx)),
+        ),
+        OwnedDataModelType::TupleStruct(onts) => {
+            let fields = |conv: &dyn Fn(&OwnedNamedType, &str) -> String| {
+                let fields: Vec<String> = onts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ont)| conv(ont, &format!("value.{i}")))
+                    .collect();
+                format!("Self({})", fields.join(", "))
+            };
+            (
+                fields(&|ont, e| borrow_expr(ont, e, false, cx)),
+                fields(&|ont, e| own_expr(ont, e, cx)),
+            )
+        }
+        OwnedDataModelType::Struct(onvs) => {
+            let fields = |conv: &dyn Fn(&OwnedNamedType, &str) -> String| {
+                let mut body = String::from("Self {\n");
+                for v in onvs {
+                    let value = conv(&v.ty, &format!("value.{}", v.name));
+                    writeln!(&mut body, "    {}: {value},", v.name).unwrap();
+                }
+                body.push('}');
+                body
+            };
+            (
+                fields(&|ont, e| borrow_expr(ont, e, false, cx)),
+                fields(&|ont, e| own_expr(ont, e, cx)),
+            )
+        }
+        OwnedDataModelType::Enum(variants) => {
+            let (_, name) = split_path(&ty.name);
+            let owned = relative_path(cx.module, &ty.name);
+            (
+                convert_variants(variants, &owned, "Self", &|ont, e| {
+                    borrow_expr(ont, e, true, cx)
+                }),
+                convert_variants(variants, name, "Self", &|ont, e| own_expr(ont, e, cx)),
+            )
+        }
+        // Aliases can't have conversions, and unit structs don't borrow
+        _ => return,
+    };
+
+    let (_, name) = split_path(&ty.name);
+    let owned = relative_path(cx.module, &ty.name);
+    let indent = |body: &str, by: &str| body.lines().collect::<Vec<_>>().join(&format!("\n{by}"));
+    let out = &mut out.types;
+    writeln!(out, "impl<'a> From<&'a {owned}> for {name}<'a> {{").unwrap();
+    writeln!(out, "    fn from(value: &'a {owned}) -> Self {{").unwrap();
+    writeln!(out, "        {}", indent(&to_borrowed, "        ")).unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "impl From<{name}<'_>> for {owned} {{").unwrap();
+    writeln!(out, "    fn from(value: {name}<'_>) -> Self {{").unwrap();
+    writeln!(out, "        {}", indent(&to_owned, "        ")).unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "impl {name}<'_> {{").unwrap();
+    writeln!(out, "    /// Copy this into the owned version of the type").unwrap();
+    writeln!(out, "    pub fn to_owned(&self) -> {owned} {{").unwrap();
+    writeln!(out, "        self.clone().into()").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+}
+
+/// A `match` converting each variant of `from` to the same variant of `to`,
+/// with `conv` converting the fields
+fn convert_variants(
+    variants: &[OwnedNamedVariant],
+    from: &str,
+    to: &str,
+    conv: &dyn Fn(&OwnedNamedType, &str) -> String,
+) -> String {
+    let mut out = String::from("match value {\n");
+    for v in variants {
+        let name = &v.name;
+        match &v.ty {
+            OwnedDataModelVariant::UnitVariant => {
+                writeln!(&mut out, "    {from}::{name} => {to}::{name},").unwrap();
+            }
+            OwnedDataModelVariant::NewtypeVariant(ont) => {
+                let value = conv(ont, "v0");
+                writeln!(&mut out, "    {from}::{name}(v0) => {to}::{name}({value}),").unwrap();
+            }
+            OwnedDataModelVariant::TupleVariant(onts) => {
+                let names: Vec<String> = (0..onts.len()).map(|i| format!("v{i}")).collect();
+                let values: Vec<String> =
+                    onts.iter().zip(&names).map(|(t, n)| conv(t, n)).collect();
+                writeln!(
+                    &mut out,
+                    "    {from}::{name}({}) => {to}::{name}({}),",
+                    names.join(", "),
+                    values.join(", ")
+                )
+                .unwrap();
+            }
+            OwnedDataModelVariant::StructVariant(onvs) => {
+                let names: Vec<&str> = onvs.iter().map(|v| v.name.as_str()).collect();
+                writeln!(
+                    &mut out,
+                    "    {from}::{name} {{ {} }} => {to}::{name} {{",
+                    names.join(", ")
+                )
+                .unwrap();
+                for v in onvs {
+                    match conv(&v.ty, &v.name) {
+                        value if value == v.name => writeln!(&mut out, "        {value},"),
+                        value => writeln!(&mut out, "        {}: {value},", v.name),
+                    }
+                    .unwrap();
+                }
+                writeln!(&mut out, "    }},").unwrap();
+            }
+        }
+    }
+    out.push('}');
+    out
+}
+
+/// Can values of this type be copied, rather than cloned?
+fn is_copy(ont: &OwnedNamedType) -> bool {
+    match &ont.ty {
+        OwnedDataModelType::Option(ont) => is_copy(ont),
+        OwnedDataModelType::Tuple(onts) => onts.iter().all(is_copy),
+        OwnedDataModelType::Bool
+        | OwnedDataModelType::I8
+        | OwnedDataModelType::U8
+        | OwnedDataModelType::I16
+        | OwnedDataModelType::I32
+        | OwnedDataModelType::I64
+        | OwnedDataModelType::I128
+        | OwnedDataModelType::U16
+        | OwnedDataModelType::U32
+        | OwnedDataModelType::U64
+        | OwnedDataModelType::U128
+        | OwnedDataModelType::Usize
+        | OwnedDataModelType::Isize
+        | OwnedDataModelType::F32
+        | OwnedDataModelType::F64
+        | OwnedDataModelType::Char
+        | OwnedDataModelType::Unit => true,
+        _ => false,
+    }
+}
+
+/// Is this type written as `&'a str` or `&'a [u8]` in borrowed types?
+fn is_borrowed_slice(ont: &OwnedNamedType) -> bool {
+    match &ont.ty {
+        OwnedDataModelType::String | OwnedDataModelType::ByteArray => true,
+        OwnedDataModelType::Seq(ont) => ont.ty == OwnedDataModelType::U8,
+        _ => false,
+    }
+}
+
+/// Is this a user type that is written with a `Box` here?
+fn is_boxed(ont: &OwnedNamedType, cx: Refr<'_>) -> bool {
+    cx.within
+        .is_some_and(|within| cx.recursion.needs_box(within, &ont.name))
+}
+
+/// An expression borrowing the owned value `e`, which is a reference if
+/// `is_ref` is set, as the borrowed version of its type
+fn borrow_expr(ont: &OwnedNamedType, e: &str, is_ref: bool, cx: Refr<'_>) -> String {
+    let borrows = cx.borrows.expect("only called for borrowed types");
+    if !borrows.ty_borrows(ont) {
+        return match (is_copy(ont), is_ref) {
+            (true, true) => format!("*{e}"),
+            (true, false) => e.to_string(),
+            (false, _) => format!("{e}.clone()"),
+        };
+    }
+    match &ont.ty {
+        OwnedDataModelType::String => format!("{e}.as_str()"),
+        OwnedDataModelType::ByteArray | OwnedDataModelType::Seq(_) if is_borrowed_slice(ont) => {
+            format!("{e}.as_slice()")
+        }
+        OwnedDataModelType::Option(inner) if is_borrowed_slice(inner) => format!("{e}.as_deref()"),
+        OwnedDataModelType::Option(inner) => format!("{e}.as_ref().map({})", borrow_fn(inner, cx)),
+        OwnedDataModelType::Seq(inner) => {
+            let f = borrow_fn(inner, cx.on_heap());
+            format!("{e}.iter().map({f}).collect()")
+        }
+        OwnedDataModelType::Map { key, val } => {
+            let cx = cx.on_heap();
+            let (k, v) = (
+                borrow_expr(key, "k", true, cx),
+                borrow_expr(val, "v", true, cx),
+            );
+            format!("{e}.iter().map(|(k, v)| ({k}, {v})).collect()")
+        }
+        OwnedDataModelType::Tuple(onts) if is_array(ont) => {
+            format!("{e}.each_ref().map({})", borrow_fn(&onts[0], cx))
+        }
+        OwnedDataModelType::Tuple(onts) => {
+            tuple_expr(onts, |i, t| borrow_expr(t, &format!("{e}.{i}"), false, cx))
+        }
+        _ => {
+            let path = relative_path(cx.module, &format!("{BORROWED}::{}", ont.name));
+            match (is_boxed(ont, cx), is_ref) {
+                (true, true) => format!("Box::new({path}::from(&**{e}))"),
+                (true, false) => format!("Box::new({path}::from(&*{e}))"),
+                (false, true) => format!("{path}::from({e})"),
+                (false, false) => format!("{path}::from(&{e})"),
+            }
+        }
+    }
+}
+
+/// A function borrowing references to owned values, for use with `map`
+fn borrow_fn(ont: &OwnedNamedType, cx: Refr<'_>) -> String {
+    match &ont.ty {
+        OwnedDataModelType::String => "String::as_str".to_string(),
+        _ if is_borrowed_slice(ont) => "Vec::as_slice".to_string(),
+        _ if is_user_ty(ont) && !is_boxed(ont, cx) => {
+            let path = relative_path(cx.module, &format!("{BORROWED}::{}", ont.name));
+            format!("{path}::from")
+        }
+        _ => format!("|v| {}", borrow_expr(ont, "v", true, cx)),
+    }
+}
+
+/// An expression converting the borrowed value `e` into the owned version
+/// of its type
+fn own_expr(ont: &OwnedNamedType, e: &str, cx: Refr<'_>) -> String {
+    let borrows = cx.borrows.expect("only called for borrowed types");
+    if !borrows.ty_borrows(ont) {
+        return e.to_string();
+    }
+    match &ont.ty {
+        OwnedDataModelType::String => format!("String::from({e})"),
+        _ if is_borrowed_slice(ont) => format!("{e}.to_vec()"),
+        OwnedDataModelType::Option(inner) => format!("{e}.map({})", own_fn(inner, cx)),
+        OwnedDataModelType::Seq(inner) => {
+            let f = own_fn(inner, cx.on_heap());
+            format!("{e}.into_iter().map({f}).collect()")
+        }
+        OwnedDataModelType::Map { key, val } => {
+            let cx = cx.on_heap();
+            let (k, v) = (own_expr(key, "k", cx), own_expr(val, "v", cx));
+            format!("{e}.into_iter().map(|(k, v)| ({k}, {v})).collect()")
+        }
+        OwnedDataModelType::Tuple(onts) if is_array(ont) => {
+            format!("{e}.map({})", own_fn(&onts[0], cx))
+        }
+        OwnedDataModelType::Tuple(onts) => {
+            tuple_expr(onts, |i, t| own_expr(t, &format!("{e}.{i}"), cx))
+        }
+        _ => {
+            let path = relative_path(cx.module, &ont.name);
+            match is_boxed(ont, cx) {
+                true => format!("Box::new({path}::from(*{e}))"),
+                false => format!("{path}::from({e})"),
+            }
+        }
+    }
+}
+
+/// A function converting borrowed values to owned ones, for use with `map`
+fn own_fn(ont: &OwnedNamedType, cx: Refr<'_>) -> String {
+    match &ont.ty {
+        OwnedDataModelType::String => "String::from".to_string(),
+        _ if is_borrowed_slice(ont) => "<[u8]>::to_vec".to_string(),
+        _ if is_user_ty(ont) && !is_boxed(ont, cx) => {
+            format!("{}::from", relative_path(cx.module, &ont.name))
+        }
+        _ => format!("|v| {}", own_expr(ont, "v", cx)),
+    }
+}
+
+/// A tuple expression, with `item` writing each item
+fn tuple_expr(onts: &[OwnedNamedType], item: impl Fn(usize, &OwnedNamedType) -> String) -> String {
+    let items: Vec<String> = onts.iter().enumerate().map(|(i, t)| item(i, t)).collect();
+    match items.len() {
+        1 => format!("({},)", items[0]),
+        _ => format!("({})", items.join(", ")),
+    }
+}
+
+/// Write a user type by name, boxing it if it is recursive
+fn write_user_ty_refr(out: &mut String, name: &str, cx: Refr<'_>) -> core::fmt::Result {
+    let path = match cx.borrows {
+        Some(borrows) => {
+            let path = relative_path(cx.module, &format!("{BORROWED}::{name}"));
+            match borrows.names.contains(&name) {
+                true => format!("{path}<'a>"),
+                false => path,
+            }
+        }
+        None => relative_path(cx.module, name),
+    };
+    match cx.within {
+        Some(within) if cx.recursion.needs_box(within, name) => {
+            write!(out, "Box<{path}>")
+        }
+        _ => write!(out, "{path}"),
+    }
+}
+
+fn write_ty_refr(out: &mut String, ont: &OwnedNamedType, cx: Refr<'_>) {
+    let cx = Refr {
+        bounds: cx.bounds.enter(ont),
+        ..cx
+    };
     match &ont.ty {
         OwnedDataModelType::Bool => write!(out, "bool"),
         OwnedDataModelType::I8 => write!(out, "i8"),
@@ -210,52 +1702,86 @@ fn write_ty_refr(out: &mut String, ont: &OwnedNamedType) {
         OwnedDataModelType::F32 => write!(out, "f32"),
         OwnedDataModelType::F64 => write!(out, "f64"),
         OwnedDataModelType::Char => write!(out, "char"),
+        OwnedDataModelType::String if cx.borrows.is_some() => write!(out, "&'a str"),
+        OwnedDataModelType::String if cx.heapless.is_some() => {
+            write!(out, "heapless::String<{}>", cx.capacity())
+        }
         OwnedDataModelType::String => write!(out, "String"),
+        OwnedDataModelType::ByteArray if cx.borrows.is_some() => write!(out, "&'a [u8]"),
+        OwnedDataModelType::ByteArray if cx.heapless.is_some() => {
+            write!(out, "heapless::Vec<u8, {}>", cx.capacity())
+        }
         OwnedDataModelType::ByteArray => write!(out, "Vec<u8>"),
         OwnedDataModelType::Unit => write!(out, "()"),
+        // Sequences of bytes are laid out the same as byte arrays
+        OwnedDataModelType::Seq(ont)
+            if cx.borrows.is_some() && ont.ty == OwnedDataModelType::U8 =>
+        {
+            write!(out, "&'a [u8]")
+        }
         OwnedDataModelType::Option(owned_named_type) => {
             write!(out, "Option<").unwrap();
-            write_ty_refr(out, owned_named_type);
+            write_ty_refr(out, owned_named_type, cx);
             write!(out, ">")
-        },
-        OwnedDataModelType::UnitStruct => write!(out, "{}", ont.name),
+        }
+        OwnedDataModelType::UnitStruct => write_user_ty_refr(out, &ont.name, cx),
         OwnedDataModelType::NewtypeStruct(_owned_named_type) => {
-            todo!("Known issue: since newtype names have the contained type in the name, we can't refer to them")
-        },
+            write_user_ty_refr(out, &ont.name, cx)
+        }
+        OwnedDataModelType::Seq(owned_named_type) if cx.heapless.is_some() => {
+            write!(out, "heapless::Vec<").unwrap();
+            write_ty_refr(out, owned_named_type, cx.on_heap());
+            write!(out, ", {}>", cx.capacity())
+        }
         OwnedDataModelType::Seq(owned_named_type) => {
             write!(out, "Vec<").unwrap();
-            write_ty_refr(out, owned_named_type);
+            write_ty_refr(out, owned_named_type, cx.on_heap());
             write!(out, ">")
-        },
+        }
         OwnedDataModelType::Tuple(owned_named_types) => {
-            write!(out, "{}", tuple_or_array_refr(owned_named_types))
-        },
+            write!(out, "{}", tuple_or_array_refr(ont, owned_named_types, cx))
+        }
         OwnedDataModelType::TupleStruct(_owned_named_types) => {
-            todo!("Known issue: since tuplestruct names have the contained type in the name, we can't refer to them")
-        },
+            write_user_ty_refr(out, &ont.name, cx)
+        }
+        OwnedDataModelType::Map { key, val } if cx.heapless.is_some() => {
+            // Index maps need a power of two capacity
+            let capacity = cx.capacity().max(2).next_power_of_two();
+            write!(out, "heapless::FnvIndexMap<").unwrap();
+            write_ty_refr(out, key, cx.on_heap());
+            write!(out, ", ").unwrap();
+            write_ty_refr(out, val, cx.on_heap());
+            write!(out, ", {capacity}>")
+        }
         OwnedDataModelType::Map { key, val } => {
             // todo: do we always want HashMap and not whatever other Map?
             write!(out, "HashMap<").unwrap();
-            write_ty_refr(out, key);
+            write_ty_refr(out, key, cx.on_heap());
             write!(out, ", ").unwrap();
-            write_ty_refr(out, val);
+            write_ty_refr(out, val, cx.on_heap());
             write!(out, ">")
-        },
-        OwnedDataModelType::Struct(_owned_named_values) => write!(out, "{}", ont.name),
-        OwnedDataModelType::Enum(_owned_named_variants) => write!(out, "{}", ont.name),
-        OwnedDataModelType::Schema => todo!(),
-    }.unwrap();
+        }
+        OwnedDataModelType::Struct(_owned_named_values) => write_user_ty_refr(out, &ont.name, cx),
+        OwnedDataModelType::Enum(_owned_named_variants) => write_user_ty_refr(out, &ont.name, cx),
+        // Only `rust_std` can write these, see `rust_heapless::Error::Schema`
+        OwnedDataModelType::Schema => write!(out, "OwnedNamedType"),
+    }
+    .unwrap();
 }
 
-fn tuple_or_array_refr(owned_named_types: &[OwnedNamedType]) -> String {
+/// The tuple `ont`, with these items
+fn tuple_or_array_refr(
+    ont: &OwnedNamedType,
+    owned_named_types: &[OwnedNamedType],
+    cx: Refr<'_>,
+) -> String {
     let mut out = String::new();
     if let Some(font) = owned_named_types.first() {
         let multiple = owned_named_types.len() > 1;
-        let all_same = owned_named_types.iter().all(|ont| ont.ty == font.ty);
-        if multiple && all_same {
+        if is_array(ont) {
             // This is an array, not a tuple!
             write!(&mut out, "[").unwrap();
-            write_ty_refr(&mut out, font);
+            write_ty_refr(&mut out, font, cx);
             write!(&mut out, "; {}]", owned_named_types.len()).unwrap();
         } else {
             // This is really a tuple
@@ -263,14 +1789,19 @@ fn tuple_or_array_refr(owned_named_types: &[OwnedNamedType]) -> String {
             let mut items = vec![];
             for t in owned_named_types {
                 let mut s = String::new();
-                write_ty_refr(&mut s, t);
+                write_ty_refr(&mut s, t, cx);
                 items.push(s);
             }
             let all = items.join(", ");
-            write!(&mut out, "{all})").unwrap();
+            if multiple {
+                write!(&mut out, "{all})").unwrap();
+            } else {
+                write!(&mut out, "{all},)").unwrap();
+            }
         }
     } else {
-        todo!("Tuple with no items?");
+        // The IDL turns empty tuples into `unit`, but they're the same thing
+        write!(&mut out, "()").unwrap();
     }
     out
 }
@@ -0,0 +1,49 @@
+//! A report of the largest encoded size of every type, endpoint and topic
+//!
+//! This is meant for sizing receive buffers, see [`crate::pidl::max_size`].
+
+use super::report_table;
+use crate::{
+    pidl::{max_size, TopicDirection},
+    Pidl,
+};
+
+/// Generate a human readable table of all sizes in bytes, one per line
+pub fn generate_size_report(p: &Pidl) -> String {
+    let mut rows: Vec<[String; 4]> = vec![];
+    for ty in p.types.iter() {
+        rows.push([
+            "type".into(),
+            ty.name.clone(),
+            String::new(),
+            max_size(p, ty).to_string(),
+        ]);
+    }
+    for ep in p.endpoints.iter() {
+        rows.push([
+            "endpoint".into(),
+            ep.name.clone(),
+            "req".into(),
+            max_size(p, &ep.req).to_string(),
+        ]);
+        rows.push([
+            String::new(),
+            String::new(),
+            "resp".into(),
+            max_size(p, &ep.resp).to_string(),
+        ]);
+    }
+    for tp in p.topics.iter() {
+        let direction = match tp.direction {
+            TopicDirection::ToServer => "in",
+            TopicDirection::ToClient => "out",
+        };
+        rows.push([
+            "topic".into(),
+            tp.name.clone(),
+            direction.into(),
+            max_size(p, &tp.ty).to_string(),
+        ]);
+    }
+    report_table(rows)
+}
@@ -0,0 +1,91 @@
+//! A protocol document in Typst, which can be compiled to a PDF with
+//! `typst compile`
+//!
+//! Every type gets a section with its fields or variants, how each is
+//! encoded, and its largest encoded size, followed by every endpoint, topic
+//! and protocol with their postcard-rpc keys. Types are linked to from
+//! everywhere they are used.
+
+use core::fmt::Write;
+
+use super::document::{self, anchor, Kind, Level, Markup};
+use crate::Pidl;
+
+/// Generate a Typst document describing everything in `p`
+pub fn generate_typst(p: &Pidl) -> String {
+    document::generate(&Typst, p)
+}
+
+struct Typst;
+
+impl Markup for Typst {
+    const SAMPLES: bool = false;
+
+    fn preamble(&self, out: &mut String, p: &Pidl) {
+        writeln!(out, "// @generated by pidl, do not edit").unwrap();
+        writeln!(out, "// source hash: {:016x}", p.source_hash).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "#set document(title: \"Protocol reference\")").unwrap();
+        writeln!(out, "#set table(inset: 6pt, stroke: 0.5pt + gray)").unwrap();
+        writeln!(out, "#show raw: set text(font: \"DejaVu Sans Mono\")").unwrap();
+    }
+
+    fn heading(&self, out: &mut String, level: Level, title: &str, target: Option<(Kind, &str)>) {
+        let marks = match level {
+            Level::Title | Level::Section => "=",
+            Level::Item => "==",
+            Level::Member => "===",
+        };
+        writeln!(out).unwrap();
+        match target {
+            Some((kind, name)) => writeln!(out, "{marks} {title} <{}>", anchor(kind, name)),
+            None => writeln!(out, "{marks} {title}"),
+        }
+        .unwrap();
+    }
+
+    fn table<const N: usize>(&self, out: &mut String, header: [&str; N], rows: Vec<[String; N]>) {
+        writeln!(out).unwrap();
+        writeln!(out, "#table(").unwrap();
+        writeln!(out, "  columns: {N},").unwrap();
+        let header: Vec<String> = header.iter().map(|h| format!("[*{h}*]")).collect();
+        writeln!(out, "  table.header({}),", header.join(", ")).unwrap();
+        for row in rows {
+            let cells: Vec<String> = row.iter().map(|cell| format!("[{cell}]")).collect();
+            writeln!(out, "  {},", cells.join(", ")).unwrap();
+        }
+        writeln!(out, ")").unwrap();
+    }
+
+    fn link(&self, kind: Kind, name: &str) -> String {
+        format!("#link(<{}>)[`{name}`]", anchor(kind, name))
+    }
+
+    fn text(&self, s: &str) -> String {
+        text(s)
+    }
+}
+
+/// Text with anything Typst would treat as markup escaped, except for
+/// balanced backticks, which are kept as raw text
+fn text(s: &str) -> String {
+    let keep_raw = s.matches('`').count().is_multiple_of(2);
+    let mut out = String::new();
+    let mut in_raw = false;
+    for c in s.chars() {
+        match c {
+            '`' if keep_raw => {
+                in_raw = !in_raw;
+                out.push(c);
+            }
+            _ if in_raw => out.push(c),
+            '\\' | '#' | '$' | '@' | '<' | '>' | '[' | ']' | '*' | '_' | '`' | '~' | '/' | '='
+            | '-' | '+' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
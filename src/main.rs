@@ -1,15 +1,294 @@
-use postcard_idl::{generate::rust_std::generate_rust_std, Pidl};
-
-// A small harness
-const INPUT: &str = include_str!("../input/input-001.kdl");
-
-fn main() {
-    println!("{INPUT}");
-    let parse = Pidl::parse_from_str(INPUT).unwrap();
-    println!("{:#?}", parse.types);
-    let gen = generate_rust_std(&parse);
-    println!("/* GENERATED */");
-    println!();
-    println!("{}", gen.aliases);
-    println!("{}", gen.types);
+//! The `pidl` command line tool
+//!
+//! Exit codes:
+//!
+//! * `0`: success
+//! * `1`: the IDL has errors, which are reported as diagnostics,
+//!   `pidl fmt --check` found files that aren't formatted, or `pidl compat`
+//!   found breaking changes, `pidl import` was given schemas it can't
+//!   write, or `pidl extract` couldn't read a crate
+//! * `2`: invalid command line usage
+//! * `3`: the output could not be written
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+use miette::{NamedSource, Report};
+use postcard_idl::{
+    generate::{
+        compat::generate_compat_report,
+        dump::{generate_json, generate_yaml},
+        keys::generate_key_report,
+        markdown::generate_markdown,
+        rust_heapless::{self, generate_rust_heapless},
+        rust_std::{self, generate_rust_std},
+        sizes::generate_size_report,
+        typst::generate_typst,
+    },
+    pidl::{compare, emit::emit, extract::extract, format_str, Error, Severity},
+    Pidl,
+};
+use postcard_schema::schema::owned::OwnedNamedType;
+
+#[derive(Parser)]
+#[command(name = "pidl", version, about = "Tools for postcard-idl files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Parse and resolve an IDL file, and everything it imports
+    Check {
+        file: PathBuf,
+        /// Print the postcard-rpc keys of all endpoints and topics
+        #[arg(long)]
+        keys: bool,
+        /// Print the largest encoded size in bytes of every type, endpoint
+        /// and topic
+        #[arg(long)]
+        sizes: bool,
+    },
+    /// Generate code from an IDL file
+    Gen {
+        #[arg(long, value_enum)]
+        lang: Lang,
+        /// The directory to write generated files to
+        #[arg(long)]
+        out: PathBuf,
+        /// Extra traits to derive for every type, e.g. `Debug,Clone`
+        #[arg(long, value_delimiter = ',')]
+        derive: Vec<String>,
+        /// An extra outer attribute for every type, e.g. `#[non_exhaustive]`
+        #[arg(long)]
+        attr: Vec<String>,
+        /// Also generate borrowed versions of the types, which deserialize
+        /// without allocating (`rust-std` only)
+        #[arg(long)]
+        borrowed: bool,
+        file: PathBuf,
+    },
+    /// Print the resolved contents of an IDL file, in a stable format
+    /// described in `postcard_idl::pidl::export`
+    Dump {
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        file: PathBuf,
+    },
+    /// Write an IDL document from schemas, e.g. those reported by a
+    /// postcard-rpc device, as a JSON list of `OwnedNamedType`s
+    Import { file: PathBuf },
+    /// Write an IDL document from the types of a Rust crate on disk that
+    /// derive `Schema`, `Serialize` and `Deserialize`
+    Extract { dir: PathBuf },
+    /// Compare two revisions of an IDL file, failing on breaking changes
+    Compat { old: PathBuf, new: PathBuf },
+    /// Rewrite IDL files in the canonical style
+    Fmt {
+        /// Don't write anything, fail if any file isn't formatted
+        #[arg(long)]
+        check: bool,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Lang {
+    /// Rust, using `std` types
+    RustStd,
+    /// Rust, using `heapless` types for `no_std` targets without an
+    /// allocator
+    RustHeapless,
+    /// A Typst protocol document, with every type, endpoint and topic
+    Typst,
+    /// A Markdown reference, with every type, endpoint and topic, and
+    /// sample encodings
+    Markdown,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Yaml,
+}
+
+const EXIT_INVALID: u8 = 1;
+const EXIT_OUTPUT: u8 = 3;
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => code,
+    }
+}
+
+fn run(cli: Cli) -> Result<(), ExitCode> {
+    match cli.command {
+        Command::Check { file, keys, sizes } => {
+            let pidl = parse(&file)?;
+            if keys {
+                print!("{}", generate_key_report(&pidl));
+            }
+            if sizes {
+                print!("{}", generate_size_report(&pidl));
+            }
+        }
+        Command::Gen {
+            lang,
+            out,
+            derive,
+            attr,
+            borrowed,
+            file,
+        } => {
+            if borrowed && !matches!(lang, Lang::RustStd) {
+                let mut cli = Cli::command().bin_name("pidl");
+                cli.build();
+                cli.find_subcommand_mut("gen")
+                    .expect("`gen` is a subcommand")
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "`--borrowed` can only be used with `--lang rust-std`",
+                    )
+                    .exit();
+            }
+            let pidl = parse(&file)?;
+            let (contents, ext) = match lang {
+                Lang::RustStd => {
+                    let options = rust_std::Options {
+                        derives: derive,
+                        attrs: attr,
+                        borrowed,
+                    };
+                    let out = generate_rust_std(&pidl, &options);
+                    warn(&out.warnings);
+                    (out.to_string(), "rs")
+                }
+                Lang::RustHeapless => {
+                    let options = rust_heapless::Options {
+                        derives: derive,
+                        attrs: attr,
+                    };
+                    let out = generate_rust_heapless(&pidl, &options).map_err(|e| {
+                        eprintln!("{:?}", Report::new(e));
+                        ExitCode::from(EXIT_INVALID)
+                    })?;
+                    warn(&out.warnings);
+                    (out.to_string(), "rs")
+                }
+                Lang::Typst => (generate_typst(&pidl), "typ"),
+                Lang::Markdown => (generate_markdown(&pidl), "md"),
+            };
+            let stem = file.file_stem().unwrap_or("generated".as_ref());
+            let path = out.join(stem).with_extension(ext);
+            std::fs::create_dir_all(&out)
+                .and_then(|()| std::fs::write(&path, contents))
+                .map_err(|e| {
+                    eprintln!("error: failed to write `{}`: {e}", path.display());
+                    ExitCode::from(EXIT_OUTPUT)
+                })?;
+        }
+        Command::Dump { format, file } => {
+            let pidl = parse(&file)?;
+            match format {
+                Format::Json => println!("{}", generate_json(&pidl)),
+                Format::Yaml => print!("{}", generate_yaml(&pidl)),
+            }
+        }
+        Command::Import { file } => {
+            let src = read(&file)?;
+            let types: Vec<OwnedNamedType> = serde_json::from_str(&src).map_err(|e| {
+                eprintln!("error: `{}` is not a list of schemas: {e}", file.display());
+                ExitCode::from(EXIT_INVALID)
+            })?;
+            let idl = emit(&types).map_err(|e| {
+                eprintln!("{:?}", Report::new(e));
+                ExitCode::from(EXIT_INVALID)
+            })?;
+            print!("{idl}");
+        }
+        Command::Extract { dir } => {
+            let extracted = extract(&dir).map_err(|e| {
+                eprintln!("{:?}", Report::new(e));
+                ExitCode::from(EXIT_INVALID)
+            })?;
+            for skip in extracted.skipped.iter() {
+                eprintln!(
+                    "warning: skipped `{}` ({}:{}): {}",
+                    skip.name,
+                    skip.file.display(),
+                    skip.line,
+                    skip.reason
+                );
+            }
+            print!("{}", extracted.idl);
+        }
+        Command::Compat { old, new } => {
+            let changes = compare(&parse(&old)?, &parse(&new)?);
+            print!("{}", generate_compat_report(&changes));
+            if changes.iter().any(|c| c.severity == Severity::Breaking) {
+                return Err(ExitCode::from(EXIT_INVALID));
+            }
+        }
+        Command::Fmt { check, files } => {
+            let mut unformatted = false;
+            for file in files {
+                let src = read(&file)?;
+                let formatted = format_str(&src).map_err(|e| {
+                    let src = NamedSource::new(file.display().to_string(), src.clone());
+                    eprintln!("{:?}", Report::new(e).with_source_code(src));
+                    ExitCode::from(EXIT_INVALID)
+                })?;
+                if formatted == src {
+                    continue;
+                }
+                if check {
+                    eprintln!("`{}` is not formatted", file.display());
+                    unformatted = true;
+                } else {
+                    std::fs::write(&file, formatted).map_err(|e| {
+                        eprintln!("error: failed to write `{}`: {e}", file.display());
+                        ExitCode::from(EXIT_OUTPUT)
+                    })?;
+                }
+            }
+            if unformatted {
+                return Err(ExitCode::from(EXIT_INVALID));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Report anything that couldn't be generated
+fn warn(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+}
+
+/// Read a file, reporting any errors
+fn read(file: &Path) -> Result<String, ExitCode> {
+    std::fs::read_to_string(file).map_err(|source| {
+        let e = Error::Io {
+            path: file.display().to_string(),
+            source,
+            span: None,
+        };
+        eprintln!("{:?}", Report::new(e));
+        ExitCode::from(EXIT_INVALID)
+    })
+}
+
+/// Parse a file, reporting any errors
+fn parse(file: &Path) -> Result<Pidl, ExitCode> {
+    Pidl::parse_from_file(file).map_err(|e| {
+        eprintln!("{:?}", Report::new(e));
+        ExitCode::from(EXIT_INVALID)
+    })
 }
@@ -0,0 +1,452 @@
+//! Changes between two revisions of an IDL document, and whether they break
+//! anything
+//!
+//! postcard encodes values by position, without any names, so what matters
+//! on the wire is the order and types of fields, and the index of enum
+//! variants. Changes are judged by whether values encoded with the old types
+//! still decode the same with the new ones, which is what a device with new
+//! firmware needs from an old client. The reverse doesn't always hold, e.g.
+//! old firmware can't decode an enum variant that was added later.
+
+use core::fmt;
+
+use postcard_schema::schema::owned::{OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType};
+use serde::Serialize;
+
+use super::is_user_ty;
+
+use super::{Bounds, Pidl};
+
+/// How much a [`Change`] breaks, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+    /// Old encodings decode the same with the new types
+    Compatible,
+    /// The encoding is the same, but code using the generated types has to
+    /// change, e.g. a renamed field
+    SourceBreaking,
+    /// Old encodings decode differently or not at all, or postcard-rpc
+    /// clients can't reach an endpoint or topic any more
+    Breaking,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Compatible => "compatible",
+            Severity::SourceBreaking => "source-breaking",
+            Severity::Breaking => "breaking",
+        })
+    }
+}
+
+/// A single difference between two revisions, see [`compare`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub severity: Severity,
+    /// Where the change is, as a member path like the keys of
+    /// [`Pidl::bounds`], e.g. `Status.label` or `Command.Send.data`
+    ///
+    /// Items of sequences are `[]`, the keys and values of maps are `{key}`
+    /// and `{value}`, and the items of tuples are numbered, e.g.
+    /// `Status.readings[]`. Endpoints and topics are their name, followed by
+    /// `.req`, `.resp` or `.ty` for their types.
+    pub path: String,
+    /// What changed, e.g. ``field `name` renamed to `label` ``
+    pub message: String,
+}
+
+/// Compare the types, endpoints and topics of two revisions
+///
+/// Changes are listed in the order of the old revision, followed by
+/// anything that was added. Changes to a user type are only listed once, at
+/// its definition, rather than everywhere it is used.
+pub fn compare(old: &Pidl, new: &Pidl) -> Vec<Change> {
+    let mut cmp = Comparer {
+        old,
+        new,
+        seen: vec![],
+        changes: vec![],
+    };
+    cmp.compare_types();
+    cmp.compare_bounds();
+    cmp.compare_rpc();
+    cmp.changes
+}
+
+struct Comparer<'a> {
+    old: &'a Pidl,
+    new: &'a Pidl,
+    /// Pairs of differently named user types that have been compared, to
+    /// stop at recursive types
+    seen: Vec<(&'a str, &'a str)>,
+    changes: Vec<Change>,
+}
+
+/// The contents of a user type or enum variant, in the order they are
+/// encoded, with their names if they are fields
+type Contents<'a> = Vec<(Option<&'a str>, &'a OwnedNamedType)>;
+
+impl<'a> Comparer<'a> {
+    fn push(&mut self, severity: Severity, path: &str, message: String) {
+        self.changes.push(Change {
+            severity,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn compare_types(&mut self) {
+        for o in self.old.types.iter() {
+            match self.new.types.iter().find(|n| n.name == o.name) {
+                Some(n) if is_user_ty(o) && is_user_ty(n) => self.compare_user_tys(o, n, &o.name),
+                Some(n) => self.compare_tys(o, n, &o.name),
+                None => self.push(Severity::SourceBreaking, &o.name, "type removed".into()),
+            }
+        }
+        for n in self.new.types.iter() {
+            if !self.old.types.iter().any(|o| o.name == n.name) {
+                self.push(Severity::Compatible, &n.name, "type added".into());
+            }
+        }
+    }
+
+    /// Limits aren't encoded, but decoders may rely on them, e.g. the
+    /// capacity of `heapless` types
+    fn compare_bounds(&mut self) {
+        let paths = self.old.bounds.keys().chain(
+            self.new
+                .bounds
+                .keys()
+                .filter(|path| !self.old.bounds.contains_key(*path)),
+        );
+        for path in paths {
+            let o = self.old.bounds.get(path).copied().unwrap_or_default();
+            let n = self.new.bounds.get(path).copied().unwrap_or_default();
+            if o == n {
+                continue;
+            }
+            let tighter = match (o.max, n.max) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(o), Some(n)) => n < o,
+            } || n.min.unwrap_or(0) > o.min.unwrap_or(0);
+            let severity = match tighter {
+                true => Severity::Breaking,
+                false => Severity::Compatible,
+            };
+            let message = format!("limits change from {} to {}", limits(o), limits(n));
+            self.push(severity, path, message);
+        }
+    }
+
+    fn compare_rpc(&mut self) {
+        for o in self.old.endpoints.iter() {
+            let Some(n) = self.new.endpoints.iter().find(|n| n.name == o.name) else {
+                self.push(Severity::Breaking, &o.name, "endpoint removed".into());
+                continue;
+            };
+            self.compare_path(&o.name, &o.path, &n.path);
+            self.compare_tys(&o.req, &n.req, &format!("{}.req", o.name));
+            self.compare_tys(&o.resp, &n.resp, &format!("{}.resp", o.name));
+            if o.path == n.path && o.req_key() != n.req_key() {
+                self.key_changed(&o.name, "request");
+            }
+            if o.path == n.path && o.resp_key() != n.resp_key() {
+                self.key_changed(&o.name, "response");
+            }
+        }
+        for n in self.new.endpoints.iter() {
+            if !self.old.endpoints.iter().any(|o| o.name == n.name) {
+                self.push(Severity::Compatible, &n.name, "endpoint added".into());
+            }
+        }
+
+        for o in self.old.topics.iter() {
+            let Some(n) = self.new.topics.iter().find(|n| n.name == o.name) else {
+                self.push(Severity::Breaking, &o.name, "topic removed".into());
+                continue;
+            };
+            self.compare_path(&o.name, &o.path, &n.path);
+            if o.direction != n.direction {
+                self.push(Severity::Breaking, &o.name, "direction changed".into());
+            }
+            self.compare_tys(&o.ty, &n.ty, &format!("{}.ty", o.name));
+            if o.path == n.path && o.key() != n.key() {
+                self.key_changed(&o.name, "message");
+            }
+        }
+        for n in self.new.topics.iter() {
+            if !self.old.topics.iter().any(|o| o.name == n.name) {
+                self.push(Severity::Compatible, &n.name, "topic added".into());
+            }
+        }
+    }
+
+    fn compare_path(&mut self, name: &str, o: &str, n: &str) {
+        if o != n {
+            let message = format!("path changes from `{o}` to `{n}`");
+            self.push(Severity::Breaking, name, message);
+        }
+    }
+
+    /// postcard-rpc keys are a hash of the path and the whole schema,
+    /// including the names of fields and variants but not the names of
+    /// types, so renaming a type keeps the keys and almost any other change
+    /// to the types changes them
+    fn key_changed(&mut self, name: &str, what: &str) {
+        let message = format!("the {what} key changes, so old clients can't reach it");
+        self.push(Severity::Breaking, name, message);
+    }
+
+    /// Compare two references to types, at the member `path`
+    fn compare_tys(&mut self, o: &'a OwnedNamedType, n: &'a OwnedNamedType, path: &str) {
+        use OwnedDataModelType as Ty;
+
+        if o == n {
+            return;
+        }
+        if is_user_ty(o) && is_user_ty(n) {
+            if o.name == n.name {
+                // Compared at the definition
+                return;
+            }
+            let message = format!("`{}` is now `{}`", o.name, n.name);
+            self.push(Severity::SourceBreaking, path, message);
+            if !self.seen.contains(&(&o.name, &n.name)) {
+                self.seen.push((&o.name, &n.name));
+                self.compare_user_tys(o, n, path);
+            }
+            return;
+        }
+        match (&o.ty, &n.ty) {
+            _ if o.ty == n.ty => {}
+            (_, _) if int_kind(o).is_some() && int_kind(n).is_some() => {
+                self.compare_ints(o, n, path)
+            }
+            (Ty::String, Ty::ByteArray) => {
+                let message = format!("`{}` is now `{}`, which encode the same", o.name, n.name);
+                self.push(Severity::SourceBreaking, path, message);
+            }
+            (Ty::String, Ty::Seq(item)) if item.ty == Ty::U8 => {
+                let message = format!("`{}` is now `{}`, which encode the same", o.name, n.name);
+                self.push(Severity::SourceBreaking, path, message);
+            }
+            (Ty::Option(o), Ty::Option(n)) => self.compare_tys(o, n, path),
+            (Ty::Seq(o), Ty::Seq(n)) => self.compare_tys(o, n, &format!("{path}[]")),
+            (Ty::Map { key: ok, val: ov }, Ty::Map { key: nk, val: nv }) => {
+                self.compare_tys(ok, nk, &format!("{path}{{key}}"));
+                self.compare_tys(ov, nv, &format!("{path}{{value}}"));
+            }
+            (Ty::Tuple(os), Ty::Tuple(ns)) if os.len() == ns.len() => {
+                for (i, (o, n)) in os.iter().zip(ns.iter()).enumerate() {
+                    self.compare_tys(o, n, &format!("{path}.{i}"));
+                }
+            }
+            _ => {
+                let message = format!("`{}` is now `{}`", o.name, n.name);
+                self.push(Severity::Breaking, path, message);
+            }
+        }
+    }
+
+    fn compare_ints(&mut self, o: &OwnedNamedType, n: &OwnedNamedType, path: &str) {
+        let (Some(ok), Some(nk)) = (int_kind(o), int_kind(n)) else {
+            unreachable!("only called for integers");
+        };
+        // Varints of the same signedness only get longer, `u8` and `i8` are
+        // a single raw byte
+        let widened = ok.signed == nk.signed && ok.varint && nk.varint && nk.bits >= ok.bits;
+        let (severity, message) = match widened {
+            true => (
+                Severity::Compatible,
+                format!(
+                    "`{}` is now `{}`, which decodes the same varints",
+                    o.name, n.name
+                ),
+            ),
+            false => (
+                Severity::Breaking,
+                format!("`{}` is now `{}`", o.name, n.name),
+            ),
+        };
+        self.push(severity, path, message);
+    }
+
+    /// Compare the definitions of two user types, at the member `path`
+    fn compare_user_tys(&mut self, o: &'a OwnedNamedType, n: &'a OwnedNamedType, path: &str) {
+        match (&o.ty, &n.ty) {
+            (OwnedDataModelType::Enum(os), OwnedDataModelType::Enum(ns)) => {
+                for (i, ov) in os.iter().enumerate() {
+                    let Some(nv) = ns.get(i) else {
+                        let message = format!("variant `{}` removed", ov.name);
+                        self.push(Severity::Breaking, path, message);
+                        continue;
+                    };
+                    let member = format!("{path}.{}", nv.name);
+                    if ov.name != nv.name {
+                        match ns.iter().position(|v| v.name == ov.name) {
+                            Some(j) => {
+                                let message =
+                                    format!("variant `{}` moved from {i} to {j}", ov.name);
+                                self.push(Severity::Breaking, path, message);
+                                continue;
+                            }
+                            None => {
+                                let message =
+                                    format!("variant `{}` renamed to `{}`", ov.name, nv.name);
+                                self.push(Severity::SourceBreaking, path, message);
+                            }
+                        }
+                    }
+                    self.compare_contents(
+                        (variant_kind(&ov.ty), variant_contents(&ov.ty)),
+                        (variant_kind(&nv.ty), variant_contents(&nv.ty)),
+                        &member,
+                    );
+                }
+                for nv in ns.iter().skip(os.len()) {
+                    let message = format!("variant `{}` added", nv.name);
+                    self.push(Severity::Compatible, path, message);
+                }
+            }
+            (OwnedDataModelType::Enum(_), _) | (_, OwnedDataModelType::Enum(_)) => {
+                let message = format!("{} is now {}", ty_kind(o), ty_kind(n));
+                self.push(Severity::Breaking, path, message);
+            }
+            _ => self.compare_contents(
+                (ty_kind(o), ty_contents(o)),
+                (ty_kind(n), ty_contents(n)),
+                path,
+            ),
+        }
+    }
+
+    /// Compare the contents of structs or variants, which are encoded the
+    /// same whatever kind of struct or variant they are
+    fn compare_contents(
+        &mut self,
+        (okind, os): (&str, Contents<'a>),
+        (nkind, ns): (&str, Contents<'a>),
+        path: &str,
+    ) {
+        if okind != nkind {
+            let message = format!("{okind} is now {nkind}");
+            self.push(Severity::SourceBreaking, path, message);
+        }
+        let name = |name: Option<&str>, i: usize| name.map_or(i.to_string(), str::to_string);
+        for (i, (oname, o)) in os.iter().enumerate() {
+            let Some((nname, n)) = ns.get(i) else {
+                let message = format!("field `{}` removed", name(*oname, i));
+                self.push(Severity::Breaking, path, message);
+                continue;
+            };
+            if let (Some(oname), Some(nname)) = (oname, nname) {
+                if oname != nname {
+                    match ns.iter().position(|(n, _)| n == &Some(*oname)) {
+                        Some(j) => {
+                            let message = format!("field `{oname}` moved from {i} to {j}");
+                            self.push(Severity::Breaking, path, message);
+                            continue;
+                        }
+                        None => {
+                            let message = format!("field `{oname}` renamed to `{nname}`");
+                            self.push(Severity::SourceBreaking, path, message);
+                        }
+                    }
+                }
+            }
+            self.compare_tys(o, n, &format!("{path}.{}", name(*nname, i)));
+        }
+        for (i, (nname, _)) in ns.iter().enumerate().skip(os.len()) {
+            let message = format!(
+                "field `{}` added, which old encodings don't have",
+                name(*nname, i)
+            );
+            self.push(Severity::Breaking, path, message);
+        }
+    }
+}
+
+fn ty_kind(ont: &OwnedNamedType) -> &'static str {
+    match ont.ty {
+        OwnedDataModelType::UnitStruct => "a unit struct",
+        OwnedDataModelType::NewtypeStruct(_) => "a newtype struct",
+        OwnedDataModelType::TupleStruct(_) => "a tuple struct",
+        OwnedDataModelType::Struct(_) => "a struct",
+        OwnedDataModelType::Enum(_) => "an enum",
+        _ => "a builtin type",
+    }
+}
+
+fn ty_contents(ont: &OwnedNamedType) -> Contents<'_> {
+    match &ont.ty {
+        OwnedDataModelType::NewtypeStruct(ont) => vec![(None, ont)],
+        OwnedDataModelType::TupleStruct(onts) => onts.iter().map(|ont| (None, ont)).collect(),
+        OwnedDataModelType::Struct(onvs) => onvs
+            .iter()
+            .map(|v| (Some(v.name.as_str()), &v.ty))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn variant_kind(ty: &OwnedDataModelVariant) -> &'static str {
+    match ty {
+        OwnedDataModelVariant::UnitVariant => "a unit variant",
+        OwnedDataModelVariant::NewtypeVariant(_) => "a newtype variant",
+        OwnedDataModelVariant::TupleVariant(_) => "a tuple variant",
+        OwnedDataModelVariant::StructVariant(_) => "a struct variant",
+    }
+}
+
+fn variant_contents(ty: &OwnedDataModelVariant) -> Contents<'_> {
+    match ty {
+        OwnedDataModelVariant::UnitVariant => vec![],
+        OwnedDataModelVariant::NewtypeVariant(ont) => vec![(None, ont)],
+        OwnedDataModelVariant::TupleVariant(onts) => onts.iter().map(|ont| (None, ont)).collect(),
+        OwnedDataModelVariant::StructVariant(onvs) => onvs
+            .iter()
+            .map(|v| (Some(v.name.as_str()), &v.ty))
+            .collect(),
+    }
+}
+
+/// How an integer type is encoded
+struct IntKind {
+    signed: bool,
+    /// Everything but `u8` and `i8`
+    varint: bool,
+    bits: u32,
+}
+
+fn int_kind(ont: &OwnedNamedType) -> Option<IntKind> {
+    let (signed, bits) = match ont.ty {
+        OwnedDataModelType::U8 => (false, 8),
+        OwnedDataModelType::U16 => (false, 16),
+        OwnedDataModelType::U32 => (false, 32),
+        OwnedDataModelType::U64 | OwnedDataModelType::Usize => (false, 64),
+        OwnedDataModelType::U128 => (false, 128),
+        OwnedDataModelType::I8 => (true, 8),
+        OwnedDataModelType::I16 => (true, 16),
+        OwnedDataModelType::I32 => (true, 32),
+        OwnedDataModelType::I64 | OwnedDataModelType::Isize => (true, 64),
+        OwnedDataModelType::I128 => (true, 128),
+        _ => return None,
+    };
+    Some(IntKind {
+        signed,
+        varint: bits > 8,
+        bits,
+    })
+}
+
+/// Limits as written in the IDL, e.g. `min=1 max=8`
+fn limits(bounds: Bounds) -> String {
+    match (bounds.min, bounds.max) {
+        (None, None) => "none".into(),
+        (None, Some(max)) => format!("max={max}"),
+        (Some(min), None) => format!("min={min}"),
+        (Some(min), Some(max)) => format!("min={min} max={max}"),
+    }
+}
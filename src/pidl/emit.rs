@@ -0,0 +1,340 @@
+//! Writing IDL documents from schemas, the reverse of parsing
+//!
+//! This takes [`OwnedNamedType`]s from anywhere, e.g. the `Schema::SCHEMA`
+//! of existing Rust types, or the schemas a postcard-rpc device reports, and
+//! writes a `types` node defining every user type they contain.
+//!
+//! Schemas don't keep everything an IDL document does, so:
+//!
+//! * References to aliases are written as the type they alias, only aliases
+//!   passed in directly are defined
+//! * `usize` and `isize` are written as `u64` and `i64`, which are encoded
+//!   the same way
+//! * Tuples of identical items are written as arrays, e.g. `[u8; 4]`, if
+//!   they are named like one
+//! * Length limits, derives and attributes are lost
+
+use miette::Diagnostic;
+use postcard_schema::schema::owned::{
+    OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue,
+};
+use thiserror::Error;
+
+use super::{
+    builtin_name,
+    format::{format_str, quoted},
+    is_array, is_user_ty,
+    parse::is_rust_ident,
+};
+use crate::generate::rust_std::relative_path;
+
+/// Errors for schemas that can't be written as IDL
+#[derive(Error, Debug, Diagnostic)]
+pub enum Error {
+    #[error("`{name}` can't be used as a type name, {reason}")]
+    #[diagnostic(help(
+        "generic types must be wrapped in a named type, e.g. `struct Ids(Vec<u32>)`"
+    ))]
+    BadName { name: String, reason: &'static str },
+
+    #[error("`{owner}` has a {kind} named `{name}`, which isn't a Rust identifier")]
+    BadMember {
+        owner: String,
+        kind: &'static str,
+        name: String,
+    },
+
+    #[error("There are different types named `{name}`")]
+    #[diagnostic(help("types in different Rust modules may need renaming first"))]
+    Conflict { name: String },
+
+    #[error("The type of `{name}` is `{kind}`, which has no IDL equivalent")]
+    Unsupported { name: String, kind: &'static str },
+}
+
+/// Write an IDL document defining `types`, and every user type they use
+///
+/// User types are defined in the order they are first found. Names with
+/// `::`, e.g. `telemetry::Status`, are defined in a `module` of that name.
+/// Types that aren't user types, like `String`, are defined as aliases.
+pub fn emit(types: &[OwnedNamedType]) -> Result<String, Error> {
+    let mut defns = Defns::default();
+    for ty in types {
+        if !is_user_ty(ty) {
+            defns.insert(ty, true)?;
+        }
+        defns.collect(ty)?;
+    }
+    write_defns(&defns.list)
+}
+
+/// Write a `types` node with exactly these definitions, and whether each is
+/// an alias
+///
+/// User types are only referred to by name, so they may be stubs. They are
+/// referred to relative to the module of the definition, like in Rust, so
+/// names in inner modules can shadow outer ones.
+pub(crate) fn write_defns(defns: &[(&OwnedNamedType, bool)]) -> Result<String, Error> {
+    let mut root = Module::default();
+    for (ty, alias) in defns.iter() {
+        let (module, name) = ty.name.rsplit_once("::").unwrap_or(("", &ty.name));
+        let path: Vec<&str> = module.split("::").filter(|s| !s.is_empty()).collect();
+        root.find(&path).defns.push(defn(name, ty, *alias, module)?);
+    }
+
+    let mut out = String::new();
+    root.write(&mut out, "types");
+    Ok(format_str(&out).expect("emitted IDL is valid KDL"))
+}
+
+/// The types to define, in order, and whether each is an alias
+#[derive(Default)]
+struct Defns<'a> {
+    list: Vec<(&'a OwnedNamedType, bool)>,
+}
+
+impl<'a> Defns<'a> {
+    /// Find every user type `ty` contains, including itself
+    fn collect(&mut self, ty: &'a OwnedNamedType) -> Result<(), Error> {
+        if is_user_ty(ty) && !self.insert(ty, false)? {
+            return Ok(());
+        }
+        match &ty.ty {
+            OwnedDataModelType::Option(t)
+            | OwnedDataModelType::NewtypeStruct(t)
+            | OwnedDataModelType::Seq(t) => self.collect(t),
+            OwnedDataModelType::Tuple(ts) | OwnedDataModelType::TupleStruct(ts) => {
+                ts.iter().try_for_each(|t| self.collect(t))
+            }
+            OwnedDataModelType::Map { key, val } => {
+                self.collect(key)?;
+                self.collect(val)
+            }
+            OwnedDataModelType::Struct(fields) => self.fields(fields),
+            OwnedDataModelType::Enum(variants) => variants.iter().try_for_each(|v| match &v.ty {
+                OwnedDataModelVariant::UnitVariant => Ok(()),
+                OwnedDataModelVariant::NewtypeVariant(t) => self.collect(t),
+                OwnedDataModelVariant::TupleVariant(ts) => {
+                    ts.iter().try_for_each(|t| self.collect(t))
+                }
+                OwnedDataModelVariant::StructVariant(fields) => self.fields(fields),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn fields(&mut self, fields: &'a [OwnedNamedValue]) -> Result<(), Error> {
+        fields.iter().try_for_each(|f| self.collect(&f.ty))
+    }
+
+    /// Add a definition, returning whether it is new
+    ///
+    /// The same type is found once for every reference to it, and the
+    /// references to a recursive type within itself are empty stubs.
+    fn insert(&mut self, ty: &'a OwnedNamedType, alias: bool) -> Result<bool, Error> {
+        check_name(&ty.name)?;
+        let Some(known) = self.list.iter_mut().find(|(t, _)| t.name == ty.name) else {
+            self.list.push((ty, alias));
+            return Ok(true);
+        };
+        if known.0 == ty || is_stub(ty) {
+            Ok(false)
+        } else if is_stub(known.0) {
+            *known = (ty, alias);
+            Ok(true)
+        } else {
+            Err(Error::Conflict {
+                name: ty.name.clone(),
+            })
+        }
+    }
+}
+
+/// The definitions of one module, and its inner modules
+#[derive(Default)]
+struct Module<'a> {
+    defns: Vec<String>,
+    modules: Vec<(&'a str, Module<'a>)>,
+}
+
+impl<'a> Module<'a> {
+    fn find(&mut self, path: &[&'a str]) -> &mut Module<'a> {
+        let Some((first, rest)) = path.split_first() else {
+            return self;
+        };
+        let idx = match self.modules.iter().position(|(name, _)| name == first) {
+            Some(idx) => idx,
+            None => {
+                self.modules.push((first, Module::default()));
+                self.modules.len() - 1
+            }
+        };
+        self.modules[idx].1.find(rest)
+    }
+
+    /// Write the module as a node, the formatter takes care of indentation
+    fn write(&self, out: &mut String, node: &str) {
+        out.push_str(node);
+        out.push_str(" {\n");
+        for defn in self.defns.iter() {
+            out.push_str(defn);
+        }
+        for (name, module) in self.modules.iter() {
+            let mut node = "module ".to_string();
+            quoted(&mut node, name);
+            module.write(out, &node);
+        }
+        out.push_str("}\n");
+    }
+}
+
+/// The node defining a user type or alias, in `module`
+fn defn(name: &str, ty: &OwnedNamedType, alias: bool, module: &str) -> Result<String, Error> {
+    let mut out = String::new();
+    if alias {
+        out.push_str("alias ");
+        quoted(&mut out, name);
+        out.push(' ');
+        quoted(&mut out, &refr(ty, module)?);
+        out.push('\n');
+        return Ok(out);
+    }
+    out.push_str(match ty.ty {
+        OwnedDataModelType::Enum(_) => "enum ",
+        _ => "struct ",
+    });
+    quoted(&mut out, name);
+    match &ty.ty {
+        OwnedDataModelType::UnitStruct => out.push('\n'),
+        OwnedDataModelType::NewtypeStruct(t) => {
+            out.push(' ');
+            quoted(&mut out, &refr(t, module)?);
+            out.push('\n');
+        }
+        OwnedDataModelType::TupleStruct(ts) => {
+            out.push(' ');
+            quoted(&mut out, &tuple(ts, module)?);
+            out.push('\n');
+        }
+        OwnedDataModelType::Struct(fields) => write_fields(&mut out, ty, fields, module)?,
+        OwnedDataModelType::Enum(variants) => {
+            out.push_str(" {\n");
+            for v in variants {
+                check_member(ty, "variant", &v.name)?;
+                quoted(&mut out, &v.name);
+                match &v.ty {
+                    OwnedDataModelVariant::UnitVariant => out.push('\n'),
+                    OwnedDataModelVariant::NewtypeVariant(t) => {
+                        out.push(' ');
+                        quoted(&mut out, &refr(t, module)?);
+                        out.push('\n');
+                    }
+                    OwnedDataModelVariant::TupleVariant(ts) => {
+                        out.push(' ');
+                        quoted(&mut out, &tuple(ts, module)?);
+                        out.push('\n');
+                    }
+                    OwnedDataModelVariant::StructVariant(fields) => {
+                        write_fields(&mut out, ty, fields, module)?
+                    }
+                }
+            }
+            out.push_str("}\n");
+        }
+        _ => unreachable!("only user types are defined as structs and enums"),
+    }
+    Ok(out)
+}
+
+fn write_fields(
+    out: &mut String,
+    owner: &OwnedNamedType,
+    fields: &[OwnedNamedValue],
+    module: &str,
+) -> Result<(), Error> {
+    out.push_str(" {\n");
+    for f in fields {
+        check_member(owner, "field", &f.name)?;
+        quoted(out, &f.name);
+        out.push(' ');
+        quoted(out, &refr(&f.ty, module)?);
+        out.push('\n');
+    }
+    out.push_str("}\n");
+    Ok(())
+}
+
+/// A reference to a type from within `module`, in the ad-hoc syntax for
+/// anything that isn't a user type
+fn refr(ty: &OwnedNamedType, module: &str) -> Result<String, Error> {
+    if let Some(builtin) = builtin_name(&ty.ty) {
+        return Ok(builtin.to_string());
+    }
+    match &ty.ty {
+        OwnedDataModelType::Option(t) => Ok(format!("option<{}>", refr(t, module)?)),
+        OwnedDataModelType::Seq(t) => Ok(format!("[{}]", refr(t, module)?)),
+        OwnedDataModelType::Tuple(ts) => match is_array(ty) {
+            true => Ok(format!("[{}; {}]", refr(&ts[0], module)?, ts.len())),
+            false => tuple(ts, module),
+        },
+        OwnedDataModelType::Map { key, val } => Ok(format!(
+            "map<{}, {}>",
+            refr(key, module)?,
+            refr(val, module)?
+        )),
+        OwnedDataModelType::Schema => Err(Error::Unsupported {
+            name: ty.name.clone(),
+            kind: "schema",
+        }),
+        _ => Ok(relative_path(module, &ty.name)),
+    }
+}
+
+fn tuple(ts: &[OwnedNamedType], module: &str) -> Result<String, Error> {
+    let items = ts
+        .iter()
+        .map(|t| refr(t, module))
+        .collect::<Result<Vec<_>, _>>()?;
+    match items.as_slice() {
+        [item] => Ok(format!("({item},)")),
+        items => Ok(format!("({})", items.join(", "))),
+    }
+}
+
+/// Recursive references are resolved to a type with nothing in it
+fn is_stub(ty: &OwnedNamedType) -> bool {
+    match &ty.ty {
+        OwnedDataModelType::Struct(fields) => fields.is_empty(),
+        OwnedDataModelType::Enum(variants) => variants.is_empty(),
+        OwnedDataModelType::TupleStruct(ts) => ts.is_empty(),
+        _ => false,
+    }
+}
+
+fn check_name(name: &str) -> Result<(), Error> {
+    let last = name.rsplit("::").next().unwrap_or(name);
+    let reason = if !name.split("::").all(is_rust_ident) {
+        "it isn't an identifier or a path of identifiers"
+    } else if super::parse::BUILTIN_TYPE_NAMES.contains(&last) {
+        "it shadows a builtin type"
+    } else {
+        return Ok(());
+    };
+    Err(Error::BadName {
+        name: name.to_string(),
+        reason,
+    })
+}
+
+/// Fields and variants are named by their node, which `pidl check` only
+/// accepts if it is a Rust identifier
+fn check_member(owner: &OwnedNamedType, kind: &'static str, name: &str) -> Result<(), Error> {
+    match is_rust_ident(name) {
+        true => Ok(()),
+        false => Err(Error::BadMember {
+            owner: owner.name.clone(),
+            kind,
+            name: name.to_string(),
+        }),
+    }
+}
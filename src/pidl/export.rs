@@ -0,0 +1,435 @@
+//! A stable, versioned form of a [`Pidl`], for tools that aren't written in
+//! Rust
+//!
+//! [`Pidl`] itself serializes the IR as it is, which follows the internals
+//! of `postcard-schema`. This has a documented shape instead, which only
+//! changes along with [`EXPORT_VERSION`]:
+//!
+//! * Type definitions have a `kind` of `alias`, `unit_struct`,
+//!   `newtype_struct`, `tuple_struct`, `struct` or `enum`, with `type`,
+//!   `items`, `fields` or `variants` as fits.
+//! * References to types have a `kind` of a builtin type name, e.g. `u8`,
+//!   `string` or `seq`, or `ref` with the `name` of a user type. References
+//!   to aliases are resolved, with the name of the alias as `alias`.
+//! * Aliases of user types are of kind `alias`, with a `ref` to that type as
+//!   their `type`. Like `pub type`, they are the same type, so references to
+//!   them are a `ref` to the user type, without an `alias`, see
+//!   [`Pidl::aliases`].
+//! * Fields and variants have the `index` they are encoded with, and
+//!   everything that was defined in the source has a `span`, see [`Span`].
+
+use postcard_schema::{
+    key::Key,
+    schema::owned::{OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue},
+};
+use serde::Serialize;
+
+use super::{Bounds, Pidl, Span, TopicDirection};
+
+/// The version of the format written by [`export`], which is increased with
+/// any change that isn't adding a field
+pub const EXPORT_VERSION: u32 = 1;
+
+/// The whole of a [`Pidl`], see the [module docs](self)
+#[derive(Debug, Clone, Serialize)]
+pub struct Export {
+    pub version: u32,
+    /// [`Pidl::source_hash`], as 16 hex digits
+    pub source_hash: String,
+    /// [`Pidl::files`], which [`Span::file`] indexes
+    pub files: Vec<String>,
+    pub types: Vec<TypeDefn>,
+    pub endpoints: Vec<EndpointDefn>,
+    pub topics: Vec<TopicDefn>,
+    pub protocols: Vec<ProtocolDefn>,
+}
+
+/// A user type or alias, by qualified name
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeDefn {
+    pub name: String,
+    #[serde(flatten)]
+    pub body: TypeBody,
+    /// Limits on the alias, or the contents of a newtype or tuple struct
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<Bounds>,
+    pub derives: Vec<String>,
+    pub attrs: Vec<String>,
+    /// See [`Pidl::docs`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeBody {
+    Alias {
+        #[serde(rename = "type")]
+        ty: TypeRefr,
+    },
+    UnitStruct,
+    NewtypeStruct {
+        #[serde(rename = "type")]
+        ty: TypeRefr,
+    },
+    TupleStruct {
+        items: Vec<TypeRefr>,
+    },
+    Struct {
+        fields: Vec<Field>,
+    },
+    Enum {
+        variants: Vec<Variant>,
+    },
+}
+
+/// A field of a struct or struct variant
+#[derive(Debug, Clone, Serialize)]
+pub struct Field {
+    pub name: String,
+    /// The position of the field, which is the order it is encoded in
+    pub index: usize,
+    #[serde(rename = "type")]
+    pub ty: TypeRefr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<Bounds>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Variant {
+    pub name: String,
+    /// The discriminant the variant is encoded with
+    pub index: usize,
+    #[serde(flatten)]
+    pub body: VariantBody,
+    /// Limits on the contents of a newtype or tuple variant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<Bounds>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VariantBody {
+    Unit,
+    Newtype {
+        #[serde(rename = "type")]
+        ty: TypeRefr,
+    },
+    Tuple {
+        items: Vec<TypeRefr>,
+    },
+    Struct {
+        fields: Vec<Field>,
+    },
+}
+
+/// A reference to a type, with builtins written out in full
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeRefr {
+    #[serde(flatten)]
+    pub ty: TypeRefrKind,
+    /// The alias this was referred to by, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeRefrKind {
+    Bool,
+    I8,
+    U8,
+    I16,
+    I32,
+    I64,
+    I128,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+    Isize,
+    F32,
+    F64,
+    Char,
+    String,
+    ByteArray,
+    Unit,
+    Schema,
+    Option {
+        item: Box<TypeRefr>,
+    },
+    Seq {
+        item: Box<TypeRefr>,
+    },
+    Tuple {
+        items: Vec<TypeRefr>,
+    },
+    Map {
+        key: Box<TypeRefr>,
+        value: Box<TypeRefr>,
+    },
+    /// A user type, by qualified name
+    Ref {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointDefn {
+    pub name: String,
+    pub path: String,
+    pub request: TypeRefr,
+    pub response: TypeRefr,
+    /// The postcard-rpc keys, as 16 hex digits
+    pub request_key: String,
+    pub response_key: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicDefn {
+    pub name: String,
+    pub path: String,
+    #[serde(rename = "type")]
+    pub ty: TypeRefr,
+    /// `in` for client to server, `out` for server to client
+    pub direction: &'static str,
+    /// The postcard-rpc key, as 16 hex digits
+    pub key: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolDefn {
+    pub name: String,
+    /// The names of the endpoints
+    pub endpoints: Vec<String>,
+    /// The names of the topics
+    pub topics: Vec<String>,
+    /// Every type the protocol can send, see [`Protocol::types`]
+    ///
+    /// [`Protocol::types`]: super::Protocol::types
+    pub types: Vec<TypeRefr>,
+    pub span: Option<Span>,
+}
+
+/// Convert a [`Pidl`] to its exported form
+pub fn export(p: &Pidl) -> Export {
+    let cx = Cx { p };
+    Export {
+        version: EXPORT_VERSION,
+        source_hash: format!("{:016x}", p.source_hash),
+        files: p.files.clone(),
+        types: p.types.iter().map(|t| cx.defn(t)).collect(),
+        endpoints: p
+            .endpoints
+            .iter()
+            .map(|ep| EndpointDefn {
+                name: ep.name.clone(),
+                path: ep.path.clone(),
+                request: cx.refr(&ep.req),
+                response: cx.refr(&ep.resp),
+                request_key: hex(ep.req_key()),
+                response_key: hex(ep.resp_key()),
+                span: p.spans.endpoints.get(&ep.name).copied(),
+            })
+            .collect(),
+        topics: p
+            .topics
+            .iter()
+            .map(|tp| TopicDefn {
+                name: tp.name.clone(),
+                path: tp.path.clone(),
+                ty: cx.refr(&tp.ty),
+                direction: match tp.direction {
+                    TopicDirection::ToServer => "in",
+                    TopicDirection::ToClient => "out",
+                },
+                key: hex(tp.key()),
+                span: p.spans.topics.get(&tp.name).copied(),
+            })
+            .collect(),
+        protocols: p
+            .protocols
+            .iter()
+            .map(|proto| ProtocolDefn {
+                name: proto.name.clone(),
+                endpoints: proto.endpoints.iter().map(|ep| ep.name.clone()).collect(),
+                topics: proto.topics.iter().map(|tp| tp.name.clone()).collect(),
+                types: proto.types.iter().map(|t| cx.refr(t)).collect(),
+                span: p.spans.protocols.get(&proto.name).copied(),
+            })
+            .collect(),
+    }
+}
+
+struct Cx<'a> {
+    p: &'a Pidl,
+}
+
+impl Cx<'_> {
+    fn bounds(&self, path: &str) -> Option<Bounds> {
+        self.p.bounds.get(path).copied()
+    }
+
+    fn doc(&self, path: &str) -> Option<String> {
+        self.p.docs.get(path).cloned()
+    }
+
+    fn span(&self, path: &str) -> Option<Span> {
+        self.p.spans.types.get(path).copied()
+    }
+
+    fn defn(&self, t: &OwnedNamedType) -> TypeDefn {
+        let name = &t.name;
+        let body = match &t.ty {
+            _ if self.p.aliases.contains_key(name) => TypeBody::Alias {
+                ty: TypeRefr {
+                    ty: TypeRefrKind::Ref {
+                        name: self.p.aliases[name].clone(),
+                    },
+                    alias: None,
+                },
+            },
+            OwnedDataModelType::UnitStruct => TypeBody::UnitStruct,
+            OwnedDataModelType::NewtypeStruct(ont) => {
+                TypeBody::NewtypeStruct { ty: self.refr(ont) }
+            }
+            OwnedDataModelType::TupleStruct(onts) => TypeBody::TupleStruct {
+                items: onts.iter().map(|ont| self.refr(ont)).collect(),
+            },
+            OwnedDataModelType::Struct(onvs) => TypeBody::Struct {
+                fields: self.fields(name, onvs),
+            },
+            OwnedDataModelType::Enum(variants) => TypeBody::Enum {
+                variants: variants
+                    .iter()
+                    .enumerate()
+                    .map(|(index, v)| {
+                        let path = format!("{name}.{}", v.name);
+                        let body = match &v.ty {
+                            OwnedDataModelVariant::UnitVariant => VariantBody::Unit,
+                            OwnedDataModelVariant::NewtypeVariant(ont) => {
+                                VariantBody::Newtype { ty: self.refr(ont) }
+                            }
+                            OwnedDataModelVariant::TupleVariant(onts) => VariantBody::Tuple {
+                                items: onts.iter().map(|ont| self.refr(ont)).collect(),
+                            },
+                            OwnedDataModelVariant::StructVariant(onvs) => VariantBody::Struct {
+                                fields: self.fields(&path, onvs),
+                            },
+                        };
+                        Variant {
+                            name: v.name.clone(),
+                            index,
+                            body,
+                            bounds: self.bounds(&path),
+                            doc: self.doc(&path),
+                            span: self.span(&path),
+                        }
+                    })
+                    .collect(),
+            },
+            // The body of an alias is its own type, not a reference to
+            // itself
+            _ => TypeBody::Alias {
+                ty: TypeRefr {
+                    alias: None,
+                    ..self.refr(t)
+                },
+            },
+        };
+        let meta = self.p.meta.get(name).cloned().unwrap_or_default();
+        TypeDefn {
+            name: name.clone(),
+            body,
+            bounds: self.bounds(name),
+            derives: meta.derives,
+            attrs: meta.attrs,
+            doc: self.doc(name),
+            span: self.span(name),
+        }
+    }
+
+    /// The fields of a struct or struct variant, whose path is `owner`
+    fn fields(&self, owner: &str, onvs: &[OwnedNamedValue]) -> Vec<Field> {
+        onvs.iter()
+            .enumerate()
+            .map(|(index, f)| {
+                let path = format!("{owner}.{}", f.name);
+                Field {
+                    name: f.name.clone(),
+                    index,
+                    ty: self.refr(&f.ty),
+                    bounds: self.bounds(&path),
+                    doc: self.doc(&path),
+                    span: self.span(&path),
+                }
+            })
+            .collect()
+    }
+
+    fn refr(&self, ont: &OwnedNamedType) -> TypeRefr {
+        let item = |ont| Box::new(self.refr(ont));
+        let ty = match &ont.ty {
+            OwnedDataModelType::Bool => TypeRefrKind::Bool,
+            OwnedDataModelType::I8 => TypeRefrKind::I8,
+            OwnedDataModelType::U8 => TypeRefrKind::U8,
+            OwnedDataModelType::I16 => TypeRefrKind::I16,
+            OwnedDataModelType::I32 => TypeRefrKind::I32,
+            OwnedDataModelType::I64 => TypeRefrKind::I64,
+            OwnedDataModelType::I128 => TypeRefrKind::I128,
+            OwnedDataModelType::U16 => TypeRefrKind::U16,
+            OwnedDataModelType::U32 => TypeRefrKind::U32,
+            OwnedDataModelType::U64 => TypeRefrKind::U64,
+            OwnedDataModelType::U128 => TypeRefrKind::U128,
+            OwnedDataModelType::Usize => TypeRefrKind::Usize,
+            OwnedDataModelType::Isize => TypeRefrKind::Isize,
+            OwnedDataModelType::F32 => TypeRefrKind::F32,
+            OwnedDataModelType::F64 => TypeRefrKind::F64,
+            OwnedDataModelType::Char => TypeRefrKind::Char,
+            OwnedDataModelType::String => TypeRefrKind::String,
+            OwnedDataModelType::ByteArray => TypeRefrKind::ByteArray,
+            OwnedDataModelType::Unit => TypeRefrKind::Unit,
+            OwnedDataModelType::Schema => TypeRefrKind::Schema,
+            OwnedDataModelType::Option(ont) => TypeRefrKind::Option { item: item(ont) },
+            OwnedDataModelType::Seq(ont) => TypeRefrKind::Seq { item: item(ont) },
+            OwnedDataModelType::Tuple(onts) => TypeRefrKind::Tuple {
+                items: onts.iter().map(|ont| self.refr(ont)).collect(),
+            },
+            OwnedDataModelType::Map { key, val } => TypeRefrKind::Map {
+                key: item(key),
+                value: item(val),
+            },
+            OwnedDataModelType::UnitStruct
+            | OwnedDataModelType::NewtypeStruct(_)
+            | OwnedDataModelType::TupleStruct(_)
+            | OwnedDataModelType::Struct(_)
+            | OwnedDataModelType::Enum(_) => {
+                return TypeRefr {
+                    ty: TypeRefrKind::Ref {
+                        name: ont.name.clone(),
+                    },
+                    alias: None,
+                }
+            }
+        };
+        let is_alias = self.p.types.iter().any(|t| t.name == ont.name);
+        TypeRefr {
+            ty,
+            alias: is_alias.then(|| ont.name.clone()),
+        }
+    }
+}
+
+fn hex(key: Key) -> String {
+    key.to_bytes().iter().map(|b| format!("{b:02x}")).collect()
+}
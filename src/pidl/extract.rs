@@ -0,0 +1,546 @@
+//! Extracting IDL from the source of a Rust crate
+//!
+//! This is a path from Rust-first types to an IDL document: every struct and
+//! enum that derives `Schema`, `Serialize` and `Deserialize` is parsed with
+//! `syn`, and written as a definition with [`emit`](super::emit). Only the
+//! crate on disk is read, nothing is fetched or built.
+//!
+//! The document has no modules, types are referred to by the last segment
+//! of their path, so `crate::telemetry::Status` is `Status`. Types that
+//! can't be written as IDL are skipped, with a reason, along with every type
+//! that uses them, so the rest of the document stays valid.
+
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+use postcard_schema::schema::owned::{
+    OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue, OwnedNamedVariant,
+};
+use syn::{
+    ext::IdentExt, spanned::Spanned, Attribute, Expr, Fields, GenericArgument, Item, Lit, LitStr,
+    Meta, PathArguments, Token, Type,
+};
+use thiserror::Error;
+
+use super::{
+    emit::{self, write_defns},
+    parse::is_rust_ident,
+};
+
+/// Errors for crates that can't be read
+#[derive(Error, Debug, Diagnostic)]
+pub enum Error {
+    #[error("Failed to read `{path}`")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse `{path}`: {message}, at line {line}")]
+    Parse {
+        path: String,
+        line: usize,
+        message: String,
+    },
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Emit(#[from] emit::Error),
+}
+
+/// The result of [`extract`]
+#[derive(Debug)]
+pub struct Extracted {
+    /// An IDL document with a `types` node, empty if nothing was found
+    pub idl: String,
+    pub skipped: Vec<Skipped>,
+}
+
+/// A type that derives the traits, but couldn't be extracted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub name: String,
+    pub file: PathBuf,
+    /// 1-based
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Extract IDL definitions from the crate in `dir`
+///
+/// If `dir` has a `src` directory, only that is read. Files are read in
+/// order of their paths, and types are defined in the order they are found.
+pub fn extract(dir: &Path) -> Result<Extracted, Error> {
+    let src = dir.join("src");
+    let root = if src.is_dir() { src } else { dir.to_path_buf() };
+    let mut files = vec![];
+    rust_files(&root, &mut files)?;
+
+    let mut found: Vec<Found> = vec![];
+    let mut skipped = vec![];
+    for file in files {
+        let text = std::fs::read_to_string(&file).map_err(|source| Error::Io {
+            path: file.display().to_string(),
+            source,
+        })?;
+        let parsed = syn::parse_file(&text).map_err(|e| Error::Parse {
+            path: file.display().to_string(),
+            line: e.span().start().line,
+            message: e.to_string(),
+        })?;
+        let mut items = vec![];
+        derived_items(&parsed.items, &mut items);
+        for item in items {
+            let (name, line, ty) = match item {
+                Item::Struct(s) => (s.ident.unraw().to_string(), s.ident.span().start().line, strukt(s)),
+                Item::Enum(e) => (e.ident.unraw().to_string(), e.ident.span().start().line, enumm(e)),
+                _ => unreachable!("only structs and enums are collected"),
+            };
+            let skip = |reason| Skipped {
+                name: name.clone(),
+                file: file.clone(),
+                line,
+                reason,
+            };
+            let ty = match ty {
+                Ok(ty) => ty,
+                Err(reason) => {
+                    skipped.push(skip(reason));
+                    continue;
+                }
+            };
+            match found.iter().find(|f| f.ty.name == name) {
+                Some(first) => skipped.push(skip(format!(
+                    "a type with this name was already found in `{}`, at line {}",
+                    first.file.display(),
+                    first.line
+                ))),
+                None => found.push(Found {
+                    ty,
+                    file: file.clone(),
+                    line,
+                }),
+            }
+        }
+    }
+
+    // Skipping a type skips everything that uses it, until nothing changes
+    while let Some(idx) = found.iter().position(|f| {
+        let mut refs = vec![];
+        user_refs(&f.ty, true, &mut refs);
+        refs.iter().any(|r| !found.iter().any(|f| f.ty.name == *r))
+    }) {
+        let f = found.remove(idx);
+        let mut refs = vec![];
+        user_refs(&f.ty, true, &mut refs);
+        let missing = refs
+            .into_iter()
+            .find(|r| !found.iter().any(|f| f.ty.name == *r) && *r != f.ty.name)
+            .unwrap_or_default();
+        skipped.push(Skipped {
+            name: f.ty.name,
+            file: f.file,
+            line: f.line,
+            reason: format!("uses `{missing}`, which wasn't extracted"),
+        });
+    }
+
+    let idl = match found.is_empty() {
+        true => String::new(),
+        false => {
+            let defns: Vec<_> = found.iter().map(|f| (&f.ty, false)).collect();
+            write_defns(&defns)?
+        }
+    };
+    Ok(Extracted { idl, skipped })
+}
+
+/// A type that was extracted, and where it was defined
+struct Found {
+    ty: OwnedNamedType,
+    file: PathBuf,
+    line: usize,
+}
+
+/// Every `.rs` file in `dir`, recursively, in order of their paths
+fn rust_files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let io = |source| Error::Io {
+        path: dir.display().to_string(),
+        source,
+    };
+    let mut entries = std::fs::read_dir(dir)
+        .map_err(io)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io)?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            rust_files(&path, out)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Structs and enums that derive all of the traits, including those in
+/// inline modules other than tests
+fn derived_items<'a>(items: &'a [Item], out: &mut Vec<&'a Item>) {
+    for item in items {
+        match item {
+            Item::Struct(s) if derives_all(&s.attrs) => out.push(item),
+            Item::Enum(e) if derives_all(&e.attrs) => out.push(item),
+            Item::Mod(m) if !is_cfg_test(&m.attrs) => {
+                if let Some((_, items)) = &m.content {
+                    derived_items(items, out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+const TRAITS: &[&str] = &["Schema", "Serialize", "Deserialize"];
+
+/// Does the item derive every one of [`TRAITS`], directly or behind
+/// `cfg_attr`?
+fn derives_all(attrs: &[Attribute]) -> bool {
+    let mut derived = vec![];
+    for attr in attrs {
+        collect_derives(&attr.meta, &mut derived);
+    }
+    TRAITS.iter().all(|t| derived.contains(&t.to_string()))
+}
+
+fn collect_derives(meta: &Meta, out: &mut Vec<String>) {
+    let Meta::List(list) = meta else {
+        return;
+    };
+    let parsed =
+        list.parse_args_with(syn::punctuated::Punctuated::<Meta, Token![,]>::parse_terminated);
+    let Ok(args) = parsed else {
+        return;
+    };
+    if list.path.is_ident("derive") {
+        for arg in args {
+            if let Some(last) = arg.path().segments.last() {
+                out.push(last.ident.to_string());
+            }
+        }
+    } else if list.path.is_ident("cfg_attr") {
+        // The first argument is the condition
+        for arg in args.iter().skip(1) {
+            collect_derives(arg, out);
+        }
+    }
+}
+
+fn is_cfg_test(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("cfg")
+            && attr
+                .parse_args::<syn::Ident>()
+                .is_ok_and(|ident| ident == "test")
+    })
+}
+
+/// The `#[serde(...)]` options that extraction understands
+#[derive(Default)]
+struct Serde {
+    rename: Option<String>,
+    skip: bool,
+}
+
+/// Read the serde options, failing on any that change the encoding
+fn serde_attrs(attrs: &[Attribute]) -> Result<Serde, String> {
+    let mut out = Serde::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        let parsed = attr.parse_nested_meta(|meta| {
+            let name = meta.path.get_ident().map(|i| i.to_string());
+            match name.as_deref().unwrap_or_default() {
+                "rename" if meta.input.peek(Token![=]) => {
+                    out.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                }
+                "skip" => out.skip = true,
+                // These don't change the encoding
+                "default"
+                | "bound"
+                | "borrow"
+                | "alias"
+                | "deny_unknown_fields"
+                | "crate"
+                | "expecting" => skip_value(&meta)?,
+                other => {
+                    return Err(meta.error(format!(
+                        "`#[serde({other})]` changes the encoding, and has no IDL equivalent"
+                    )))
+                }
+            }
+            Ok(())
+        });
+        parsed.map_err(|e| e.to_string())?;
+    }
+    Ok(out)
+}
+
+fn skip_value(meta: &syn::meta::ParseNestedMeta<'_>) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|inner| skip_value(&inner))?;
+    }
+    Ok(())
+}
+
+/// The name of a type, which stays the Rust name even if serde renames it,
+/// as references to it use that name
+fn type_name(ident: &syn::Ident, attrs: &[Attribute]) -> Result<String, String> {
+    serde_attrs(attrs)?;
+    idl_name("name", ident.unraw().to_string())
+}
+
+/// Names in IDL must be Rust identifiers, and can't be raw, so e.g. a field
+/// `r#type` has no IDL equivalent
+fn idl_name(kind: &str, name: String) -> Result<String, String> {
+    match is_rust_ident(&name) {
+        true => Ok(name),
+        false => Err(format!(
+            "its {kind} `{name}` is a Rust keyword or not an identifier, which IDL names can't be"
+        )),
+    }
+}
+
+fn strukt(s: &syn::ItemStruct) -> Result<OwnedNamedType, String> {
+    no_generics(&s.generics)?;
+    Ok(OwnedNamedType {
+        name: type_name(&s.ident, &s.attrs)?,
+        ty: match &s.fields {
+            Fields::Unit => OwnedDataModelType::UnitStruct,
+            Fields::Unnamed(_) => match unnamed(&s.fields)?.as_slice() {
+                [] => OwnedDataModelType::UnitStruct,
+                [one] => OwnedDataModelType::NewtypeStruct(Box::new(one.clone())),
+                _ => OwnedDataModelType::TupleStruct(unnamed(&s.fields)?),
+            },
+            // Empty structs can't be declared, but encode the same
+            Fields::Named(_) => match named(&s.fields)? {
+                fields if fields.is_empty() => OwnedDataModelType::UnitStruct,
+                fields => OwnedDataModelType::Struct(fields),
+            },
+        },
+    })
+}
+
+fn enumm(e: &syn::ItemEnum) -> Result<OwnedNamedType, String> {
+    no_generics(&e.generics)?;
+    let mut variants = vec![];
+    let mut skipped = None;
+    for v in e.variants.iter() {
+        let serde = serde_attrs(&v.attrs)?;
+        if serde.skip {
+            skipped.get_or_insert(&v.ident);
+            continue;
+        }
+        // serde counts skipped variants when numbering the others, so only
+        // skipping variants at the end leaves the encoding the same
+        if let Some(skipped) = skipped {
+            return Err(format!(
+                "the variant `{skipped}` is skipped, which changes the index of `{}`",
+                v.ident
+            ));
+        }
+        let ty = match &v.fields {
+            Fields::Unit => OwnedDataModelVariant::UnitVariant,
+            Fields::Unnamed(_) => match unnamed(&v.fields)?.as_slice() {
+                [] => OwnedDataModelVariant::UnitVariant,
+                [one] => OwnedDataModelVariant::NewtypeVariant(Box::new(one.clone())),
+                _ => OwnedDataModelVariant::TupleVariant(unnamed(&v.fields)?),
+            },
+            Fields::Named(_) => OwnedDataModelVariant::StructVariant(named(&v.fields)?),
+        };
+        let name = serde.rename.unwrap_or_else(|| v.ident.unraw().to_string());
+        variants.push(OwnedNamedVariant {
+            name: idl_name("variant", name)?,
+            ty,
+        });
+    }
+    if variants.is_empty() {
+        return Err("it has no variants, so can't be encoded".into());
+    }
+    Ok(OwnedNamedType {
+        name: type_name(&e.ident, &e.attrs)?,
+        ty: OwnedDataModelType::Enum(variants),
+    })
+}
+
+fn no_generics(generics: &syn::Generics) -> Result<(), String> {
+    match generics.type_params().next() {
+        Some(param) => Err(format!(
+            "it is generic over `{}`, and IDL types can't be",
+            param.ident
+        )),
+        None => Ok(()),
+    }
+}
+
+fn unnamed(fields: &Fields) -> Result<Vec<OwnedNamedType>, String> {
+    let mut out = vec![];
+    for f in fields.iter() {
+        if !serde_attrs(&f.attrs)?.skip {
+            out.push(refr(&f.ty)?);
+        }
+    }
+    Ok(out)
+}
+
+fn named(fields: &Fields) -> Result<Vec<OwnedNamedValue>, String> {
+    let mut out = vec![];
+    for f in fields.iter() {
+        let serde = serde_attrs(&f.attrs)?;
+        if serde.skip {
+            continue;
+        }
+        let ident = f.ident.as_ref().expect("named fields have names");
+        let name = serde.rename.unwrap_or_else(|| ident.unraw().to_string());
+        out.push(OwnedNamedValue {
+            name: idl_name("field", name)?,
+            ty: refr(&f.ty)?,
+        });
+    }
+    Ok(out)
+}
+
+/// The schema of a Rust type, with user types as stubs with just a name
+fn refr(ty: &Type) -> Result<OwnedNamedType, String> {
+    let unsupported = |why: &str| {
+        let text = ty
+            .span()
+            .source_text()
+            .unwrap_or_else(|| "this type".into());
+        Err(format!("`{text}` {why}"))
+    };
+    let named = |name: &str, ty| OwnedNamedType {
+        name: name.to_string(),
+        ty,
+    };
+    match ty {
+        Type::Paren(p) => refr(&p.elem),
+        Type::Group(g) => refr(&g.elem),
+        Type::Tuple(t) if t.elems.is_empty() => Ok(named("()", OwnedDataModelType::Unit)),
+        Type::Tuple(t) => {
+            let items = t.elems.iter().map(refr).collect::<Result<Vec<_>, _>>()?;
+            Ok(named("(..)", OwnedDataModelType::Tuple(items)))
+        }
+        Type::Reference(r) => match &*r.elem {
+            Type::Path(p) if p.path.is_ident("str") => {
+                Ok(named("string", OwnedDataModelType::String))
+            }
+            Type::Slice(s) => Ok(named(
+                "[T]",
+                OwnedDataModelType::Seq(Box::new(refr(&s.elem)?)),
+            )),
+            elem => refr(elem),
+        },
+        Type::Array(a) => {
+            let Expr::Lit(syn::ExprLit {
+                lit: Lit::Int(len), ..
+            }) = &a.len
+            else {
+                return unsupported("has a length that isn't a number");
+            };
+            let Ok(len) = len.base10_parse::<usize>() else {
+                return unsupported("has a length that isn't a number");
+            };
+            let item = refr(&a.elem)?;
+            Ok(named("[T; N]", OwnedDataModelType::Tuple(vec![item; len])))
+        }
+        Type::Path(p) if p.qself.is_none() => {
+            let last = p.path.segments.last().expect("paths aren't empty");
+            let args: Vec<&Type> = match &last.arguments {
+                PathArguments::None => vec![],
+                PathArguments::AngleBracketed(args) => args
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        GenericArgument::Type(t) => Some(t),
+                        _ => None,
+                    })
+                    .collect(),
+                PathArguments::Parenthesized(_) => return unsupported("is a function"),
+            };
+            let ident = last.ident.unraw().to_string();
+            let builtin = match (ident.as_str(), args.as_slice()) {
+                ("bool", []) => OwnedDataModelType::Bool,
+                ("i8", []) => OwnedDataModelType::I8,
+                ("i16", []) => OwnedDataModelType::I16,
+                ("i32", []) => OwnedDataModelType::I32,
+                ("i64", []) => OwnedDataModelType::I64,
+                ("i128", []) => OwnedDataModelType::I128,
+                ("isize", []) => OwnedDataModelType::Isize,
+                ("u8", []) => OwnedDataModelType::U8,
+                ("u16", []) => OwnedDataModelType::U16,
+                ("u32", []) => OwnedDataModelType::U32,
+                ("u64", []) => OwnedDataModelType::U64,
+                ("u128", []) => OwnedDataModelType::U128,
+                ("usize", []) => OwnedDataModelType::Usize,
+                ("f32", []) => OwnedDataModelType::F32,
+                ("f64", []) => OwnedDataModelType::F64,
+                ("char", []) => OwnedDataModelType::Char,
+                // `heapless::String<N>` has a const argument
+                ("String", []) => OwnedDataModelType::String,
+                ("Option", [t]) => OwnedDataModelType::Option(Box::new(refr(t)?)),
+                ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [t]) => {
+                    OwnedDataModelType::Seq(Box::new(refr(t)?))
+                }
+                ("HashMap" | "BTreeMap", [k, v]) => OwnedDataModelType::Map {
+                    key: Box::new(refr(k)?),
+                    val: Box::new(refr(v)?),
+                },
+                // Serde sees through these
+                ("Box" | "Rc" | "Arc", [t]) => return refr(t),
+                (_, []) => return Ok(named(&ident, OwnedDataModelType::Struct(vec![]))),
+                _ => return unsupported("is generic, and IDL types can't be"),
+            };
+            Ok(named(&ident, builtin))
+        }
+        _ => unsupported("has no IDL equivalent"),
+    }
+}
+
+/// The names of the user types `ty` refers to
+fn user_refs(ty: &OwnedNamedType, top: bool, out: &mut Vec<String>) {
+    match &ty.ty {
+        OwnedDataModelType::Struct(fields) if !top && fields.is_empty() => {
+            out.push(ty.name.clone())
+        }
+        OwnedDataModelType::Option(t)
+        | OwnedDataModelType::Seq(t)
+        | OwnedDataModelType::NewtypeStruct(t) => user_refs(t, false, out),
+        OwnedDataModelType::Tuple(ts) | OwnedDataModelType::TupleStruct(ts) => {
+            ts.iter().for_each(|t| user_refs(t, false, out))
+        }
+        OwnedDataModelType::Map { key, val } => {
+            user_refs(key, false, out);
+            user_refs(val, false, out);
+        }
+        OwnedDataModelType::Struct(fields) => {
+            fields.iter().for_each(|f| user_refs(&f.ty, false, out))
+        }
+        OwnedDataModelType::Enum(variants) => {
+            for v in variants {
+                match &v.ty {
+                    OwnedDataModelVariant::UnitVariant => {}
+                    OwnedDataModelVariant::NewtypeVariant(t) => user_refs(t, false, out),
+                    OwnedDataModelVariant::TupleVariant(ts) => {
+                        ts.iter().for_each(|t| user_refs(t, false, out))
+                    }
+                    OwnedDataModelVariant::StructVariant(fields) => {
+                        fields.iter().for_each(|f| user_refs(&f.ty, false, out))
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
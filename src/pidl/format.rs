@@ -0,0 +1,336 @@
+//! The canonical formatting of IDL documents, used by `pidl fmt`
+//!
+//! The canonical style is:
+//!
+//! * Four spaces of indentation, one node per line
+//! * Node names and property keys are bare identifiers where possible
+//! * String arguments and property values are always double-quoted, except
+//!   for the `as name` of imports, which are bare like identifiers
+//! * The fields of structs and struct variants are aligned in columns,
+//!   runs of fields are separated by blank lines
+//! * Comments are kept, and runs of blank lines are collapsed into one
+
+use kdl::{KdlDocument, KdlEntry, KdlIdentifier, KdlNode, KdlValue};
+
+use super::Error;
+
+const INDENT: &str = "    ";
+
+/// Format an IDL document in the canonical style
+///
+/// This only checks that `src` is valid KDL, not that it is a valid IDL
+/// document.
+pub fn format_str(src: &str) -> Result<String, Error> {
+    let doc = KdlDocument::parse(src)?;
+    let mut out = String::new();
+    let mut block = Block::new(&mut out, 0);
+    if let Some(format) = doc.format() {
+        block.trivia(&format.leading);
+    }
+    block.nodes(doc.nodes(), Body::Other);
+    if let Some(format) = doc.format() {
+        block.trivia(&format.trailing);
+    }
+    block.finish();
+    Ok(out)
+}
+
+/// What the nodes of a block are, which decides how they are laid out
+#[derive(Clone, Copy, PartialEq)]
+enum Body {
+    /// The fields of a struct or struct variant, which are aligned
+    Fields,
+    /// The variants of an enum
+    Variants,
+    Other,
+}
+
+/// Lines that are not nodes
+enum Trivia {
+    Blank,
+    Comment(String),
+}
+
+/// The contents of a single document, either the root or a children block
+struct Block<'a> {
+    out: &'a mut String,
+    depth: usize,
+    /// Blank lines and comments that haven't been written yet
+    pending: Vec<Trivia>,
+    /// Whether anything has been written to this block yet
+    started: bool,
+    /// Whether the line of the last node written goes on, i.e. the node
+    /// wasn't terminated by a newline
+    line_open: bool,
+}
+
+impl<'a> Block<'a> {
+    fn new(out: &'a mut String, depth: usize) -> Self {
+        Self {
+            out,
+            depth,
+            pending: vec![],
+            started: false,
+            line_open: false,
+        }
+    }
+
+    /// Collect the comments and blank lines out of whitespace between nodes
+    ///
+    /// A comment after the `}` of a node, e.g. `} // comment`, goes on the
+    /// next line: `kdl` can't parse another node after it.
+    fn trivia(&mut self, text: &str) {
+        let mut lines = text.split('\n').peekable();
+        let mut is_first = true;
+        while let Some(line) = lines.next() {
+            let is_last = lines.peek().is_none();
+            let same_line = std::mem::take(&mut is_first) && self.line_open;
+            if same_line {
+                let line = line.trim();
+                if !line.is_empty() {
+                    self.pending.push(Trivia::Comment(line.to_string()));
+                }
+            } else if !line.trim().is_empty() {
+                self.pending
+                    .push(Trivia::Comment(line.trim_end().to_string()));
+            } else if !is_last {
+                self.pending.push(Trivia::Blank);
+            }
+        }
+    }
+
+    /// Write out pending trivia, dropping blank lines at the start of a block
+    fn flush(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        let mut blank = false;
+        let mut comments: Vec<String> = vec![];
+        for item in pending {
+            match item {
+                Trivia::Blank => {
+                    self.comments(&mut comments);
+                    blank = true;
+                }
+                Trivia::Comment(line) => {
+                    if blank && self.started && comments.is_empty() {
+                        self.out.push('\n');
+                    }
+                    blank = false;
+                    comments.push(line);
+                }
+            }
+        }
+        self.comments(&mut comments);
+        if blank && self.started {
+            self.out.push('\n');
+        }
+    }
+
+    /// Write a run of comment lines, keeping their relative indentation
+    fn comments(&mut self, comments: &mut Vec<String>) {
+        let dedent = comments
+            .iter()
+            .map(|c| c.len() - c.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        for line in comments.drain(..) {
+            self.indent();
+            self.out.push_str(&line[dedent..]);
+            self.out.push('\n');
+            self.started = true;
+            self.line_open = false;
+        }
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn nodes(&mut self, nodes: &[KdlNode], body: Body) {
+        let widths = match body {
+            Body::Fields => field_widths(nodes),
+            _ => vec![0; nodes.len()],
+        };
+        for (node, width) in nodes.iter().zip(widths) {
+            self.node(node, width, body);
+        }
+    }
+
+    fn node(&mut self, node: &KdlNode, width: usize, body: Body) {
+        let format = node.format();
+        if let Some(format) = format {
+            self.trivia(&format.leading);
+        }
+        self.flush();
+
+        self.indent();
+        let start = self.out.len();
+        if let Some(ty) = node.ty() {
+            self.out.push('(');
+            self.out.push_str(&identifier(ty));
+            self.out.push(')');
+        }
+        self.out.push_str(&identifier(node.name()));
+        if body == Body::Fields && !node.entries().is_empty() {
+            let pad = width.saturating_sub(self.out.len() - start);
+            self.out.extend(std::iter::repeat_n(' ', pad));
+        }
+        // `use "../common.kdl" as common`
+        let is_import = matches!(node.name().value(), "use" | "import");
+        for (i, entry) in node.entries().iter().enumerate() {
+            self.out.push(' ');
+            write_entry(self.out, entry, is_import && i > 0);
+        }
+
+        if let Some(before) = format.map(|f| f.before_children.trim()) {
+            if !before.is_empty() {
+                self.out.push(' ');
+                self.out.push_str(before);
+            }
+        }
+
+        if let Some(children) = node.children() {
+            self.out.push_str(" {");
+            // The first line is the rest of the line the block opens on
+            let leading = children.format().map_or("", |f| &f.leading);
+            let (first, rest) = leading.split_once('\n').unwrap_or(("", ""));
+            self.trivia_inline(first);
+            self.out.push('\n');
+            let mut block = Block::new(self.out, self.depth + 1);
+            block.trivia(rest);
+            let inner = match node.name().value() {
+                _ if body == Body::Variants => Body::Fields,
+                "struct" => Body::Fields,
+                "enum" => Body::Variants,
+                _ => Body::Other,
+            };
+            block.nodes(children.nodes(), inner);
+            if let Some(format) = children.format() {
+                block.trivia(&format.trailing);
+            }
+            block.finish();
+            self.indent();
+            self.out.push('}');
+        }
+
+        // Same-line comments end up in the terminator
+        if let Some(format) = format {
+            let comment = format!("{}{}", format.before_terminator, format.terminator);
+            let comment = comment.trim().trim_start_matches(';').trim();
+            if !comment.is_empty() {
+                self.out.push(' ');
+                self.out.push_str(comment);
+            }
+            self.trivia_inline(&format.trailing);
+        }
+        self.out.push('\n');
+        self.started = true;
+        self.line_open = format.is_some_and(|f| !f.terminator.ends_with('\n'));
+    }
+
+    /// Comments directly following a node stay on the same line
+    fn trivia_inline(&mut self, text: &str) {
+        let text = text.trim();
+        if !text.is_empty() {
+            self.out.push(' ');
+            self.out.push_str(text);
+        }
+    }
+
+    /// Write out any trivia at the end of the block
+    fn finish(&mut self) {
+        while matches!(self.pending.last(), Some(Trivia::Blank)) {
+            self.pending.pop();
+        }
+        self.flush();
+    }
+}
+
+/// The width of the name column for each node, which is the widest name in
+/// each run of fields
+///
+/// A run ends at a blank line, or at a node with children.
+fn field_widths(nodes: &[KdlNode]) -> Vec<usize> {
+    let mut widths = vec![0; nodes.len()];
+    let mut start = 0;
+    for i in 0..=nodes.len() {
+        let breaks = match nodes.get(i) {
+            None => true,
+            Some(node) => {
+                node.children().is_some()
+                    || node.format().is_some_and(|f| has_blank_line(&f.leading))
+            }
+        };
+        if breaks {
+            let run = &nodes[start..i];
+            let width = run.iter().map(name_width).max().unwrap_or(0);
+            widths[start..i].fill(width);
+            start = i;
+        }
+    }
+    widths
+}
+
+/// Whether whitespace between two nodes contains a blank line
+fn has_blank_line(leading: &str) -> bool {
+    let mut lines = leading.split('\n');
+    lines.next_back();
+    lines.any(|l| l.trim().is_empty())
+}
+
+fn name_width(node: &KdlNode) -> usize {
+    let ty = node.ty().map_or(0, |ty| identifier(ty).len() + 2);
+    ty + identifier(node.name()).len()
+}
+
+/// An identifier, bare if possible
+fn identifier(ident: &KdlIdentifier) -> String {
+    let mut ident = ident.clone();
+    ident.clear_format();
+    ident.to_string()
+}
+
+/// Write an entry, with a string value written as a bare identifier if
+/// `bare` and possible
+fn write_entry(out: &mut String, entry: &KdlEntry, bare: bool) {
+    // Slashdashed entries end up in the leading whitespace
+    if let Some(format) = entry.format() {
+        let leading = format.leading.trim();
+        if !leading.is_empty() {
+            out.push_str(leading);
+            out.push(' ');
+        }
+    }
+    if let Some(name) = entry.name() {
+        out.push_str(&identifier(name));
+        out.push('=');
+    }
+    if let Some(ty) = entry.ty() {
+        out.push('(');
+        out.push_str(&identifier(ty));
+        out.push(')');
+    }
+    match entry.value() {
+        KdlValue::String(s) if bare => out.push_str(&identifier(&KdlIdentifier::from(s.as_str()))),
+        KdlValue::String(s) => quoted(out, s),
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// A double-quoted string, with escapes
+pub(super) fn quoted(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -1,53 +1,900 @@
-use parse::PidlTypes;
-use postcard_schema::schema::owned::OwnedNamedType;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use kdl::{KdlDocument, KdlNode};
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceSpan};
+use parse::{resolve_protocols, resolve_rpc, FileSpans, Import, PidlTypes, UnresolvedFile};
+use postcard_schema::{
+    key::{hash::Fnv1a64Hasher, Key},
+    schema::owned::{OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue},
+};
+use serde::Serialize;
 use thiserror::Error;
 
+mod compat;
+pub mod emit;
+pub mod export;
+pub mod extract;
+mod format;
 mod parse;
+mod sample;
+mod size;
+
+pub use compat::{compare, Change, Severity};
+pub use format::format_str;
+pub use sample::{sample, Sample};
+pub use size::{max_size, MaxSize};
 
-#[derive(Error, Debug)]
+/// Errors that can occur while parsing and resolving an IDL document
+///
+/// Errors carry [`SourceSpan`]s pointing into the parsed document. Attach the
+/// source text (e.g. with [`miette::Report::with_source_code`]) to render
+/// them with labels.
+#[derive(Error, Debug, Diagnostic)]
 pub enum Error {
     #[error("Parse Error")]
+    #[diagnostic(transparent)]
     Parse(#[from] kdl::KdlError),
 
-    #[error("Validation: {0}")]
-    Invalid(String),
-
     #[error("No Types were found")]
+    #[diagnostic(help("add a `types {{ ... }}` node to the document"))]
     NoTypes,
 
-    #[error("Illegal type name: {0}")]
-    BadName(String),
+    #[error("Unexpected node `{name}`")]
+    UnexpectedNode {
+        name: String,
+        expected: &'static str,
+        #[label("expected {expected}")]
+        span: SourceSpan,
+    },
+
+    #[error("Duplicate `{name}` node")]
+    DuplicateNode {
+        name: String,
+        #[label("duplicate node")]
+        span: SourceSpan,
+        #[label("first defined here")]
+        first: SourceSpan,
+    },
+
+    #[error("Malformed `{what}`")]
+    Malformed {
+        what: String,
+        reason: String,
+        #[label("{reason}")]
+        span: SourceSpan,
+        #[help]
+        help: Option<String>,
+    },
+
+    #[error("Invalid type `{ty}`")]
+    BadTypeRefr {
+        ty: String,
+        reason: String,
+        #[label("{reason}")]
+        span: SourceSpan,
+    },
+
+    #[error("Illegal name `{name}`")]
+    BadName {
+        name: String,
+        reason: String,
+        #[label("{reason}")]
+        span: SourceSpan,
+    },
+
+    #[error("Duplicate {kind} `{name}`")]
+    DuplicateMember {
+        kind: &'static str,
+        name: String,
+        #[label("duplicate {kind}")]
+        span: SourceSpan,
+        #[label("first defined here")]
+        first: SourceSpan,
+    },
+
+    #[error("{count} type definition(s) could not be resolved")]
+    Unresolvable {
+        count: usize,
+        #[related]
+        errors: Vec<Error>,
+    },
+
+    #[error("Unknown type `{name}`, referenced by `{defn}`")]
+    UnknownType {
+        name: String,
+        defn: String,
+        #[label("unknown type")]
+        span: SourceSpan,
+        #[label("in this definition")]
+        defn_span: SourceSpan,
+        #[help]
+        help: Option<String>,
+    },
+
+    #[error("Type reference cycle: {path}")]
+    TypeCycle {
+        path: String,
+        #[label(collection)]
+        labels: Vec<LabeledSpan>,
+        #[help]
+        help: String,
+    },
+
+    #[error("The {kind} `{name}` sends the recursive type `{ty}`")]
+    #[diagnostic(help(
+        "postcard-rpc needs a `Schema` for every message, which recursive types don't have"
+    ))]
+    RecursiveMessage {
+        kind: &'static str,
+        name: String,
+        ty: String,
+        #[label("recursive types can't be sent")]
+        span: SourceSpan,
+    },
+
+    #[error("Could not resolve type `{name}`")]
+    Unresolved {
+        name: String,
+        deps: String,
+        #[label("depends on {deps}, which could not be resolved")]
+        span: SourceSpan,
+    },
+
+    #[error("Failed to read `{path}`")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+        #[label("imported here")]
+        span: Option<SourceSpan>,
+    },
+
+    #[error("Import cycle: {path}")]
+    #[diagnostic(help("files may not import themselves, directly or indirectly"))]
+    ImportCycle {
+        path: String,
+        #[label("imports a file that is already being imported")]
+        span: SourceSpan,
+    },
+
+    #[error("Unknown {kind} `{name}`")]
+    UnknownItem {
+        kind: &'static str,
+        name: String,
+        #[label("unknown {kind}")]
+        span: SourceSpan,
+        #[help]
+        help: Option<String>,
+    },
+
+    /// Errors in one file of a multi-file project, see [`Pidl::parse_from_file`]
+    #[error("Error in `{path}`")]
+    InFile {
+        path: String,
+        #[source_code]
+        src: NamedSource<String>,
+        #[related]
+        errors: Vec<Error>,
+    },
+}
+
+/// A postcard-rpc endpoint, declared in an `endpoints` node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Endpoint {
+    /// The name of the endpoint, used as the name of the marker type
+    pub name: String,
+    /// The path of the endpoint, e.g. `status/get`
+    pub path: String,
+    /// The request type
+    pub req: OwnedNamedType,
+    /// The response type
+    pub resp: OwnedNamedType,
+}
+
+impl Endpoint {
+    /// The postcard-rpc key of the request, see [`schema_key`]
+    pub fn req_key(&self) -> Key {
+        Key::for_owned_schema_path(&self.path, &self.req)
+    }
+
+    /// The postcard-rpc key of the response, see [`schema_key`]
+    pub fn resp_key(&self) -> Key {
+        Key::for_owned_schema_path(&self.path, &self.resp)
+    }
+}
+
+/// A postcard-rpc topic, declared in a `topics` node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Topic {
+    /// The name of the topic, used as the name of the marker type
+    pub name: String,
+    /// The path of the topic, e.g. `telem/v1`
+    pub path: String,
+    /// The message type
+    pub ty: OwnedNamedType,
+    pub direction: TopicDirection,
+}
+
+impl Topic {
+    /// The postcard-rpc key of the message, see [`schema_key`]
+    pub fn key(&self) -> Key {
+        Key::for_owned_schema_path(&self.path, &self.ty)
+    }
+}
+
+/// The direction messages on a [`Topic`] are sent in
+///
+/// Written as `direction="in"` or `direction="out"`, from the point of view
+/// of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TopicDirection {
+    /// Client to server, `in`
+    ToServer,
+    /// Server to client, `out`
+    ToClient,
+}
+
+/// The postcard-rpc [`Key`] for a path and schema, the same as
+/// `Key::for_path::<T>(path)` for the generated type `T`.
+///
+/// Recursive types have no finite schema, so there is no key for them.
+/// Messages are never recursive (see [`Error::RecursiveMessage`]), so
+/// endpoints and topics always have keys.
+pub fn schema_key(path: &str, ty: &OwnedNamedType) -> Option<Key> {
+    (!contains_stub(ty)).then(|| Key::for_owned_schema_path(path, ty))
+}
+
+/// Does this type contain a reference back to a recursive type? See
+/// [`Pidl::types`].
+fn contains_stub(ont: &OwnedNamedType) -> bool {
+    find_stub(ont).is_some()
+}
+
+/// The first reference back to a recursive type within this type, if any
+fn find_stub(ont: &OwnedNamedType) -> Option<&OwnedNamedType> {
+    fn first(ts: &[OwnedNamedType]) -> Option<&OwnedNamedType> {
+        ts.iter().find_map(find_stub)
+    }
+    fn first_field(fields: &[OwnedNamedValue]) -> Option<&OwnedNamedType> {
+        fields.iter().find_map(|f| find_stub(&f.ty))
+    }
+    match &ont.ty {
+        // Empty structs and enums can't be declared, these are stubs
+        OwnedDataModelType::Struct(v) if v.is_empty() => Some(ont),
+        OwnedDataModelType::Enum(v) if v.is_empty() => Some(ont),
+        OwnedDataModelType::TupleStruct(v) if v.is_empty() => Some(ont),
+        OwnedDataModelType::Option(t)
+        | OwnedDataModelType::NewtypeStruct(t)
+        | OwnedDataModelType::Seq(t) => find_stub(t),
+        OwnedDataModelType::Tuple(ts) | OwnedDataModelType::TupleStruct(ts) => first(ts),
+        OwnedDataModelType::Map { key, val } => find_stub(key).or_else(|| find_stub(val)),
+        OwnedDataModelType::Struct(fields) => first_field(fields),
+        OwnedDataModelType::Enum(variants) => variants.iter().find_map(|v| match &v.ty {
+            OwnedDataModelVariant::UnitVariant => None,
+            OwnedDataModelVariant::NewtypeVariant(t) => find_stub(t),
+            OwnedDataModelVariant::TupleVariant(ts) => first(ts),
+            OwnedDataModelVariant::StructVariant(fields) => first_field(fields),
+        }),
+        _ => None,
+    }
+}
+
+/// Is this a user type, i.e. a struct or enum that is defined by name?
+pub(crate) fn is_user_ty(ont: &OwnedNamedType) -> bool {
+    matches!(
+        ont.ty,
+        OwnedDataModelType::UnitStruct
+            | OwnedDataModelType::NewtypeStruct(_)
+            | OwnedDataModelType::TupleStruct(_)
+            | OwnedDataModelType::Struct(_)
+            | OwnedDataModelType::Enum(_)
+    )
+}
+
+/// Is this an array? Arrays are tuples in the schema, but keep a name like
+/// `[T; N]`, so a tuple like `(u8, u8)` stays a tuple.
+///
+/// Aliases only keep their own name, so an alias of a tuple of identical
+/// items is taken to be an array.
+pub(crate) fn is_array(ont: &OwnedNamedType) -> bool {
+    let OwnedDataModelType::Tuple(onts) = &ont.ty else {
+        return false;
+    };
+    let same = onts
+        .first()
+        .is_some_and(|first| onts.iter().all(|t| t == first));
+    match ont.name.chars().next() {
+        Some('[') => same && ont.name.contains(';'),
+        Some('(') => false,
+        _ => same && onts.len() > 1,
+    }
+}
+
+/// The IDL name of a builtin type without any type parameters, e.g. `u8`
+///
+/// `usize` and `isize` are named `u64` and `i64`, which are encoded the same
+/// way.
+pub(crate) fn builtin_name(ty: &OwnedDataModelType) -> Option<&'static str> {
+    Some(match ty {
+        OwnedDataModelType::Bool => "bool",
+        OwnedDataModelType::I8 => "i8",
+        OwnedDataModelType::U8 => "u8",
+        OwnedDataModelType::I16 => "i16",
+        OwnedDataModelType::I32 => "i32",
+        OwnedDataModelType::I64 | OwnedDataModelType::Isize => "i64",
+        OwnedDataModelType::I128 => "i128",
+        OwnedDataModelType::U16 => "u16",
+        OwnedDataModelType::U32 => "u32",
+        OwnedDataModelType::U64 | OwnedDataModelType::Usize => "u64",
+        OwnedDataModelType::U128 => "u128",
+        OwnedDataModelType::F32 => "f32",
+        OwnedDataModelType::F64 => "f64",
+        OwnedDataModelType::Char => "char",
+        OwnedDataModelType::String => "string",
+        OwnedDataModelType::ByteArray => "bytearray",
+        OwnedDataModelType::Unit => "()",
+        _ => return None,
+    })
+}
+
+/// A named set of endpoints and topics, declared with a `protocol` node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Protocol {
+    pub name: String,
+    pub endpoints: Vec<Endpoint>,
+    pub topics: Vec<Topic>,
+    /// Every type used by the protocol, including builtins and any types
+    /// listed in the protocol itself, without duplicates.
+    ///
+    /// This is the aggregate schema of everything the protocol can send.
+    pub types: Vec<OwnedNamedType>,
 }
 
 /// A postcard-idl record
 ///
 /// Parsed from IDL files
+#[derive(Debug, Serialize)]
 pub struct Pidl {
+    /// All defined types, in the order they were defined
+    ///
+    /// References from a recursive type back to itself are represented as a
+    /// "stub" type with the same name and an empty body, the full definition
+    /// can be found by name in this list.
+    ///
+    /// Aliases of user types are listed with their own name and the body of
+    /// the type they stand for, see [`Pidl::aliases`].
     pub types: Vec<OwnedNamedType>,
+    /// Aliases of user types, by qualified name, with the qualified name of
+    /// the user type they stand for
+    ///
+    /// Like `pub type`, these are the same type as the one they stand for,
+    /// so references to them are references to that type, e.g. a field of
+    /// type `alias "Other" "Status"` is a `Status`.
+    pub aliases: BTreeMap<String, String>,
+    /// Extra information about types, by qualified name
+    ///
+    /// Types without any extra information are not included.
+    pub meta: BTreeMap<String, TypeMeta>,
+    /// Length limits, by the path of the member they were set on
+    ///
+    /// Paths are the qualified name of a type, followed by the names of the
+    /// field, variant, or variant field, e.g. `Status`, `Status.label`,
+    /// `Command.Say`, or `Command.Send.data`. A bare type name is an alias,
+    /// or the contents of a newtype or tuple struct. Members without limits
+    /// are not included.
+    pub bounds: BTreeMap<String, Bounds>,
+    /// Documentation, by the path of the member it was written on, see
+    /// [`Pidl::bounds`]
+    ///
+    /// Written as `///` comments directly before a definition, field or
+    /// variant, or as a `doc="..."` property. Lines are joined with `\n`.
+    pub docs: BTreeMap<String, String>,
+    /// All declared endpoints, in the order they were declared
+    pub endpoints: Vec<Endpoint>,
+    /// All declared topics, in the order they were declared
+    pub topics: Vec<Topic>,
+    /// All declared protocols, in the order they were declared
+    pub protocols: Vec<Protocol>,
+    /// The FNV-1a hash of the source text of every parsed file, in the order
+    /// they were loaded
+    pub source_hash: u64,
+    /// The paths of every parsed file, in the order they were loaded
+    ///
+    /// Empty for [`Pidl::parse_from_str`], where spans are in the given
+    /// string.
+    pub files: Vec<String>,
+    /// Where everything was defined
+    pub spans: Spans,
 }
 
-impl Pidl {
-    /// Attempt to parse a [`Pidl`] from the given `str`.
-    pub fn parse_from_str(s: &str) -> Result<Self, Error> {
-        let doc = kdl::KdlDocument::parse(s)?;
-        let mut types = None;
+/// Where types, endpoints, topics and protocols were defined, see [`Span`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Spans {
+    /// Type definitions and their members, by path, e.g. `Status` or
+    /// `Status.label`, see [`Pidl::bounds`]
+    pub types: BTreeMap<String, Span>,
+    /// Endpoints, by name
+    pub endpoints: BTreeMap<String, Span>,
+    /// Topics, by name
+    pub topics: BTreeMap<String, Span>,
+    /// Protocols, by name
+    pub protocols: BTreeMap<String, Span>,
+}
+
+impl Spans {
+    /// Locate spans within the source text of the files they came from
+    fn new(spans: FileSpans, srcs: &[&str]) -> Self {
+        let locate = |spans: BTreeMap<String, (usize, SourceSpan)>| {
+            spans
+                .into_iter()
+                .map(|(path, (file, span))| (path, Span::new(file, span, srcs[file])))
+                .collect()
+        };
+        Self {
+            types: locate(spans.types),
+            endpoints: locate(spans.endpoints),
+            topics: locate(spans.topics),
+            protocols: locate(spans.protocols),
+        }
+    }
+}
+
+/// The location of a node in the source, covering the whole node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Span {
+    /// The index of the file in [`Pidl::files`]
+    pub file: usize,
+    /// The offset of the start, in bytes
+    pub offset: usize,
+    /// The length, in bytes
+    pub len: usize,
+    /// The line of the start, from 1
+    pub line: usize,
+    /// The column of the start, in characters from 1
+    pub column: usize,
+}
+
+impl Span {
+    fn new(file: usize, span: SourceSpan, src: &str) -> Self {
+        let before = &src[..span.offset()];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            file,
+            offset: span.offset(),
+            len: span.len(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// Extra information about a type that isn't part of its schema
+///
+/// Set with `derive` and `attr` properties on a type definition, or on the
+/// `module` or `types` node containing it, e.g.
+/// `struct "Status" derive="Debug, Clone" attr="#[repr(C)]"`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TypeMeta {
+    /// Extra traits to derive for the generated type, e.g. `Debug`
+    pub derives: Vec<String>,
+    /// Extra outer attributes for the generated type, from the outermost
+    /// scope inwards
+    pub attrs: Vec<String>,
+}
+
+/// Limits on the length of the strings, byte arrays, sequences and maps in
+/// the type of a member, see [`Pidl::bounds`]
+///
+/// Set with `max=` and `min=` on an alias, a newtype or tuple struct, a
+/// field, or a variant with a type, e.g. `label "string" min=1 max=32`.
+/// Limits apply to the outermost lengths of the type, looking through
+/// options, tuples and arrays, so `"option<[u8]>" max=8` limits the
+/// sequence. Items of a sequence or map can be limited by using an alias for
+/// them.
+///
+/// Lengths are what postcard encodes as the length prefix, bytes for strings
+/// and items otherwise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Bounds {
+    /// The maximum length, if any
+    pub max: Option<usize>,
+    /// The minimum length, if any, which is never more than the maximum
+    pub min: Option<usize>,
+}
+
+/// The [`Bounds`] that apply at some point within a type
+///
+/// Start with [`BoundsScope::member`] for the type of a member, then follow
+/// the type with [`BoundsScope::enter`] and [`BoundsScope::items`].
+#[derive(Debug, Clone, Copy)]
+pub struct BoundsScope<'a> {
+    bounds: &'a BTreeMap<String, Bounds>,
+    /// The key of the bounds in effect, if any
+    key: Option<&'a str>,
+}
+
+impl<'a> BoundsScope<'a> {
+    /// A scope where no bounds are in effect yet
+    pub fn new(bounds: &'a BTreeMap<String, Bounds>) -> Self {
+        Self { bounds, key: None }
+    }
+
+    /// The scope for the type of the member at `path`, see [`Pidl::bounds`]
+    pub fn member<'b>(self, path: &'b str) -> BoundsScope<'b>
+    where
+        'a: 'b,
+    {
+        BoundsScope {
+            bounds: self.bounds,
+            key: self.bounds.contains_key(path).then_some(path),
+        }
+    }
+
+    /// The scope for `ont`, which is part of the type of this scope
+    ///
+    /// References to aliases bring the bounds of the alias into effect,
+    /// unless the member already has its own.
+    pub fn enter<'b>(self, ont: &'b OwnedNamedType) -> BoundsScope<'b>
+    where
+        'a: 'b,
+    {
+        match self.key {
+            None if self.bounds.contains_key(&ont.name) => BoundsScope {
+                bounds: self.bounds,
+                key: Some(&ont.name),
+            },
+            key => BoundsScope {
+                bounds: self.bounds,
+                key,
+            },
+        }
+    }
+
+    /// The scope for the items of a sequence or map, which the bounds of
+    /// the sequence or map don't apply to
+    pub fn items(self) -> Self {
+        Self { key: None, ..self }
+    }
+
+    /// The bounds in effect
+    pub fn get(&self) -> Bounds {
+        self.key
+            .and_then(|key| self.bounds.get(key))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Hash the source text of the given files, see [`Pidl::source_hash`]
+fn hash_sources<'a>(srcs: impl IntoIterator<Item = &'a str>) -> u64 {
+    let mut hasher = Fnv1a64Hasher::new();
+    for src in srcs {
+        // Length prefixed, so moving text between files changes the hash
+        hasher.update(&(src.len() as u64).to_le_bytes());
+        hasher.update(src.as_bytes());
+    }
+    hasher.digest()
+}
+
+/// A file that has been read and parsed, but not yet resolved
+struct LoadedFile {
+    path: PathBuf,
+    src: String,
+    doc: KdlDocument,
+    /// The indices of the files imported by this file, in order of the
+    /// `use` nodes
+    imports: Vec<usize>,
+}
+
+impl LoadedFile {
+    fn error(&self, error: Error) -> Error {
+        let path = self.path.display().to_string();
+        Error::InFile {
+            src: NamedSource::new(&path, self.src.clone()),
+            path,
+            errors: vec![error],
+        }
+    }
+}
+
+/// The top level nodes of a single document
+struct TopLevel<'a> {
+    types: Option<&'a KdlNode>,
+    endpoints: Option<&'a KdlNode>,
+    topics: Option<&'a KdlNode>,
+    protocols: Vec<&'a KdlNode>,
+    imports: Vec<Import<'a>>,
+}
+
+impl<'a> TopLevel<'a> {
+    fn from_doc(doc: &'a KdlDocument) -> Result<Self, Error> {
+        let mut types: Option<&KdlNode> = None;
+        let mut endpoints: Option<&KdlNode> = None;
+        let mut topics: Option<&KdlNode> = None;
+        let mut protocols: Vec<&KdlNode> = vec![];
+        let mut imports: Vec<Import<'a>> = vec![];
 
         for x in doc.nodes() {
             match x.name().value() {
-                "types" => {
-                    let t = PidlTypes::from_node(x)?;
-                    assert!(types.replace(t).is_none());
+                name @ ("types" | "endpoints" | "topics") => {
+                    let slot = match name {
+                        "types" => &mut types,
+                        "endpoints" => &mut endpoints,
+                        _ => &mut topics,
+                    };
+                    if let Some(first) = slot {
+                        return Err(Error::DuplicateNode {
+                            name: name.into(),
+                            span: x.name().span(),
+                            first: first.name().span(),
+                        });
+                    }
+                    *slot = Some(x);
+                }
+                "protocol" => protocols.push(x),
+                "use" | "import" => {
+                    let import = Import::from_node(x)?;
+                    if let Some(first) = imports.iter().find(|i| i.alias == import.alias) {
+                        return Err(Error::DuplicateMember {
+                            kind: "import",
+                            name: import.alias.to_string(),
+                            span: import.span,
+                            first: first.span,
+                        });
+                    }
+                    imports.push(import);
+                }
+                other => {
+                    return Err(Error::UnexpectedNode {
+                        name: other.to_string(),
+                        expected: "`types`, `endpoints`, `topics`, `protocol`, or `use`",
+                        span: x.name().span(),
+                    })
                 }
-                _ => todo!(),
             }
         }
 
-        let Some(types) = types else {
-            return Err(Error::NoTypes);
+        Ok(Self {
+            types,
+            endpoints,
+            topics,
+            protocols,
+            imports,
+        })
+    }
+}
+
+/// The path each loaded file is imported as, given the `(alias, file index)`
+/// imports of each file, with the root file last
+///
+/// A file imported in several places gets the shortest of its paths, and
+/// the first of those.
+fn namespaces<'a>(imports: &[Vec<(&'a str, usize)>]) -> Vec<Vec<&'a str>> {
+    let mut namespaces: Vec<Option<Vec<&str>>> = vec![None; imports.len()];
+    let root = imports.len() - 1;
+    namespaces[root] = Some(vec![]);
+    let mut queue = VecDeque::from([root]);
+    while let Some(idx) = queue.pop_front() {
+        for (alias, imported) in imports[idx].iter() {
+            if namespaces[*imported].is_none() {
+                let mut path = namespaces[idx].clone().expect("queued files have a path");
+                path.push(*alias);
+                namespaces[*imported] = Some(path);
+                queue.push_back(*imported);
+            }
+        }
+    }
+    namespaces
+        .into_iter()
+        .map(|ns| ns.expect("every file is imported by the root"))
+        .collect()
+}
+
+/// Loads a file and everything it imports, with imports before importers
+#[derive(Default)]
+struct Loader {
+    files: Vec<LoadedFile>,
+    /// Canonical paths of loaded files, to their index in `files`
+    loaded: HashMap<PathBuf, usize>,
+    /// Canonical paths of files currently being loaded
+    stack: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// Load the file at `path`, returning its index.
+    ///
+    /// `span` is the location of the `use` node that imported the file,
+    /// errors at that location must be wrapped by the caller.
+    fn load(&mut self, path: &Path, span: Option<SourceSpan>) -> Result<usize, Error> {
+        let io_err = |source| Error::Io {
+            path: path.display().to_string(),
+            source,
+            span,
+        };
+        let canonical = path.canonicalize().map_err(io_err)?;
+        if let Some(pos) = self.stack.iter().position(|p| *p == canonical) {
+            let path = self.stack[pos..]
+                .iter()
+                .chain([&canonical])
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(Error::ImportCycle {
+                path,
+                span: span.expect("the root file can't be part of a cycle yet"),
+            });
+        }
+        if let Some(idx) = self.loaded.get(&canonical) {
+            return Ok(*idx);
+        }
+
+        let src = std::fs::read_to_string(path).map_err(io_err)?;
+        let mut file = LoadedFile {
+            path: path.to_path_buf(),
+            doc: KdlDocument::new(),
+            src,
+            imports: vec![],
         };
+        file.doc = KdlDocument::parse(&file.src).map_err(|e| file.error(e.into()))?;
+        let imports = TopLevel::from_doc(&file.doc)
+            .map_err(|e| file.error(e))?
+            .imports
+            .iter()
+            .map(|i| (PathBuf::from(i.path), i.span))
+            .collect::<Vec<_>>();
+
+        // Imports are relative to the importing file
+        let dir = path.parent().unwrap_or(Path::new(""));
+        self.stack.push(canonical.clone());
+        for (import, span) in imports {
+            match self.load(&dir.join(import), Some(span)) {
+                Ok(idx) => file.imports.push(idx),
+                Err(e) => {
+                    self.stack.pop();
+                    return Err(match e {
+                        // Errors located at our `use` node
+                        Error::Io { .. } | Error::ImportCycle { .. } => file.error(e),
+                        e => e,
+                    });
+                }
+            }
+        }
+        self.stack.pop();
+
+        let idx = self.files.len();
+        self.files.push(file);
+        self.loaded.insert(canonical, idx);
+        Ok(idx)
+    }
+}
+
+impl Pidl {
+    /// The definition of `ty`, which for an alias of a user type is the
+    /// definition of that type, see [`Pidl::aliases`]
+    ///
+    /// Bounds and docs of members are keyed by the path of the user type,
+    /// so look them up through this.
+    pub fn aliased<'a>(&'a self, ty: &'a OwnedNamedType) -> &'a OwnedNamedType {
+        self.aliases
+            .get(&ty.name)
+            .and_then(|target| self.types.iter().find(|t| t.name == *target))
+            .unwrap_or(ty)
+    }
+
+    /// Attempt to parse a [`Pidl`] from the given `str`.
+    ///
+    /// Documents parsed this way can't import other files, see
+    /// [`Pidl::parse_from_file`].
+    pub fn parse_from_str(s: &str) -> Result<Self, Error> {
+        let doc = kdl::KdlDocument::parse(s)?;
+        let top = TopLevel::from_doc(&doc)?;
+
+        if let Some(import) = top.imports.first() {
+            return Err(Error::Malformed {
+                what: "use".into(),
+                reason: "imports are not supported here".into(),
+                span: import.span,
+                help: Some("use `Pidl::parse_from_file` to parse documents with imports".into()),
+            });
+        }
+        if top.types.is_none() {
+            return Err(Error::NoTypes);
+        }
+
+        let files = [UnresolvedFile::new(&top, vec![], &[])?];
+        let types = PidlTypes::from_files(&files).map_err(|(_, e)| *e)?;
+        let (endpoints, topics) = resolve_rpc(&files).map_err(|(_, e)| *e)?;
+        let protocols = resolve_protocols(&files, &endpoints, &topics).map_err(|(_, e)| *e)?;
+
+        Ok(Self {
+            types: types.resolved,
+            aliases: types.aliases,
+            meta: types.meta,
+            bounds: types.bounds,
+            docs: types.docs,
+            endpoints,
+            topics,
+            protocols,
+            source_hash: hash_sources([s]),
+            files: vec![],
+            spans: Spans::new(FileSpans::from_files(&files), &[s]),
+        })
+    }
+
+    /// Attempt to parse a [`Pidl`] from the file at the given path, along with
+    /// all of the files it imports.
+    ///
+    /// Imports are written as `use "../common.kdl" as common`, with paths
+    /// relative to the importing file, and make the types of the imported
+    /// file available as e.g. `common::Header`. Without `as`, the file name
+    /// is used.
+    ///
+    /// Like modules, imports name their types: in the resulting [`Pidl`],
+    /// and in generated code, the type is `common::Header`, so files can use
+    /// the same names. A file imported in several places is named after the
+    /// shortest of its import paths.
+    ///
+    /// Errors within a file are reported as [`Error::InFile`], which carries
+    /// the source of that file.
+    pub fn parse_from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut loader = Loader::default();
+        loader.load(path.as_ref(), None)?;
+        let loaded = loader.files;
+
+        let tops = loaded
+            .iter()
+            // Already checked while loading
+            .map(|file| TopLevel::from_doc(&file.doc).map_err(|e| file.error(e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let imports: Vec<Vec<(&str, usize)>> = tops
+            .iter()
+            .zip(loaded.iter())
+            .map(|(top, file)| {
+                let aliases = top.imports.iter().map(|i| i.alias);
+                aliases.zip(file.imports.iter().copied()).collect()
+            })
+            .collect();
+        let namespaces = namespaces(&imports);
+
+        let mut files = vec![];
+        let mut any_types = false;
+        for (idx, (top, file)) in tops.iter().zip(loaded.iter()).enumerate() {
+            any_types |= top.types.is_some();
+            let imports = imports[idx].clone();
+            files.push(
+                UnresolvedFile::new(top, imports, &namespaces[idx]).map_err(|e| file.error(e))?,
+            );
+        }
+        if !any_types {
+            return Err(Error::NoTypes);
+        }
+
+        let types = PidlTypes::from_files(&files).map_err(|(idx, e)| loaded[idx].error(*e))?;
+        let (endpoints, topics) = resolve_rpc(&files).map_err(|(idx, e)| loaded[idx].error(*e))?;
+        let protocols = resolve_protocols(&files, &endpoints, &topics)
+            .map_err(|(idx, e)| loaded[idx].error(*e))?;
 
         Ok(Self {
             types: types.resolved,
+            aliases: types.aliases,
+            meta: types.meta,
+            bounds: types.bounds,
+            docs: types.docs,
+            endpoints,
+            topics,
+            protocols,
+            source_hash: hash_sources(loaded.iter().map(|f| f.src.as_str())),
+            files: loaded
+                .iter()
+                .map(|f| f.path.display().to_string())
+                .collect(),
+            spans: Spans::new(
+                FileSpans::from_files(&files),
+                &loaded.iter().map(|f| f.src.as_str()).collect::<Vec<_>>(),
+            ),
         })
     }
 }
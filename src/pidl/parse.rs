@@ -17,9 +17,16 @@
 //! We then do "resolution", which attempts to convert all `UnresolvedTypeDefn`
 //! into `OwnedNameTypes`, and convert all `UnresolvedTypeRefr` into the resolved
 //! `OwnedNameTypes`.
+//!
+//! Types may be recursive, as long as the recursion goes through an `option`,
+//! seq, or map. Since `OwnedNameTypes` are trees, a reference back to a type
+//! that is already being resolved is replaced by a "stub": a type with the
+//! same name and an empty body.
+
+use std::collections::{BTreeMap, HashMap};
 
-use kdl::KdlNode;
-use miette::SourceSpan;
+use kdl::{KdlDocument, KdlEntry, KdlNode};
+use miette::{LabeledSpan, SourceSpan};
 use postcard_schema::{
     schema::owned::{
         OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue,
@@ -28,56 +35,94 @@ use postcard_schema::{
     Schema,
 };
 
-use super::Error;
+use super::{Bounds, Endpoint, Error, Protocol, TopLevel, Topic, TopicDirection, TypeMeta};
+use crate::generate::rust_std::{key_const, snake_case, BORROWED};
+
+/// A result where errors come with the index of the file they occurred in
+pub(crate) type FileResult<T> = Result<T, (usize, Box<Error>)>;
 
 #[derive(Debug)]
 pub struct PidlTypes {
     pub(crate) resolved: Vec<OwnedNamedType>,
+    /// Aliases of user types, by qualified name, with the user type they
+    /// stand for
+    pub(crate) aliases: BTreeMap<String, String>,
+    /// Extra information for each type that has any, by qualified name
+    pub(crate) meta: BTreeMap<String, TypeMeta>,
+    /// Length limits, by member path
+    pub(crate) bounds: BTreeMap<String, Bounds>,
+    /// Documentation, by member path
+    pub(crate) docs: BTreeMap<String, String>,
 }
 
+/// The properties that may be set on `types`, `module`s and type definitions
+const META_PROPS: &[&str] = &["derive", "attr"];
+
+/// The properties of enums, which may also be documented
+const ENUM_PROPS: &[&str] = &["derive", "attr", "doc"];
+
+/// The properties of aliases and structs, which may also have bounds
+const DEFN_PROPS: &[&str] = &["derive", "attr", "doc", "max", "min"];
+
+/// The properties of fields and variants
+const MEMBER_PROPS: &[&str] = &["max", "min", "doc"];
+
 impl PidlTypes {
     fn absorb_alias(node: &KdlNode) -> Result<UnresolvedTypeDefn<'_>, Error> {
-        if let [name, ty] = node.entries() {
-            let name = name
-                .value()
-                .as_string()
-                .expect("alias should have two string args");
-            let ty = ty
-                .value()
-                .as_string()
-                .expect("alias should have two string args");
-
+        no_children(node, "alias")?;
+        if let [name, ty] = node_props(node, "alias", DEFN_PROPS)?.0[..] {
+            let name = entry_str(name, "alias")?;
             Ok(UnresolvedTypeDefn::Alias {
                 name,
-                ty: UnresolvedTypeRefr::parse_entirely(ty)?,
+                ty: UnresolvedTypeRefr::parse_entirely(TyStr::from_entry(ty, "alias")?)?,
                 span: node.span(),
             })
         } else {
-            todo!("alias should have two string args")
+            Err(Error::Malformed {
+                what: "alias".into(),
+                reason: "alias should have two string args".into(),
+                span: node.span(),
+                help: Some(r#"e.g. `alias "Name" "u32"`"#.into()),
+            })
         }
     }
 
     fn absorb_struct_field(node: &KdlNode) -> Result<(&str, UnresolvedTypeRefr<'_>), Error> {
         let name = node.name().value();
-        if let [ty] = node.entries() {
-            let ty = ty
-                .value()
-                .as_string()
-                .expect("struct field should have two string args");
+        member_name_legal(node, "field")?;
+        no_children(node, "field")?;
+        if let [ty] = node_props(node, "field", MEMBER_PROPS)?.0[..] {
+            let ty = TyStr::from_entry(ty, "field")?;
             Ok((name, UnresolvedTypeRefr::parse_entirely(ty)?))
         } else {
-            todo!()
+            Err(Error::Malformed {
+                what: "field".into(),
+                reason: "field should have one string arg".into(),
+                span: node.span(),
+                help: Some(format!(r#"e.g. `{name} "u32"`"#)),
+            })
         }
     }
 
+    fn absorb_fields(
+        children: &kdl::KdlDocument,
+    ) -> Result<Vec<(&str, UnresolvedTypeRefr<'_>)>, Error> {
+        let mut fields = vec![];
+        for ch in children.nodes() {
+            check_unique(children.nodes(), ch, "field")?;
+            fields.push(Self::absorb_struct_field(ch)?);
+        }
+        Ok(fields)
+    }
+
     fn absorb_struct(node: &KdlNode) -> Result<UnresolvedTypeDefn<'_>, Error> {
-        let entries = node.entries();
+        let (entries, _) = node_props(node, "struct", DEFN_PROPS)?;
         let children = node.children();
 
-        match (entries, children) {
+        match (&entries[..], children) {
             ([name], None) => {
                 // UnitStruct
-                let name = name.value().as_string().expect("struct needs a name");
+                let name = entry_str(name, "struct")?;
                 Ok(UnresolvedTypeDefn::UnitStruct {
                     name,
                     span: node.span(),
@@ -85,48 +130,72 @@ impl PidlTypes {
             }
             ([name, ty], None) => {
                 // newtypestruct/tuplestruct
-                let name = name.value().as_string().expect("struct needs a name");
-                let ty = ty.value().as_string().expect("ty needs a ty");
+                let name = entry_str(name, "struct")?;
                 Ok(UnresolvedTypeDefn::NewTypeTupleStruct {
                     name,
-                    ty: UnresolvedTypeRefr::parse_entirely(ty)?,
+                    ty: UnresolvedTypeRefr::parse_entirely(TyStr::from_entry(ty, "struct")?)?,
                     span: node.span(),
                 })
             }
             ([name], Some(children)) => {
                 // struct
-                let name = name.value().as_string().expect("struct needs a name");
-
-                let mut fields = vec![];
-                for ch in children.nodes() {
-                    fields.push(Self::absorb_struct_field(ch)?);
+                let name = entry_str(name, "struct")?;
+                let fields = Self::absorb_fields(children)?;
+                if fields.is_empty() {
+                    return Err(Error::Malformed {
+                        what: "struct".into(),
+                        reason: "struct has no fields".into(),
+                        span: node.span(),
+                        help: Some(format!(
+                            r#"use `struct "{name}"` without a body for a unit struct"#
+                        )),
+                    });
                 }
 
-                // TODO: ensure all field names are unique and valid!
-
                 Ok(UnresolvedTypeDefn::Struct {
                     name,
                     fields,
                     span: node.span(),
                 })
             }
-            _ => {
-                panic!("What? {entries:?}, {children:?}");
-            }
+            (_, children) => Err(Error::Malformed {
+                what: "struct".into(),
+                reason: if children.is_some() {
+                    "a struct with fields should only have a name".into()
+                } else {
+                    "struct should have a name, and optionally a type".into()
+                },
+                span: node.span(),
+                help: None,
+            }),
         }
     }
 
     fn absorb_enum(node: &KdlNode) -> Result<UnresolvedTypeDefn<'_>, Error> {
-        let [name] = node.entries() else {
-            panic!("What? {:?}", node.entries());
+        let [name] = node_props(node, "enum", ENUM_PROPS)?.0[..] else {
+            return Err(Error::Malformed {
+                what: "enum".into(),
+                reason: "enum should have a single name arg".into(),
+                span: node.span(),
+                help: None,
+            });
         };
-        let name = name.value().as_string().expect("enum needs a name");
+        let name = entry_str(name, "enum")?;
         let mut variants = vec![];
-        for ch in node.iter_children() {
-            variants.push(Self::absorb_enum_variant(ch)?);
+        if let Some(children) = node.children() {
+            for ch in children.nodes() {
+                check_unique(children.nodes(), ch, "variant")?;
+                variants.push(Self::absorb_enum_variant(ch)?);
+            }
+        }
+        if variants.is_empty() {
+            return Err(Error::Malformed {
+                what: "enum".into(),
+                reason: "enum has no variants".into(),
+                span: node.span(),
+                help: None,
+            });
         }
-
-        // TODO: ensure all variant names are unique and valid!
 
         Ok(UnresolvedTypeDefn::Enum {
             name,
@@ -137,57 +206,1028 @@ impl PidlTypes {
 
     fn absorb_enum_variant(node: &KdlNode) -> Result<UnresolvedEnumVariant<'_>, Error> {
         let name = node.name().value();
-        let entries = node.entries();
+        member_name_legal(node, "variant")?;
+        let (entries, _) = node_props(node, "variant", MEMBER_PROPS)?;
         let children = node.children();
 
-        match (entries, children) {
+        match (&entries[..], children) {
             ([], None) => Ok(UnresolvedEnumVariant::Unit { name }),
             ([], Some(children)) => {
-                let mut fields = vec![];
-                for ch in children.nodes() {
-                    fields.push(Self::absorb_struct_field(ch)?);
+                let fields = Self::absorb_fields(children)?;
+                if fields.is_empty() {
+                    return Err(Error::Malformed {
+                        what: "variant".into(),
+                        reason: "struct variant has no fields".into(),
+                        span: node.span(),
+                        help: Some(format!("use `{name}` without a body for a unit variant")),
+                    });
                 }
 
                 Ok(UnresolvedEnumVariant::Struct { name, fields })
             }
             ([ty], None) => {
-                let ty = ty.value().as_string().expect("ty needs a ty");
+                let ty = TyStr::from_entry(ty, "variant")?;
                 let item = UnresolvedTypeRefr::parse_entirely(ty)?;
-                if let UnresolvedTypeRefr::Tuple { tys } = item {
-                    Ok(UnresolvedEnumVariant::Tuple { name, fields: tys })
-                } else {
-                    Ok(UnresolvedEnumVariant::NewType { name, ty: item })
+                match item {
+                    UnresolvedTypeRefr::Tuple { tys } if tys.len() > 1 => {
+                        Ok(UnresolvedEnumVariant::Tuple { name, fields: tys })
+                    }
+                    item => Ok(UnresolvedEnumVariant::NewType { name, ty: item }),
+                }
+            }
+            _ => Err(Error::Malformed {
+                what: "variant".into(),
+                reason: "variant should have either one type arg or a body of fields".into(),
+                span: node.span(),
+                help: None,
+            }),
+        }
+    }
+
+    /// Resolve the types of all loaded files.
+    ///
+    /// Files must come after all of the files they import. On failure, the
+    /// index of the file containing the error is returned along with it.
+    pub(crate) fn from_files(files: &[UnresolvedFile<'_>]) -> FileResult<Self> {
+        let meta = files
+            .iter()
+            .flat_map(|f| f.defns.iter())
+            .filter(|d| d.meta != TypeMeta::default())
+            .map(|d| (d.qualified(), d.meta.clone()))
+            .collect();
+        let (resolved, aliases) = resolve_types(files)?;
+
+        // Bounds can only be checked once we know what they are bounding
+        let mut bounds = BTreeMap::new();
+        let mut docs = BTreeMap::new();
+        let mut tys = resolved.iter();
+        for (fidx, file) in files.iter().enumerate() {
+            for (scoped, ty) in file.defns.iter().zip(tys.by_ref()) {
+                for (member, doc) in scoped.docs.iter() {
+                    let mut path = vec![ty.name.as_str()];
+                    path.extend(member.iter());
+                    docs.insert(path.join("."), doc.clone());
+                }
+                for bound in scoped.bounds.iter() {
+                    if let Some(target) = aliases.get(&ty.name) {
+                        return Err((
+                            fidx,
+                            Box::new(Error::Malformed {
+                                what: bound.what.into(),
+                                reason: format!("this is an alias of `{target}`"),
+                                span: bound.span,
+                                help: Some(
+                                    "aliases of user types have the limits of that type".into(),
+                                ),
+                            }),
+                        ));
+                    }
+                    if !member_has_lengths(ty, &bound.member) {
+                        return Err((
+                            fidx,
+                            Box::new(Error::Malformed {
+                                what: bound.what.into(),
+                                reason: "there is no length here to limit".into(),
+                                span: bound.span,
+                                help: Some(
+                                    "`max` and `min` apply to strings, byte arrays, sequences \
+                                     and maps, or options, tuples and arrays of them"
+                                        .into(),
+                                ),
+                            }),
+                        ));
+                    }
+                    let mut path = vec![ty.name.as_str()];
+                    path.extend(bound.member.iter());
+                    bounds.insert(path.join("."), bound.bounds);
                 }
             }
-            _ => todo!("What? entries: {entries:?}, children: {children:?}"),
         }
+
+        Ok(Self {
+            resolved,
+            aliases,
+            meta,
+            bounds,
+            docs,
+        })
     }
 
-    pub fn from_node(node: &KdlNode) -> Result<Self, Error> {
-        assert!(node.entries().is_empty());
+    fn absorb(node: &KdlNode) -> Result<Vec<ScopedDefn<'_>>, Error> {
+        let (args, _) = node_props(node, "types", META_PROPS)?;
+        if let Some(e) = args.first() {
+            return Err(Error::Malformed {
+                what: "types".into(),
+                reason: "unexpected argument".into(),
+                span: e.span(),
+                help: Some("`types` only takes a body of type definitions".into()),
+            });
+        }
+        let meta = absorb_meta(node, "types", &TypeMeta::default())?;
 
         let mut types = vec![];
+        Self::absorb_scope(node, &[], &meta, &mut types)?;
+        Ok(types)
+    }
+
+    /// Gather the definitions in the body of a `types` or `module` node,
+    /// which inherit the derives and attributes of that node
+    fn absorb_scope<'a>(
+        node: &'a KdlNode,
+        module: &[&'a str],
+        meta: &TypeMeta,
+        types: &mut Vec<ScopedDefn<'a>>,
+    ) -> Result<(), Error> {
+        let mut modules: Vec<(&str, SourceSpan)> = vec![];
+
+        for (idx, ch) in node.iter_children().enumerate() {
+            let what = ch.name().value();
+            let defn = match what {
+                "alias" => Self::absorb_alias(ch)?,
+                "struct" => Self::absorb_struct(ch)?,
+                "enum" => Self::absorb_enum(ch)?,
+                "module" => {
+                    let name = Self::absorb_module_name(ch)?;
+                    if let Some((_, first)) = modules.iter().find(|(n, _)| *n == name) {
+                        return Err(Error::DuplicateMember {
+                            kind: "module",
+                            name: name.to_string(),
+                            span: ch.span(),
+                            first: *first,
+                        });
+                    }
+                    modules.push((name, ch.span()));
+                    let mut inner = module.to_vec();
+                    inner.push(name);
+                    let meta = absorb_meta(ch, "module", meta)?;
+                    Self::absorb_scope(ch, &inner, &meta, types)?;
+                    continue;
+                }
+                other => {
+                    return Err(Error::UnexpectedNode {
+                        name: other.to_string(),
+                        expected: "`alias`, `struct`, `enum`, or `module`",
+                        span: ch.name().span(),
+                    })
+                }
+            };
+            // The properties were checked while absorbing the definition
+            let siblings = node.children().expect("the node has children");
+            types.push(ScopedDefn {
+                namespace: vec![],
+                module: module.to_vec(),
+                defn,
+                meta: absorb_meta(ch, what, meta)?,
+                bounds: absorb_bounds(ch)?,
+                spans: member_spans(ch),
+                docs: absorb_docs(siblings, idx)?,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn absorb_module_name(node: &KdlNode) -> Result<&str, Error> {
+        let malformed = |reason: &str, span| Error::Malformed {
+            what: "module".into(),
+            reason: reason.into(),
+            span,
+            help: Some(r#"e.g. `module "telemetry" { struct "Status" { ... } }`"#.into()),
+        };
+        let [name] = node_props(node, "module", META_PROPS)?.0[..] else {
+            return Err(malformed("module should have one string arg", node.span()));
+        };
+        let name = entry_str(name, "module")?;
+        match node.children() {
+            Some(ch) if !ch.nodes().is_empty() => {}
+            Some(ch) => return Err(malformed("modules must not be empty", ch.span())),
+            None => return Err(malformed("modules must have a body", node.span())),
+        }
+        if !is_rust_ident(name) {
+            return Err(Error::BadName {
+                name: name.to_string(),
+                reason: "not a valid module name".into(),
+                span: node.span(),
+            });
+        }
+        Ok(name)
+    }
+}
+
+/// A type definition, along with the (possibly nested) module it is
+/// defined in
+struct ScopedDefn<'a> {
+    /// The path the file it is defined in was imported as, e.g. `common`,
+    /// see [`UnresolvedFile::new`]
+    namespace: Vec<&'a str>,
+    module: Vec<&'a str>,
+    defn: UnresolvedTypeDefn<'a>,
+    /// Including anything inherited from the enclosing modules
+    meta: TypeMeta,
+    bounds: Vec<UnresolvedBound<'a>>,
+    /// The definition and its members, by path within the definition
+    spans: Vec<(Vec<&'a str>, SourceSpan)>,
+    /// The documentation of the definition and its members, by path within
+    /// the definition
+    docs: Vec<(Vec<&'a str>, String)>,
+}
+
+/// The `max=` and `min=` of a definition or one of its members, which can
+/// only be checked once the definition is resolved
+struct UnresolvedBound<'a> {
+    /// The path within the definition, see [`super::Pidl::bounds`]
+    member: Vec<&'a str>,
+    bounds: Bounds,
+    /// The first of the properties, for errors
+    what: &'static str,
+    span: SourceSpan,
+}
+
+impl ScopedDefn<'_> {
+    /// The full name of this type, e.g. `telemetry::Status`, or
+    /// `common::Header` in a file imported as `common`.
+    ///
+    /// This is the name used in the IR.
+    fn qualified(&self) -> String {
+        let mut path = self.namespace.clone();
+        path.extend(self.module.iter());
+        path.push(self.defn.name());
+        path.join("::")
+    }
+}
+
+/// The unresolved contents of a single IDL file
+pub(crate) struct UnresolvedFile<'a> {
+    defns: Vec<ScopedDefn<'a>>,
+    endpoints: Vec<UnresolvedEndpoint<'a>>,
+    topics: Vec<UnresolvedTopic<'a>>,
+    protocols: Vec<UnresolvedProtocol<'a>>,
+    /// The files imported by this file, as `(alias, file index)`
+    imports: Vec<(&'a str, usize)>,
+}
+
+impl<'a> UnresolvedFile<'a> {
+    /// Gather the definitions from the top level nodes of a file
+    ///
+    /// Like a module, the definitions of a file are named after the path it
+    /// was imported as, its `namespace`. This is empty for the root file.
+    pub(crate) fn new(
+        top: &TopLevel<'a>,
+        imports: Vec<(&'a str, usize)>,
+        namespace: &[&'a str],
+    ) -> Result<Self, Error> {
+        let mut defns = match top.types {
+            Some(node) => PidlTypes::absorb(node)?,
+            None => vec![],
+        };
+        for d in defns.iter_mut() {
+            d.namespace = namespace.to_vec();
+        }
+        // Imports and modules share the paths of their types
+        for import in top.imports.iter() {
+            if defns
+                .iter()
+                .any(|d| d.module.first() == Some(&import.alias))
+            {
+                return Err(Error::BadName {
+                    name: import.alias.to_string(),
+                    reason: "a module with this name already exists".into(),
+                    span: import.span,
+                });
+            }
+        }
+        let endpoints = match top.endpoints {
+            Some(node) => UnresolvedEndpoint::absorb(node)?,
+            None => vec![],
+        };
+        let topics = match top.topics {
+            Some(node) => UnresolvedTopic::absorb(node)?,
+            None => vec![],
+        };
+        let protocols = top
+            .protocols
+            .iter()
+            .map(|node| UnresolvedProtocol::absorb(node))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            defns,
+            endpoints,
+            topics,
+            protocols,
+            imports,
+        })
+    }
+
+    /// Is there a module at this path?
+    fn has_module(&self, module: &[&str]) -> bool {
+        self.defns.iter().any(|d| d.module.starts_with(module))
+    }
+}
+
+/// Where everything in a set of files was defined, as `(file index, span)`,
+/// see [`super::Spans`]
+#[derive(Default)]
+pub(crate) struct FileSpans {
+    pub(crate) types: BTreeMap<String, (usize, SourceSpan)>,
+    pub(crate) endpoints: BTreeMap<String, (usize, SourceSpan)>,
+    pub(crate) topics: BTreeMap<String, (usize, SourceSpan)>,
+    pub(crate) protocols: BTreeMap<String, (usize, SourceSpan)>,
+}
+
+impl FileSpans {
+    /// Gather the spans of files that have been resolved without errors
+    pub(crate) fn from_files(files: &[UnresolvedFile<'_>]) -> Self {
+        let mut spans = Self::default();
+        for (fidx, file) in files.iter().enumerate() {
+            for defn in file.defns.iter() {
+                let name = defn.qualified();
+                for (member, span) in defn.spans.iter() {
+                    let mut path = vec![name.as_str()];
+                    path.extend(member.iter());
+                    spans.types.insert(path.join("."), (fidx, *span));
+                }
+            }
+            for ep in file.endpoints.iter() {
+                spans.endpoints.insert(ep.name.into(), (fidx, ep.span));
+            }
+            for tp in file.topics.iter() {
+                spans.topics.insert(tp.name.into(), (fidx, tp.span));
+            }
+            for proto in file.protocols.iter() {
+                spans
+                    .protocols
+                    .insert(proto.name.into(), (fidx, proto.span));
+            }
+        }
+        spans
+    }
+}
+
+/// An import of another IDL file, e.g. `use "../common.kdl" as common`
+pub(crate) struct Import<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) alias: &'a str,
+    pub(crate) span: SourceSpan,
+}
+
+impl<'a> Import<'a> {
+    pub(crate) fn from_node(node: &'a KdlNode) -> Result<Self, Error> {
+        let what = node.name().value();
+        no_children(node, what)?;
+        let malformed = |reason: &str| Error::Malformed {
+            what: what.into(),
+            reason: reason.into(),
+            span: node.span(),
+            help: Some(format!(r#"e.g. `{what} "../common.kdl" as common`"#)),
+        };
+        let (path, alias) = match node_args(node, what)?[..] {
+            [path] => {
+                // Without an alias, use the file name
+                let path = entry_str(path, what)?;
+                let stem = std::path::Path::new(path)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .ok_or_else(|| malformed("can't name an import without a file name"))?;
+                (path, stem)
+            }
+            [path, kw, alias] if kw.value().as_string() == Some("as") => {
+                (entry_str(path, what)?, entry_str(alias, what)?)
+            }
+            _ => return Err(malformed("expected a path, and optionally `as name`")),
+        };
+        if !is_rust_ident(alias) {
+            return Err(Error::BadName {
+                name: alias.to_string(),
+                reason: "not a valid import name".into(),
+                span: node.span(),
+            });
+        }
+        Ok(Self {
+            path,
+            alias,
+            span: node.span(),
+        })
+    }
+}
+
+/// A postcard-rpc endpoint, e.g.
+/// `endpoint "GetStatus" path="status/get" req="()" resp="Status"`
+struct UnresolvedEndpoint<'a> {
+    name: &'a str,
+    path: &'a str,
+    req: UnresolvedTypeRefr<'a>,
+    resp: UnresolvedTypeRefr<'a>,
+    span: SourceSpan,
+}
+
+impl<'a> UnresolvedEndpoint<'a> {
+    fn absorb(node: &'a KdlNode) -> Result<Vec<Self>, Error> {
+        absorb_section(node, "endpoint", "`endpoint`", Self::absorb_endpoint)
+    }
+
+    fn item(&self) -> RpcItem<'a> {
+        RpcItem {
+            kind: "endpoint",
+            name: self.name,
+            path: self.path,
+            span: self.span,
+        }
+    }
+
+    fn absorb_endpoint(node: &'a KdlNode) -> Result<Self, Error> {
+        no_children(node, "endpoint")?;
+        let (args, props) = node_props(node, "endpoint", &["path", "req", "resp"])?;
+        let malformed = |reason: &str| Error::Malformed {
+            what: "endpoint".into(),
+            reason: reason.into(),
+            span: node.span(),
+            help: Some(
+                r#"e.g. `endpoint "GetStatus" path="status/get" req="()" resp="Status"`"#.into(),
+            ),
+        };
+        let [name] = args[..] else {
+            return Err(malformed("endpoint should have one string arg"));
+        };
+        let [Some(path), Some(req), Some(resp)] = props[..] else {
+            return Err(malformed("endpoint needs `path`, `req`, and `resp`"));
+        };
+        let path_str = entry_str(path, "endpoint")?;
+        if path_str.is_empty() {
+            return Err(Error::Malformed {
+                what: "endpoint".into(),
+                reason: "path must not be empty".into(),
+                span: path.span(),
+                help: None,
+            });
+        }
+        Ok(Self {
+            name: entry_str(name, "endpoint")?,
+            path: path_str,
+            req: UnresolvedTypeRefr::parse_entirely(TyStr::from_entry(req, "endpoint")?)?,
+            resp: UnresolvedTypeRefr::parse_entirely(TyStr::from_entry(resp, "endpoint")?)?,
+            span: node.span(),
+        })
+    }
+}
+
+/// A postcard-rpc topic, e.g.
+/// `topic "Telemetry" path="telem/v1" ty="TelemSample" direction="out"`
+struct UnresolvedTopic<'a> {
+    name: &'a str,
+    path: &'a str,
+    ty: UnresolvedTypeRefr<'a>,
+    direction: TopicDirection,
+    span: SourceSpan,
+}
+
+impl<'a> UnresolvedTopic<'a> {
+    fn absorb(node: &'a KdlNode) -> Result<Vec<Self>, Error> {
+        absorb_section(node, "topic", "`topic`", Self::absorb_topic)
+    }
+
+    fn item(&self) -> RpcItem<'a> {
+        RpcItem {
+            kind: "topic",
+            name: self.name,
+            path: self.path,
+            span: self.span,
+        }
+    }
+
+    fn absorb_topic(node: &'a KdlNode) -> Result<Self, Error> {
+        no_children(node, "topic")?;
+        let (args, props) = node_props(node, "topic", &["path", "ty", "direction"])?;
+        let malformed = |reason: &str| Error::Malformed {
+            what: "topic".into(),
+            reason: reason.into(),
+            span: node.span(),
+            help: Some(
+                r#"e.g. `topic "Telemetry" path="telem/v1" ty="TelemSample" direction="out"`"#
+                    .into(),
+            ),
+        };
+        let [name] = args[..] else {
+            return Err(malformed("topic should have one string arg"));
+        };
+        let [Some(path), Some(ty), Some(direction)] = props[..] else {
+            return Err(malformed("topic needs `path`, `ty`, and `direction`"));
+        };
+        let path_str = entry_str(path, "topic")?;
+        if path_str.is_empty() {
+            return Err(Error::Malformed {
+                what: "topic".into(),
+                reason: "path must not be empty".into(),
+                span: path.span(),
+                help: None,
+            });
+        }
+        let direction = match entry_str(direction, "topic")? {
+            "in" => TopicDirection::ToServer,
+            "out" => TopicDirection::ToClient,
+            _ => {
+                return Err(Error::Malformed {
+                    what: "topic".into(),
+                    reason: "unknown direction".into(),
+                    span: direction.span(),
+                    help: Some(
+                        "use `in` for client to server, or `out` for server to client".into(),
+                    ),
+                })
+            }
+        };
+        Ok(Self {
+            name: entry_str(name, "topic")?,
+            path: path_str,
+            ty: UnresolvedTypeRefr::parse_entirely(TyStr::from_entry(ty, "topic")?)?,
+            direction,
+            span: node.span(),
+        })
+    }
+}
+
+/// A named set of endpoints and topics, e.g.
+/// `protocol "FirmwareV2" { endpoints "GetStatus"; topics "Telemetry"; }`
+struct UnresolvedProtocol<'a> {
+    name: &'a str,
+    /// Endpoints by name
+    endpoints: Vec<(&'a str, SourceSpan)>,
+    /// Topics by name
+    topics: Vec<(&'a str, SourceSpan)>,
+    /// Any additional types
+    types: Vec<UnresolvedTypeRefr<'a>>,
+    span: SourceSpan,
+}
+
+impl<'a> UnresolvedProtocol<'a> {
+    fn absorb(node: &'a KdlNode) -> Result<Self, Error> {
+        let malformed = |reason: &str, span| Error::Malformed {
+            what: "protocol".into(),
+            reason: reason.into(),
+            span,
+            help: Some(
+                r#"e.g. `protocol "FirmwareV2" { endpoints "GetStatus"; topics "Telemetry"; }`"#
+                    .into(),
+            ),
+        };
+        let [name] = node_args(node, "protocol")?[..] else {
+            return Err(malformed(
+                "protocol should have one string arg",
+                node.span(),
+            ));
+        };
+        let mut proto = Self {
+            name: entry_str(name, "protocol")?,
+            endpoints: vec![],
+            topics: vec![],
+            types: vec![],
+            span: node.span(),
+        };
 
         for ch in node.iter_children() {
-            match ch.name().value() {
-                "alias" => {
-                    types.push(Self::absorb_alias(ch)?);
+            let what = ch.name().value();
+            no_children(ch, what)?;
+            let (list, kind) = match what {
+                "endpoints" => (&mut proto.endpoints, "endpoint"),
+                "topics" => (&mut proto.topics, "topic"),
+                "types" => {
+                    for ty in node_args(ch, what)? {
+                        let ty = TyStr::from_entry(ty, what)?;
+                        proto.types.push(UnresolvedTypeRefr::parse_entirely(ty)?);
+                    }
+                    continue;
                 }
-                "struct" => {
-                    types.push(Self::absorb_struct(ch)?);
+                other => {
+                    return Err(Error::UnexpectedNode {
+                        name: other.to_string(),
+                        expected: "`endpoints`, `topics`, or `types`",
+                        span: ch.name().span(),
+                    })
                 }
-                "enum" => {
-                    types.push(Self::absorb_enum(ch)?);
+            };
+            for e in node_args(ch, what)? {
+                let name = entry_str(e, what)?;
+                if let Some((_, first)) = list.iter().find(|(n, _)| *n == name) {
+                    return Err(Error::DuplicateMember {
+                        kind,
+                        name: name.to_string(),
+                        span: e.span(),
+                        first: *first,
+                    });
                 }
-                other => todo!("what: '{other}'"),
+                list.push((name, e.span()));
+            }
+        }
+
+        if proto.endpoints.is_empty() && proto.topics.is_empty() {
+            return Err(malformed(
+                "protocol has no endpoints or topics",
+                node.span(),
+            ));
+        }
+        Ok(proto)
+    }
+}
+
+/// Gather the children of a section like `endpoints`, which must all be
+/// `child` nodes
+fn absorb_section<'a, T>(
+    node: &'a KdlNode,
+    child: &str,
+    expected: &'static str,
+    absorb: impl Fn(&'a KdlNode) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    let what = node.name().value();
+    if let Some(e) = node.entries().first() {
+        return Err(Error::Malformed {
+            what: what.into(),
+            reason: "unexpected argument".into(),
+            span: e.span(),
+            help: Some(format!("`{what}` only takes a body of `{child}`s")),
+        });
+    }
+
+    let mut items = vec![];
+    for ch in node.iter_children() {
+        if ch.name().value() != child {
+            return Err(Error::UnexpectedNode {
+                name: ch.name().value().to_string(),
+                expected,
+                span: ch.name().span(),
+            });
+        }
+        items.push(absorb(ch)?);
+    }
+    Ok(items)
+}
+
+/// Get the positional arguments of a node, along with the given properties,
+/// in the order they were asked for. Any other property is rejected.
+fn node_props<'a>(
+    node: &'a KdlNode,
+    what: &str,
+    names: &[&str],
+) -> Result<(Vec<&'a KdlEntry>, Vec<Option<&'a KdlEntry>>), Error> {
+    let mut args = vec![];
+    let mut props = vec![None; names.len()];
+    for e in node.entries() {
+        let Some(prop) = e.name() else {
+            args.push(e);
+            continue;
+        };
+        let Some(idx) = names.iter().position(|n| *n == prop.value()) else {
+            let help = did_you_mean(prop.value(), names.iter().copied())
+                .unwrap_or_else(|| format!("expected one of `{}`", names.join("`, `")));
+            return Err(Error::Malformed {
+                what: what.into(),
+                reason: format!("unexpected property `{}`", prop.value()),
+                span: e.span(),
+                help: Some(help),
+            });
+        };
+        if let Some(first) = props[idx].replace(e) {
+            return Err(Error::DuplicateMember {
+                kind: "property",
+                name: prop.value().to_string(),
+                span: e.span(),
+                first: first.span(),
+            });
+        }
+    }
+    Ok((args, props))
+}
+
+/// Parse the `derive` and `attr` properties of a node, adding to the ones it
+/// inherits
+///
+/// The properties of the node must have been checked by [`node_props`].
+fn absorb_meta(node: &KdlNode, what: &str, inherited: &TypeMeta) -> Result<TypeMeta, Error> {
+    let mut meta = inherited.clone();
+    if let Some(derive) = node.entry("derive") {
+        let items = entry_str(derive, what)?.split(',').map(str::trim);
+        for item in items.filter(|i| !i.is_empty()) {
+            match parser::take_valid_rust_tyname(item) {
+                Ok((_, "")) if !item.starts_with(|c: char| c.is_ascii_digit()) => {}
+                _ => {
+                    return Err(Error::Malformed {
+                        what: what.into(),
+                        reason: format!("`{item}` is not a trait name"),
+                        span: value_span(derive),
+                        help: Some(r#"e.g. `derive="Debug, Clone, defmt::Format"`"#.into()),
+                    })
+                }
+            }
+            if !meta.derives.iter().any(|d| d == item) {
+                meta.derives.push(item.to_string());
+            }
+        }
+    }
+    if let Some(attr) = node.entry("attr") {
+        let text = entry_str(attr, what)?.trim();
+        if !(text.starts_with("#[") && text.ends_with(']')) {
+            return Err(Error::Malformed {
+                what: what.into(),
+                reason: "expected an outer attribute".into(),
+                span: value_span(attr),
+                help: Some(r##"e.g. `attr="#[repr(u8)]"`"##.into()),
+            });
+        }
+        meta.attrs.push(text.to_string());
+    }
+    Ok(meta)
+}
+
+/// Gather the `max=` and `min=` bounds of a definition and its fields or
+/// variants
+///
+/// The nodes have already been checked while absorbing the definition.
+fn absorb_bounds(node: &KdlNode) -> Result<Vec<UnresolvedBound<'_>>, Error> {
+    let mut bounds = vec![];
+    bounds.extend(absorb_bound(vec![], node)?);
+    for ch in node.iter_children() {
+        let name = ch.name().value();
+        bounds.extend(absorb_bound(vec![name], ch)?);
+        // The fields of struct variants
+        for field in ch.iter_children() {
+            bounds.extend(absorb_bound(vec![name, field.name().value()], field)?);
+        }
+    }
+    Ok(bounds)
+}
+
+/// The spans of a definition node and its members, with the paths of the
+/// members, see [`super::Pidl::bounds`]
+fn member_spans(node: &KdlNode) -> Vec<(Vec<&str>, SourceSpan)> {
+    let mut spans = vec![(vec![], node.span())];
+    for ch in node.iter_children() {
+        let name = ch.name().value();
+        spans.push((vec![name], ch.span()));
+        // The fields of struct variants
+        for field in ch.iter_children() {
+            spans.push((vec![name, field.name().value()], field.span()));
+        }
+    }
+    spans
+}
+
+/// Gather the documentation of the definition at `idx` of `siblings`, and
+/// of its fields or variants
+fn absorb_docs(siblings: &KdlDocument, idx: usize) -> Result<Vec<(Vec<&str>, String)>, Error> {
+    let node = &siblings.nodes()[idx];
+    let mut docs = vec![];
+    docs.extend(absorb_doc(siblings, idx)?.map(|doc| (vec![], doc)));
+    let Some(children) = node.children() else {
+        return Ok(docs);
+    };
+    for (idx, ch) in children.nodes().iter().enumerate() {
+        let name = ch.name().value();
+        docs.extend(absorb_doc(children, idx)?.map(|doc| (vec![name], doc)));
+        // The fields of struct variants
+        let Some(fields) = ch.children() else {
+            continue;
+        };
+        for (idx, field) in fields.nodes().iter().enumerate() {
+            let path = vec![name, field.name().value()];
+            docs.extend(absorb_doc(fields, idx)?.map(|doc| (path, doc)));
+        }
+    }
+    Ok(docs)
+}
+
+/// The documentation of the node at `idx` of `siblings`, from the `///`
+/// comments directly before it, or its `doc` property
+fn absorb_doc(siblings: &KdlDocument, idx: usize) -> Result<Option<String>, Error> {
+    let node = &siblings.nodes()[idx];
+    let prop = node.entry("doc");
+    // The whitespace before the first node of a block belongs to the block
+    let mut leading = String::new();
+    if idx == 0 {
+        leading.extend(siblings.format().map(|f| f.leading.as_str()));
+    }
+    leading.extend(node.format().map(|f| f.leading.as_str()));
+
+    let mut lines: Vec<&str> = leading
+        .lines()
+        .rev()
+        .skip_while(|line| line.trim().is_empty())
+        .map_while(|line| {
+            let line = line.trim().strip_prefix("///")?;
+            (!line.starts_with('/')).then(|| line.strip_prefix(' ').unwrap_or(line))
+        })
+        .collect();
+    lines.reverse();
+
+    match (prop, lines.is_empty()) {
+        (None, true) => Ok(None),
+        (None, false) => Ok(Some(lines.join("\n"))),
+        (Some(prop), true) => Ok(Some(entry_str(prop, "doc")?.to_string())),
+        (Some(prop), false) => Err(Error::Malformed {
+            what: "doc".into(),
+            reason: "this is already documented by `///` comments".into(),
+            span: prop.span(),
+            help: Some("use either `///` comments or `doc=`, not both".into()),
+        }),
+    }
+}
+
+/// The bounds set by the `max` and `min` properties of a node, if any
+fn absorb_bound<'a>(
+    member: Vec<&'a str>,
+    node: &KdlNode,
+) -> Result<Option<UnresolvedBound<'a>>, Error> {
+    let (max, min) = (node.entry("max"), node.entry("min"));
+    let (what, span) = match (max, min) {
+        (Some(max), _) => ("max", max.span()),
+        (None, Some(min)) => ("min", min.span()),
+        (None, None) => return Ok(None),
+    };
+    let bounds = Bounds {
+        max: max.map(|max| absorb_length(max, "max")).transpose()?,
+        min: min.map(|min| absorb_length(min, "min")).transpose()?,
+    };
+    if let (Some(max), Some(min), Some(entry)) = (bounds.max, bounds.min, min) {
+        if min > max {
+            return Err(Error::Malformed {
+                what: "min".into(),
+                reason: format!("`min` is more than `max`, which is {max}"),
+                span: entry.span(),
+                help: None,
+            });
+        }
+    }
+    Ok(Some(UnresolvedBound {
+        member,
+        bounds,
+        what,
+        span,
+    }))
+}
+
+fn absorb_length(entry: &KdlEntry, what: &str) -> Result<usize, Error> {
+    entry
+        .value()
+        .as_integer()
+        .and_then(|len| usize::try_from(len).ok())
+        .ok_or_else(|| Error::Malformed {
+            what: what.into(),
+            reason: "expected a non-negative integer".into(),
+            span: entry.span(),
+            help: Some(format!("e.g. `{what}=64`")),
+        })
+}
+
+/// Does the member of `ty` at `member` have any lengths that [`Bounds`] could
+/// limit? See [`super::Pidl::bounds`] for the paths of members.
+fn member_has_lengths(ty: &OwnedNamedType, member: &[&str]) -> bool {
+    let field = |fields: &[OwnedNamedValue], name: &str| {
+        fields
+            .iter()
+            .find(|f| f.name == name)
+            .is_some_and(|f| has_lengths(&f.ty))
+    };
+    match (&ty.ty, member) {
+        (OwnedDataModelType::NewtypeStruct(ont), []) => has_lengths(ont),
+        (OwnedDataModelType::TupleStruct(onts), []) => onts.iter().any(has_lengths),
+        (OwnedDataModelType::UnitStruct, _) => false,
+        (OwnedDataModelType::Struct(fields), [name]) => field(fields, name),
+        (OwnedDataModelType::Enum(variants), [name, rest @ ..]) => {
+            let Some(variant) = variants.iter().find(|v| v.name == *name) else {
+                return false;
+            };
+            match (&variant.ty, rest) {
+                (OwnedDataModelVariant::NewtypeVariant(ont), []) => has_lengths(ont),
+                (OwnedDataModelVariant::TupleVariant(onts), []) => onts.iter().any(has_lengths),
+                (OwnedDataModelVariant::StructVariant(fields), [name]) => field(fields, name),
+                _ => false,
             }
         }
+        (OwnedDataModelType::Struct(_) | OwnedDataModelType::Enum(_), _) => false,
+        // Aliases
+        (_, []) => has_lengths(ty),
+        _ => false,
+    }
+}
 
-        // TODO: Actually resolve types!
-        let mut rtypes = vec![];
-        resolve_types(&mut rtypes, &mut types)?;
+/// Does this type have a length, looking through options, tuples and arrays?
+fn has_lengths(ont: &OwnedNamedType) -> bool {
+    match &ont.ty {
+        OwnedDataModelType::String
+        | OwnedDataModelType::ByteArray
+        | OwnedDataModelType::Seq(_)
+        | OwnedDataModelType::Map { .. } => true,
+        OwnedDataModelType::Option(ont) => has_lengths(ont),
+        OwnedDataModelType::Tuple(onts) => onts.iter().any(has_lengths),
+        _ => false,
+    }
+}
 
-        Ok(Self { resolved: rtypes })
+/// Get the positional arguments of a node, rejecting any properties
+fn node_args<'a>(node: &'a KdlNode, what: &str) -> Result<Vec<&'a KdlEntry>, Error> {
+    let mut args = vec![];
+    for e in node.entries() {
+        if let Some(prop) = e.name() {
+            return Err(Error::Malformed {
+                what: what.into(),
+                reason: format!("unexpected property `{}`", prop.value()),
+                span: e.span(),
+                help: None,
+            });
+        }
+        args.push(e);
+    }
+    Ok(args)
+}
+
+/// Ensure that a node has no children block
+fn no_children(node: &KdlNode, what: &str) -> Result<(), Error> {
+    match node.children() {
+        Some(ch) => Err(Error::Malformed {
+            what: what.into(),
+            reason: "unexpected body".into(),
+            span: ch.span(),
+            help: None,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Get the value of an entry as a string
+fn entry_str<'a>(entry: &'a KdlEntry, what: &str) -> Result<&'a str, Error> {
+    entry.value().as_string().ok_or_else(|| Error::Malformed {
+        what: what.into(),
+        reason: "expected a string".into(),
+        span: entry.span(),
+        help: None,
+    })
+}
+
+/// Get the span of the *contents* of a string entry, without quotes or
+/// property names, so that we can point into type strings.
+fn value_span(entry: &KdlEntry) -> SourceSpan {
+    let span = entry.span();
+    let (Some(fmt), Some(s)) = (entry.format(), entry.value().as_string()) else {
+        return span;
+    };
+    let repr = &fmt.value_repr;
+    let repr_start = (span.offset() + span.len()).saturating_sub(repr.len());
+    match repr.find(s) {
+        Some(idx) if !s.is_empty() => (repr_start + idx, s.len()).into(),
+        _ => span,
+    }
+}
+
+/// Make sure `node` is the first node in `nodes` with its name
+fn check_unique(nodes: &[KdlNode], node: &KdlNode, kind: &'static str) -> Result<(), Error> {
+    let name = node.name().value();
+    let first = nodes
+        .iter()
+        .find(|n| n.name().value() == name)
+        .expect("node is in nodes");
+    if first.span() == node.span() {
+        Ok(())
+    } else {
+        Err(Error::DuplicateMember {
+            kind,
+            name: name.to_string(),
+            span: node.name().span(),
+            first: first.name().span(),
+        })
+    }
+}
+
+/// A type string from the input, along with where it lives in the source
+#[derive(Debug, Clone, Copy)]
+struct TyStr<'a> {
+    s: &'a str,
+    offset: usize,
+}
+
+impl<'a> TyStr<'a> {
+    fn from_entry(entry: &'a KdlEntry, what: &str) -> Result<Self, Error> {
+        Ok(Self {
+            s: entry_str(entry, what)?,
+            offset: value_span(entry).offset(),
+        })
+    }
+
+    /// The span of `sub`, which must be a slice of this type string
+    fn span_of(&self, sub: &str) -> SourceSpan {
+        let start = (sub.as_ptr() as usize).saturating_sub(self.s.as_ptr() as usize);
+        (self.offset + start, sub.len()).into()
+    }
+
+    /// Create an error pointing at the next token in `remain`
+    fn error(&self, remain: &str, reason: &str) -> Error {
+        let remain = remain.trim_start();
+        let token = remain
+            .char_indices()
+            .find(|(i, c)| *i != 0 && !(c.is_ascii_alphanumeric() || *c == '_'))
+            .map(|(i, _)| &remain[..i])
+            .unwrap_or(remain);
+        Error::BadTypeRefr {
+            ty: self.s.to_string(),
+            reason: reason.to_string(),
+            span: self.span_of(token),
+        }
     }
 }
 
@@ -195,6 +1235,7 @@ impl PidlTypes {
 enum UnresolvedTypeRefr<'a> {
     Name {
         name: &'a str,
+        span: SourceSpan,
     },
     Option {
         ty: Box<UnresolvedTypeRefr<'a>>,
@@ -216,33 +1257,65 @@ enum UnresolvedTypeRefr<'a> {
 }
 
 impl<'a> UnresolvedTypeRefr<'a> {
-    fn parse_entirely(s: &'a str) -> Result<Self, Error> {
-        let (me, rem) = Self::parse(s)?;
+    /// Collect all type names referenced by this type
+    fn refs(&self, indirect: bool, out: &mut Vec<TyRef<'a>>) {
+        match self {
+            UnresolvedTypeRefr::Name { name, span } => out.push(TyRef {
+                name,
+                span: *span,
+                indirect,
+            }),
+            UnresolvedTypeRefr::Option { ty } | UnresolvedTypeRefr::Seq { ty } => {
+                ty.refs(true, out)
+            }
+            UnresolvedTypeRefr::Array { ty, .. } => ty.refs(indirect, out),
+            UnresolvedTypeRefr::Map { kty, vty } => {
+                kty.refs(true, out);
+                vty.refs(true, out);
+            }
+            UnresolvedTypeRefr::Tuple { tys } => {
+                for ty in tys {
+                    ty.refs(indirect, out);
+                }
+            }
+        }
+    }
+
+    fn parse_entirely(src: TyStr<'a>) -> Result<Self, Error> {
+        let (me, rem) = Self::parse(src.s, src)?;
         if rem.trim().is_empty() {
             Ok(me)
         } else {
-            Err(Error::Invalid(rem.to_string()))
+            Err(src.error(rem, "unexpected trailing characters"))
         }
     }
 
-    fn parse(s: &'a str) -> Result<(Self, &'a str), Error> {
+    fn parse(s: &'a str, src: TyStr<'a>) -> Result<(Self, &'a str), Error> {
+        let s = s.trim_start();
         if s.starts_with('(') {
-            Self::parse_tuple(s)
+            Self::parse_tuple(s, src)
         } else if s.starts_with('[') {
-            Self::parse_seq_array(s)
+            Self::parse_seq_array(s, src)
         } else if s.starts_with("option<") {
-            Self::parse_option(s)
+            Self::parse_option(s, src)
         } else if s.starts_with("map<") {
-            Self::parse_map(s)
-        } else if let Ok((tyn, rem)) = parser::take_valid_rust_tyname(s) {
-            Ok((Self::Name { name: tyn }, rem))
+            Self::parse_map(s, src)
         } else {
-            todo!("what: {s}")
+            match parser::take_valid_rust_tyname(s) {
+                Ok((tyn, rem)) if !tyn.is_empty() => Ok((
+                    Self::Name {
+                        name: tyn,
+                        span: src.span_of(tyn),
+                    },
+                    rem,
+                )),
+                _ => Err(src.error(s, "expected a type")),
+            }
         }
     }
 
-    fn parse_tuple(s: &'a str) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
-        let mut remain = parser::take_char(s, '(').map_err(|s| Error::Invalid(s.to_string()))?;
+    fn parse_tuple(s: &'a str, src: TyStr<'a>) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
+        let mut remain = parser::take_char(s, '(').map_err(|s| src.error(s, "expected `(`"))?;
         let mut items = vec![];
         loop {
             if let Ok(rem) = parser::take_char(remain, ')') {
@@ -250,27 +1323,31 @@ impl<'a> UnresolvedTypeRefr<'a> {
                 break;
             } else if let Ok(rem) = parser::take_char(remain, ',') {
                 remain = rem;
-            } else if let Ok((ty, rem)) = Self::parse(remain) {
+            } else if remain.trim().is_empty() {
+                return Err(src.error(remain, "unclosed tuple, expected `)`"));
+            } else {
+                let (ty, rem) = Self::parse(remain, src)?;
                 remain = rem;
                 items.push(ty);
-            } else {
-                return Err(Error::Invalid(remain.to_string()));
             }
         }
         Ok((UnresolvedTypeRefr::Tuple { tys: items }, remain))
     }
 
-    fn parse_seq_array(s: &'a str) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
-        let remain = parser::take_char(s, '[').map_err(|s| Error::Invalid(s.to_string()))?;
-        let (ty, remain) = Self::parse(remain)?;
+    fn parse_seq_array(
+        s: &'a str,
+        src: TyStr<'a>,
+    ) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
+        let remain = parser::take_char(s, '[').map_err(|s| src.error(s, "expected `[`"))?;
+        let (ty, remain) = Self::parse(remain, src)?;
         if let Ok(remain) = parser::take_char(remain, ']') {
             return Ok((UnresolvedTypeRefr::Seq { ty: Box::new(ty) }, remain));
         }
         let Ok(remain) = parser::take_char(remain, ';') else {
-            return Err(Error::Invalid(remain.to_string()));
+            return Err(src.error(remain, "expected `]` or `;`"));
         };
         let Ok((ct, remain)) = parser::take_num(remain) else {
-            return Err(Error::Invalid(remain.to_string()));
+            return Err(src.error(remain, "expected an array length"));
         };
         if let Ok(remain) = parser::take_char(remain, ']') {
             Ok((
@@ -281,25 +1358,29 @@ impl<'a> UnresolvedTypeRefr<'a> {
                 remain,
             ))
         } else {
-            Err(Error::Invalid(remain.to_string()))
+            Err(src.error(remain, "expected `]`"))
         }
     }
 
-    fn parse_option(s: &'a str) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
-        let remain = parser::take_str(s, "option").map_err(|s| Error::Invalid(s.to_string()))?;
-        let remain = parser::take_char(remain, '<').map_err(|s| Error::Invalid(s.to_string()))?;
-        let (ty, remain) = Self::parse(remain)?;
-        let remain = parser::take_char(remain, '>').map_err(|s| Error::Invalid(s.to_string()))?;
+    fn parse_option(
+        s: &'a str,
+        src: TyStr<'a>,
+    ) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
+        let remain =
+            parser::take_str(s, "option").map_err(|s| src.error(s, "expected `option`"))?;
+        let remain = parser::take_char(remain, '<').map_err(|s| src.error(s, "expected `<`"))?;
+        let (ty, remain) = Self::parse(remain, src)?;
+        let remain = parser::take_char(remain, '>').map_err(|s| src.error(s, "expected `>`"))?;
         Ok((UnresolvedTypeRefr::Option { ty: Box::new(ty) }, remain))
     }
 
-    fn parse_map(s: &'a str) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
-        let remain = parser::take_str(s, "map").map_err(|s| Error::Invalid(s.to_string()))?;
-        let remain = parser::take_char(remain, '<').map_err(|s| Error::Invalid(s.to_string()))?;
-        let (kty, remain) = Self::parse(remain)?;
-        let remain = parser::take_char(remain, ',').map_err(|s| Error::Invalid(s.to_string()))?;
-        let (vty, remain) = Self::parse(remain)?;
-        let remain = parser::take_char(remain, '>').map_err(|s| Error::Invalid(s.to_string()))?;
+    fn parse_map(s: &'a str, src: TyStr<'a>) -> Result<(UnresolvedTypeRefr<'a>, &'a str), Error> {
+        let remain = parser::take_str(s, "map").map_err(|s| src.error(s, "expected `map`"))?;
+        let remain = parser::take_char(remain, '<').map_err(|s| src.error(s, "expected `<`"))?;
+        let (kty, remain) = Self::parse(remain, src)?;
+        let remain = parser::take_char(remain, ',').map_err(|s| src.error(s, "expected `,`"))?;
+        let (vty, remain) = Self::parse(remain, src)?;
+        let remain = parser::take_char(remain, '>').map_err(|s| src.error(s, "expected `>`"))?;
         Ok((
             UnresolvedTypeRefr::Map {
                 kty: Box::new(kty),
@@ -312,8 +1393,6 @@ impl<'a> UnresolvedTypeRefr<'a> {
 
 /// Simple helper methods, mostly for taking/skipping certain syntax elements
 mod parser {
-    use super::is_valid_rust_tyname;
-
     pub fn take_char(s: &str, c: char) -> Result<&str, &str> {
         let trim = s.trim_start();
         if let Some(rem) = trim.strip_prefix(c) {
@@ -347,24 +1426,64 @@ mod parser {
         trim.parse::<usize>().map_err(|_| trim).map(|n| (n, remain))
     }
 
+    /// Split off the name at the start of `s`. Whether it names a type is
+    /// only known once it is looked up.
     pub(crate) fn take_valid_rust_tyname(s: &str) -> Result<(&str, &str), &str> {
-        // wrong
         let trim = s.trim();
-        let tyn = trim.split_once(|c: char| !c.is_ascii_alphanumeric() && c != '_');
-        if let Some((st, _)) = tyn {
-            let (now, later) = trim.split_at(st.len());
-            Ok((now, later))
-        } else if is_valid_rust_tyname(trim) {
-            Ok((trim, ""))
-        } else {
-            Err(trim)
+        let ident_len = |s: &str| {
+            s.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(s.len())
+        };
+        // Names may be paths into imports, like `common::Header`
+        let mut len = ident_len(trim);
+        while len != 0 && trim[len..].starts_with("::") {
+            let seg = ident_len(&trim[len + 2..]);
+            if seg == 0 {
+                break;
+            }
+            len += 2 + seg;
         }
+        Ok(trim.split_at(len))
     }
 }
 
-fn is_valid_rust_tyname(s: &str) -> bool {
-    // this is wrong
-    s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+/// Is this a name Rust code can use as it is? Every name in the IDL ends up
+/// as an identifier in the generated code.
+pub(crate) fn is_rust_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    let first = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    first
+        && s != "_"
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !RUST_KEYWORDS.contains(&s)
+}
+
+/// The strict and reserved keywords of Rust 2021
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Check the name of a field or variant, which is the name of its node
+fn member_name_legal(node: &KdlNode, kind: &str) -> Result<(), Error> {
+    let name = node.name().value();
+    if is_rust_ident(name) {
+        return Ok(());
+    }
+    let reason = match RUST_KEYWORDS.contains(&name) {
+        true => format!("`{name}` is a Rust keyword"),
+        false => format!("not a valid {kind} name"),
+    };
+    Err(Error::BadName {
+        name: name.to_string(),
+        reason,
+        span: node.name().span(),
+    })
 }
 
 #[derive(Debug)]
@@ -414,7 +1533,7 @@ enum UnresolvedTypeDefn<'a> {
     },
 }
 
-const BUILTIN_TYPE_NAMES: &[&str] = &[
+pub(crate) const BUILTIN_TYPE_NAMES: &[&str] = &[
     "bool",
     "i8",
     "i16",
@@ -443,266 +1562,215 @@ const BUILTIN_TYPE_NAMES: &[&str] = &[
     "enum",
 ];
 
-fn resolve_ty(
-    ty: &UnresolvedTypeRefr<'_>,
-    known: &[OwnedNamedType],
-) -> Result<Option<OwnedNamedType>, Error> {
-    // println!("Resolving '{name}'");
-    match ty {
-        UnresolvedTypeRefr::Name { name } => match *name {
-            "bool" => Ok(Some(<bool as Schema>::SCHEMA.into())),
-            "i8" => Ok(Some(<i8 as Schema>::SCHEMA.into())),
-            "i16" => Ok(Some(<i16 as Schema>::SCHEMA.into())),
-            "i32" => Ok(Some(<i32 as Schema>::SCHEMA.into())),
-            "i64" => Ok(Some(<i64 as Schema>::SCHEMA.into())),
-            "i128" => Ok(Some(<i128 as Schema>::SCHEMA.into())),
-            "u8" => Ok(Some(<u8 as Schema>::SCHEMA.into())),
-            "u16" => Ok(Some(<u16 as Schema>::SCHEMA.into())),
-            "u32" => Ok(Some(<u32 as Schema>::SCHEMA.into())),
-            "u64" => Ok(Some(<u64 as Schema>::SCHEMA.into())),
-            "u128" => Ok(Some(<u128 as Schema>::SCHEMA.into())),
-            "f32" => Ok(Some(<f32 as Schema>::SCHEMA.into())),
-            "f64" => Ok(Some(<f64 as Schema>::SCHEMA.into())),
-            "char" => Ok(Some(<char as Schema>::SCHEMA.into())),
-            "string" => Ok(Some(<&str as Schema>::SCHEMA.into())),
-            "bytearray" => Ok(Some(<[u8] as Schema>::SCHEMA.into())),
-            "unit" | "()" => Ok(Some(<() as Schema>::SCHEMA.into())),
-            "option" => Err(Error::Invalid("don't do that".into())),
-            "unitstruct" => Err(Error::Invalid("don't do that".into())),
-            "newtypestruct" => Err(Error::Invalid("don't do that".into())),
-            "seq" => Err(Error::Invalid("don't do that".into())),
-            "tuple" => Err(Error::Invalid("don't do that".into())),
-            "tuplestruct" => Err(Error::Invalid("don't do that".into())),
-            "map" => Err(Error::Invalid("don't do that".into())),
-            "struct" => Err(Error::Invalid("don't do that".into())),
-            "enum" => Err(Error::Invalid("don't do that".into())),
-            _ => {
-                if looks_adhoc(name) {
-                    todo!("adhoc: '{name}'")
-                } else {
-                    Ok(known.iter().find(|ont| ont.name.as_str() == *name).cloned())
+/// A reference to a type by name, found within a definition
+#[derive(Debug, Clone, Copy)]
+struct TyRef<'a> {
+    name: &'a str,
+    span: SourceSpan,
+    /// Is this reference behind an `option`, seq, or map? These can be
+    /// used to build recursive types, as they don't always contain a value.
+    indirect: bool,
+}
+
+/// Check whether `name` may be used for a new type definition
+///
+/// `qualified` is the name including its module path, which must be unique
+fn new_tyname_legal(
+    name: &str,
+    qualified: &str,
+    span: &SourceSpan,
+    known: &[String],
+) -> Result<(), Error> {
+    let reason = if BUILTIN_TYPE_NAMES.contains(&name) {
+        // does this name alias a builtin?
+        "shadows a builtin type"
+    } else if known.iter().any(|k| k == qualified) {
+        // does this name alias an existing type?
+        "a type with this name already exists"
+    } else if !is_rust_ident(name) {
+        "not a valid type name"
+    } else {
+        return Ok(());
+    };
+    Err(Error::BadName {
+        name: name.to_string(),
+        reason: reason.to_string(),
+        span: *span,
+    })
+}
+
+impl<'a> UnresolvedTypeDefn<'a> {
+    fn name(&self) -> &'a str {
+        match self {
+            UnresolvedTypeDefn::Alias { name, .. }
+            | UnresolvedTypeDefn::UnitStruct { name, .. }
+            | UnresolvedTypeDefn::NewTypeTupleStruct { name, .. }
+            | UnresolvedTypeDefn::Struct { name, .. }
+            | UnresolvedTypeDefn::Enum { name, .. } => name,
+        }
+    }
+
+    /// All type names referenced by this definition
+    fn refs(&self) -> Vec<TyRef<'a>> {
+        let mut out = vec![];
+        match self {
+            UnresolvedTypeDefn::UnitStruct { .. } => {}
+            UnresolvedTypeDefn::Alias { ty, .. }
+            | UnresolvedTypeDefn::NewTypeTupleStruct { ty, .. } => ty.refs(false, &mut out),
+            UnresolvedTypeDefn::Struct { fields, .. } => {
+                for (_, ty) in fields {
+                    ty.refs(false, &mut out);
                 }
             }
-        },
-        UnresolvedTypeRefr::Option { ty } => match resolve_ty(ty, known)? {
-            Some(t) => Ok(Some(OwnedNamedType {
-                name: format!("Option<{}>", t.name),
-                ty: OwnedDataModelType::Option(Box::new(t)),
-            })),
-            None => Ok(None),
-        },
-        UnresolvedTypeRefr::Seq { ty } => match resolve_ty(ty, known)? {
-            Some(t) => Ok(Some(OwnedNamedType {
-                name: format!("[{}]", t.name),
-                ty: OwnedDataModelType::Seq(Box::new(t)),
-            })),
-            None => Ok(None),
-        },
-        UnresolvedTypeRefr::Array { ty, ct } => match resolve_ty(ty, known)? {
-            Some(t) => Ok(Some(OwnedNamedType {
-                name: format!("[{}; {ct}]", t.name),
-                ty: OwnedDataModelType::Tuple({
-                    let mut v = vec![];
-                    for _ in 0..*ct {
-                        v.push(t.clone());
+            UnresolvedTypeDefn::Enum { variants, .. } => {
+                for var in variants {
+                    match var {
+                        UnresolvedEnumVariant::Unit { .. } => {}
+                        UnresolvedEnumVariant::NewType { ty, .. } => ty.refs(false, &mut out),
+                        UnresolvedEnumVariant::Tuple { fields, .. } => {
+                            for ty in fields {
+                                ty.refs(false, &mut out);
+                            }
+                        }
+                        UnresolvedEnumVariant::Struct { fields, .. } => {
+                            for (_, ty) in fields {
+                                ty.refs(false, &mut out);
+                            }
+                        }
                     }
-                    v
-                }),
-            })),
-            None => Ok(None),
-        },
-        UnresolvedTypeRefr::Map { kty, vty } => {
-            let a = resolve_ty(kty, known)?;
-            let b = resolve_ty(vty, known)?;
-            let (Some(k), Some(v)) = (a, b) else {
-                return Ok(None);
-            };
-            Ok(Some(OwnedNamedType {
-                name: format!("Map<{}, {}>", k.name, v.name),
-                ty: OwnedDataModelType::Map {
-                    key: Box::new(k),
-                    val: Box::new(v),
-                },
-            }))
-        }
-        UnresolvedTypeRefr::Tuple { tys } => {
-            let mut ts = vec![];
-            let mut names = vec![];
-            for t in tys {
-                let t = resolve_ty(t, known)?;
-                let Some(t) = t else {
-                    return Ok(None);
-                };
-                names.push(t.name.clone());
-                ts.push(t);
+                }
             }
-            let joined = names.join(", ");
-            Ok(Some(OwnedNamedType {
-                name: format!("({joined})"),
-                ty: OwnedDataModelType::Tuple(ts),
-            }))
         }
+        out
     }
-}
-
-fn looks_adhoc(name: &str) -> bool {
-    name.starts_with('(')
-        || name.starts_with("option<")
-        || name.starts_with('[')
-        || name.starts_with("map<")
-}
 
-fn new_tyname_legal(name: &str, known: &[OwnedNamedType]) -> bool {
-    // does this name alias a builtin?
-    if BUILTIN_TYPE_NAMES.contains(&name) {
-        return false;
+    fn span(&self) -> SourceSpan {
+        match self {
+            UnresolvedTypeDefn::Alias { span, .. }
+            | UnresolvedTypeDefn::UnitStruct { span, .. }
+            | UnresolvedTypeDefn::NewTypeTupleStruct { span, .. }
+            | UnresolvedTypeDefn::Struct { span, .. }
+            | UnresolvedTypeDefn::Enum { span, .. } => *span,
+        }
     }
-    // does this name alias an existing type?
-    if known.iter().any(|ont| ont.name.as_str() == name) {
-        return false;
+
+    /// Is this definition a new (nominal) type, rather than an alias?
+    fn is_nominal(&self) -> bool {
+        !matches!(self, UnresolvedTypeDefn::Alias { .. })
     }
-    // TODO: valid Rust type name check
-    true
-}
 
-impl UnresolvedTypeDefn<'_> {
-    fn resolve(&self, known: &[OwnedNamedType]) -> Result<Option<OwnedNamedType>, Error> {
-        match self {
-            UnresolvedTypeDefn::Alias { name, ty, span } => {
-                Self::resolve_alias(name, ty, span, known)
+    /// The placeholder used when this type refers back to itself.
+    ///
+    /// The stub has the (qualified) name of the type, and an empty body.
+    fn stub(&self, name: &str) -> OwnedNamedType {
+        let ty = match self {
+            UnresolvedTypeDefn::Alias { .. } | UnresolvedTypeDefn::UnitStruct { .. } => {
+                unreachable!("only recursive nominal types are stubbed")
             }
-            UnresolvedTypeDefn::Struct { name, fields, span } => {
-                Self::resolve_struct(name, fields, span, known)
+            UnresolvedTypeDefn::NewTypeTupleStruct { .. } => {
+                OwnedDataModelType::TupleStruct(vec![])
             }
-            UnresolvedTypeDefn::UnitStruct { name, span } => {
-                Self::resolve_unitstruct(name, span, known)
-            }
-            UnresolvedTypeDefn::NewTypeTupleStruct { name, ty, span } => {
-                Self::resolve_newtype_tuple_struct(name, ty, span, known)
+            UnresolvedTypeDefn::Struct { .. } => OwnedDataModelType::Struct(vec![]),
+            UnresolvedTypeDefn::Enum { .. } => OwnedDataModelType::Enum(vec![]),
+        };
+        OwnedNamedType {
+            name: name.to_string(),
+            ty,
+        }
+    }
+
+    /// Resolve this definition, giving it the (qualified) `name`
+    fn resolve(&self, name: &str, rs: &mut Resolver<'a, '_>) -> Result<OwnedNamedType, Error> {
+        match self {
+            UnresolvedTypeDefn::Alias { ty, .. } => Self::resolve_alias(name, ty, rs),
+            UnresolvedTypeDefn::Struct { fields, .. } => Self::resolve_struct(name, fields, rs),
+            UnresolvedTypeDefn::UnitStruct { .. } => Ok(Self::resolve_unitstruct(name)),
+            UnresolvedTypeDefn::NewTypeTupleStruct { ty, .. } => {
+                Self::resolve_newtype_tuple_struct(name, ty, rs)
             }
-            UnresolvedTypeDefn::Enum {
-                name,
-                variants,
-                span,
-            } => Self::resolve_enum(name, variants, span, known),
+            UnresolvedTypeDefn::Enum { variants, .. } => Self::resolve_enum(name, variants, rs),
         }
     }
 
     fn resolve_alias(
         name: &str,
-        ty: &UnresolvedTypeRefr<'_>,
-        _span: &SourceSpan,
-        known: &[OwnedNamedType],
-    ) -> Result<Option<OwnedNamedType>, Error> {
-        // Is the name illegal?
-        if !new_tyname_legal(name, known) {
-            return Err(Error::BadName(name.to_string()));
-        }
-        match resolve_ty(ty, known) {
-            Ok(Some(t)) => Ok(Some(OwnedNamedType {
+        ty: &UnresolvedTypeRefr<'a>,
+        rs: &mut Resolver<'a, '_>,
+    ) -> Result<OwnedNamedType, Error> {
+        let t = rs.resolve_ty(ty)?;
+        match t.ty {
+            // Like `pub type`, an alias of a user type is that same type, so
+            // references to the alias are references to the type itself
+            OwnedDataModelType::UnitStruct
+            | OwnedDataModelType::NewtypeStruct(_)
+            | OwnedDataModelType::TupleStruct(_)
+            | OwnedDataModelType::Struct(_)
+            | OwnedDataModelType::Enum(_) => Ok(t),
+            ty => Ok(OwnedNamedType {
                 name: name.to_string(),
-                ty: t.ty,
-            })),
-            Ok(None) => Ok(None),
-            Err(e) => Err(e),
+                ty,
+            }),
         }
     }
 
-    fn resolve_unitstruct(
-        name: &str,
-        _span: &SourceSpan,
-        known: &[OwnedNamedType],
-    ) -> Result<Option<OwnedNamedType>, Error> {
-        if !new_tyname_legal(name, known) {
-            return Err(Error::BadName(name.to_string()));
-        }
-        Ok(Some(OwnedNamedType {
+    fn resolve_unitstruct(name: &str) -> OwnedNamedType {
+        OwnedNamedType {
             name: name.to_string(),
             ty: OwnedDataModelType::UnitStruct,
-        }))
+        }
     }
 
     fn resolve_newtype_tuple_struct(
         name: &str,
-        ty: &UnresolvedTypeRefr<'_>,
-        _span: &SourceSpan,
-        known: &[OwnedNamedType],
-    ) -> Result<Option<OwnedNamedType>, Error> {
-        if !new_tyname_legal(name, known) {
-            return Err(Error::BadName(name.to_string()));
-        }
-        let t = match resolve_ty(ty, known) {
-            Ok(Some(t)) => t,
-            Ok(None) => return Ok(None),
-            Err(_) => todo!(),
-        };
-
-        if let OwnedNamedType {
-            name: tname,
-            ty: OwnedDataModelType::Tuple(t),
-        } = t
-        {
-            Ok(Some(OwnedNamedType {
-                name: format!("{name}{tname}"),
-                ty: OwnedDataModelType::TupleStruct(t),
-            }))
-        } else {
-            Ok(Some(OwnedNamedType {
-                name: format!("{name}({})", t.name),
-                ty: OwnedDataModelType::NewtypeStruct(Box::new(t)),
-            }))
+        ty: &UnresolvedTypeRefr<'a>,
+        rs: &mut Resolver<'a, '_>,
+    ) -> Result<OwnedNamedType, Error> {
+        // Like serde, only tuples with more than one item are tuple structs,
+        // anything else (including named tuple aliases) is a newtype.
+        let t = rs.resolve_ty(ty)?;
+        match (ty, t.ty) {
+            (UnresolvedTypeRefr::Tuple { tys }, OwnedDataModelType::Tuple(t)) if tys.len() > 1 => {
+                Ok(OwnedNamedType {
+                    name: name.to_string(),
+                    ty: OwnedDataModelType::TupleStruct(t),
+                })
+            }
+            (_, inner) => Ok(OwnedNamedType {
+                name: name.to_string(),
+                ty: OwnedDataModelType::NewtypeStruct(Box::new(OwnedNamedType {
+                    name: t.name,
+                    ty: inner,
+                })),
+            }),
         }
     }
 
     fn resolve_struct(
         name: &str,
-        fields: &[(&str, UnresolvedTypeRefr<'_>)],
-        _span: &SourceSpan,
-        known: &[OwnedNamedType],
-    ) -> Result<Option<OwnedNamedType>, Error> {
-        if !new_tyname_legal(name, known) {
-            return Err(Error::BadName(name.to_string()));
-        }
+        fields: &[(&str, UnresolvedTypeRefr<'a>)],
+        rs: &mut Resolver<'a, '_>,
+    ) -> Result<OwnedNamedType, Error> {
         let mut rfields = vec![];
         for (fname, fty) in fields {
-            // todo: check field name legal
-            match resolve_ty(fty, known) {
-                Ok(Some(t)) => {
-                    rfields.push(OwnedNamedValue {
-                        name: fname.to_string(),
-                        ty: t,
-                    });
-                }
-                Ok(None) => return Ok(None),
-                Err(_) => todo!(),
-            }
+            rfields.push(OwnedNamedValue {
+                name: fname.to_string(),
+                ty: rs.resolve_ty(fty)?,
+            });
         }
 
-        // TODO: make sure there are no dupes in field names
-
-        if rfields.is_empty() {
-            todo!("We should have caught this earlier")
-            // Ok(Some(OwnedNamedType {
-            //     name: name.to_string(),
-            //     ty: OwnedDataModelType::UnitStruct,
-            // }))
-        } else {
-            Ok(Some(OwnedNamedType {
-                name: name.to_string(),
-                ty: OwnedDataModelType::Struct(rfields),
-            }))
-        }
+        // Empty structs, and bad or duplicate field names, are rejected when
+        // absorbing
+        Ok(OwnedNamedType {
+            name: name.to_string(),
+            ty: OwnedDataModelType::Struct(rfields),
+        })
     }
 
     fn resolve_enum(
         name: &str,
-        variants: &[UnresolvedEnumVariant<'_>],
-        _span: &SourceSpan,
-        known: &[OwnedNamedType],
-    ) -> Result<Option<OwnedNamedType>, Error> {
-        if !new_tyname_legal(name, known) {
-            return Err(Error::BadName(name.to_string()));
-        }
+        variants: &[UnresolvedEnumVariant<'a>],
+        rs: &mut Resolver<'a, '_>,
+    ) -> Result<OwnedNamedType, Error> {
         let mut rvars = vec![];
         for var in variants {
             match var {
@@ -713,9 +1781,7 @@ impl UnresolvedTypeDefn<'_> {
                     });
                 }
                 UnresolvedEnumVariant::NewType { name, ty } => {
-                    let Some(t) = resolve_ty(ty, known)? else {
-                        return Ok(None);
-                    };
+                    let t = rs.resolve_ty(ty)?;
                     rvars.push(OwnedNamedVariant {
                         name: name.to_string(),
                         ty: OwnedDataModelVariant::NewtypeVariant(Box::new(t)),
@@ -724,10 +1790,7 @@ impl UnresolvedTypeDefn<'_> {
                 UnresolvedEnumVariant::Tuple { name, fields } => {
                     let mut rfields = vec![];
                     for f in fields {
-                        let Some(t) = resolve_ty(f, known)? else {
-                            return Ok(None);
-                        };
-                        rfields.push(t);
+                        rfields.push(rs.resolve_ty(f)?);
                     }
                     rvars.push(OwnedNamedVariant {
                         name: name.to_string(),
@@ -737,12 +1800,9 @@ impl UnresolvedTypeDefn<'_> {
                 UnresolvedEnumVariant::Struct { name, fields } => {
                     let mut rfields = vec![];
                     for (n, ty) in fields {
-                        let Some(t) = resolve_ty(ty, known)? else {
-                            return Ok(None);
-                        };
                         rfields.push(OwnedNamedValue {
                             name: n.to_string(),
-                            ty: t,
+                            ty: rs.resolve_ty(ty)?,
                         });
                     }
                     rvars.push(OwnedNamedVariant {
@@ -752,33 +1812,975 @@ impl UnresolvedTypeDefn<'_> {
                 }
             }
         }
-        Ok(Some(OwnedNamedType {
+        Ok(OwnedNamedType {
             name: name.to_string(),
             ty: OwnedDataModelType::Enum(rvars),
-        }))
+        })
+    }
+}
+
+/// Resolves definitions into [`OwnedNamedType`]s, depth first.
+///
+/// Since `OwnedNamedType`s are trees, recursive types can't be fully
+/// represented. When a type refers back to a type that is currently being
+/// resolved, a "stub" with just the name of that type (and an empty body)
+/// is used instead. The full definition can be found by name.
+///
+/// Definitions are only resolved once, as types that are used in many
+/// places would otherwise be resolved once per path to them. What a
+/// definition resolves to only depends on the definitions being resolved
+/// around it if it contains a stub, so only stub-free results are kept.
+struct Resolver<'a, 'b> {
+    files: &'b [UnresolvedFile<'a>],
+    /// The file whose definition is currently being resolved
+    file: usize,
+    /// The module of the definition currently being resolved
+    module: &'b [&'a str],
+    /// Nominal types currently being resolved
+    stack: Vec<DefnId>,
+    /// Definitions that have been resolved without any stubs
+    done: HashMap<DefnId, OwnedNamedType>,
+    /// How many stubs have been made so far
+    stubs: usize,
+}
+
+/// A definition, as `(file index, definition index)`
+type DefnId = (usize, usize);
+
+/// Find the definition `name` refers to, as seen from `module` of `file`.
+///
+/// Like lexical scoping, names are first looked up in the module itself, and
+/// then in each of its parents, up to the root of the file.
+fn lookup(
+    files: &[UnresolvedFile<'_>],
+    file: usize,
+    module: &[&str],
+    name: &str,
+) -> Option<DefnId> {
+    (0..=module.len())
+        .rev()
+        .find_map(|depth| lookup_in(files, file, &module[..depth], name))
+}
+
+/// Find the definition `name` refers to, only within `module` of `file`.
+///
+/// `name` may start with `super::` to refer to the parent module.
+fn lookup_in(
+    files: &[UnresolvedFile<'_>],
+    file: usize,
+    module: &[&str],
+    name: &str,
+) -> Option<DefnId> {
+    if let Some((prefix, rest)) = name.split_once("::") {
+        if prefix == "super" {
+            let (_, parent) = module.split_last()?;
+            return lookup_in(files, file, parent, rest);
+        }
+        let mut inner = module.to_vec();
+        inner.push(prefix);
+        if files[file].has_module(&inner) {
+            lookup_in(files, file, &inner, rest)
+        } else if module.is_empty() {
+            let (_, imported) = files[file].imports.iter().find(|(a, _)| *a == prefix)?;
+            lookup_in(files, *imported, &[], rest)
+        } else {
+            None
+        }
+    } else {
+        let idx = files[file]
+            .defns
+            .iter()
+            .position(|d| d.module == module && d.defn.name() == name)?;
+        Some((file, idx))
+    }
+}
+
+/// All names that can be referenced from `module` of `file`
+fn visible_names(files: &[UnresolvedFile<'_>], file: usize, module: &[&str]) -> Vec<String> {
+    let mut names = vec![];
+    for d in files[file].defns.iter() {
+        // Use the shortest path, from the innermost enclosing module
+        let Some(depth) = (0..=module.len())
+            .rev()
+            .find(|depth| d.module.starts_with(&module[..*depth]))
+        else {
+            continue;
+        };
+        let mut path: Vec<&str> = d.module[depth..].to_vec();
+        path.push(d.defn.name());
+        names.push(path.join("::"));
     }
+    for (alias, imported) in files[file].imports.iter() {
+        for name in visible_names(files, *imported, &[]) {
+            names.push(format!("{alias}::{name}"));
+        }
+    }
+    names
 }
 
+impl<'a, 'b> Resolver<'a, 'b> {
+    /// Resolve type references as seen from the root module of `file`
+    fn new(files: &'b [UnresolvedFile<'a>], file: usize) -> Self {
+        Self {
+            files,
+            file,
+            module: &[],
+            stack: vec![],
+            done: HashMap::new(),
+            stubs: 0,
+        }
+    }
+
+    fn resolve_defn(&mut self, id: DefnId) -> Result<OwnedNamedType, Error> {
+        if let Some(done) = self.done.get(&id) {
+            return Ok(done.clone());
+        }
+        let files: &'b [UnresolvedFile<'a>] = self.files;
+        let scoped = &files[id.0].defns[id.1];
+        let defn = &scoped.defn;
+        if defn.is_nominal() {
+            if self.stack.contains(&id) {
+                self.stubs += 1;
+                return Ok(defn.stub(&scoped.qualified()));
+            }
+            self.stack.push(id);
+        }
+        let stubs = self.stubs;
+        let file = core::mem::replace(&mut self.file, id.0);
+        let module = core::mem::replace(&mut self.module, &scoped.module);
+        let res = defn.resolve(&scoped.qualified(), self);
+        self.file = file;
+        self.module = module;
+        if defn.is_nominal() {
+            self.stack.pop();
+        }
+        if let Ok(ty) = &res {
+            if self.stubs == stubs {
+                self.done.insert(id, ty.clone());
+            }
+        }
+        res
+    }
+
+    fn resolve_ty(&mut self, ty: &UnresolvedTypeRefr<'a>) -> Result<OwnedNamedType, Error> {
+        match ty {
+            UnresolvedTypeRefr::Name { name, span } => match *name {
+                "bool" => Ok(<bool as Schema>::SCHEMA.into()),
+                "i8" => Ok(<i8 as Schema>::SCHEMA.into()),
+                "i16" => Ok(<i16 as Schema>::SCHEMA.into()),
+                "i32" => Ok(<i32 as Schema>::SCHEMA.into()),
+                "i64" => Ok(<i64 as Schema>::SCHEMA.into()),
+                "i128" => Ok(<i128 as Schema>::SCHEMA.into()),
+                "u8" => Ok(<u8 as Schema>::SCHEMA.into()),
+                "u16" => Ok(<u16 as Schema>::SCHEMA.into()),
+                "u32" => Ok(<u32 as Schema>::SCHEMA.into()),
+                "u64" => Ok(<u64 as Schema>::SCHEMA.into()),
+                "u128" => Ok(<u128 as Schema>::SCHEMA.into()),
+                "f32" => Ok(<f32 as Schema>::SCHEMA.into()),
+                "f64" => Ok(<f64 as Schema>::SCHEMA.into()),
+                "char" => Ok(<char as Schema>::SCHEMA.into()),
+                "string" => Ok(<&str as Schema>::SCHEMA.into()),
+                "bytearray" => Ok(<[u8] as Schema>::SCHEMA.into()),
+                "unit" | "()" => Ok(<() as Schema>::SCHEMA.into()),
+                "option" | "unitstruct" | "newtypestruct" | "seq" | "tuple" | "tuplestruct"
+                | "map" | "struct" | "enum" => Err(Error::BadTypeRefr {
+                    ty: name.to_string(),
+                    reason: format!("`{name}` is a reserved name, not a type"),
+                    span: *span,
+                }),
+                _ => {
+                    let id = lookup(self.files, self.file, self.module, name)
+                        .expect("references are checked before resolving");
+                    self.resolve_defn(id)
+                }
+            },
+            UnresolvedTypeRefr::Option { ty } => {
+                let t = self.resolve_ty(ty)?;
+                Ok(OwnedNamedType {
+                    name: format!("Option<{}>", t.name),
+                    ty: OwnedDataModelType::Option(Box::new(t)),
+                })
+            }
+            UnresolvedTypeRefr::Seq { ty } => {
+                let t = self.resolve_ty(ty)?;
+                Ok(OwnedNamedType {
+                    name: format!("[{}]", t.name),
+                    ty: OwnedDataModelType::Seq(Box::new(t)),
+                })
+            }
+            UnresolvedTypeRefr::Array { ty, ct } => {
+                let t = self.resolve_ty(ty)?;
+                Ok(OwnedNamedType {
+                    name: format!("[{}; {ct}]", t.name),
+                    ty: OwnedDataModelType::Tuple({
+                        let mut v = vec![];
+                        for _ in 0..*ct {
+                            v.push(t.clone());
+                        }
+                        v
+                    }),
+                })
+            }
+            UnresolvedTypeRefr::Map { kty, vty } => {
+                let k = self.resolve_ty(kty)?;
+                let v = self.resolve_ty(vty)?;
+                Ok(OwnedNamedType {
+                    name: format!("Map<{}, {}>", k.name, v.name),
+                    ty: OwnedDataModelType::Map {
+                        key: Box::new(k),
+                        val: Box::new(v),
+                    },
+                })
+            }
+            UnresolvedTypeRefr::Tuple { tys } if tys.is_empty() => {
+                Ok(<() as Schema>::SCHEMA.into())
+            }
+            UnresolvedTypeRefr::Tuple { tys } => {
+                let mut ts = vec![];
+                let mut names = vec![];
+                for t in tys {
+                    let t = self.resolve_ty(t)?;
+                    names.push(t.name.clone());
+                    ts.push(t);
+                }
+                let joined = names.join(", ");
+                Ok(OwnedNamedType {
+                    name: format!("({joined})"),
+                    ty: OwnedDataModelType::Tuple(ts),
+                })
+            }
+        }
+    }
+}
+
+/// Resolve every definition, in order, along with the user types that
+/// aliases stand for, see [`PidlTypes::aliases`]
 fn resolve_types(
-    known: &mut Vec<OwnedNamedType>,
-    unknown: &mut Vec<UnresolvedTypeDefn<'_>>,
+    files: &[UnresolvedFile<'_>],
+) -> FileResult<(Vec<OwnedNamedType>, BTreeMap<String, String>)> {
+    // Qualified names include the module and import path, so the same name
+    // may be used in different modules and files
+    let mut names = vec![];
+    for (fidx, file) in files.iter().enumerate() {
+        for scoped in file.defns.iter() {
+            let defn = &scoped.defn;
+            let qualified = scoped.qualified();
+            new_tyname_legal(defn.name(), &qualified, &defn.span(), &names)
+                .map_err(|e| (fidx, Box::new(e)))?;
+            names.push(qualified);
+        }
+    }
+
+    for fidx in 0..files.len() {
+        check_references(files, fidx).map_err(|e| (fidx, Box::new(e)))?;
+    }
+
+    let mut rs = Resolver::new(files, 0);
+    let mut resolved = vec![];
+    let mut aliases = BTreeMap::new();
+    for (fidx, file) in files.iter().enumerate() {
+        for (idx, scoped) in file.defns.iter().enumerate() {
+            let mut ty = rs
+                .resolve_defn((fidx, idx))
+                .map_err(|e| (fidx, Box::new(e)))?;
+            // The definition of an alias of a user type keeps its own name
+            let qualified = scoped.qualified();
+            if ty.name != qualified {
+                aliases.insert(
+                    qualified.clone(),
+                    core::mem::replace(&mut ty.name, qualified),
+                );
+            }
+            resolved.push(ty);
+        }
+    }
+    Ok((resolved, aliases))
+}
+
+/// Resolve the endpoints and topics of all files.
+///
+/// Type references in these are resolved from the root module of the file
+/// they are declared in. Must only be called once all types have been
+/// checked by [`resolve_types`].
+pub(crate) fn resolve_rpc(files: &[UnresolvedFile<'_>]) -> FileResult<(Vec<Endpoint>, Vec<Topic>)> {
+    let mut seen: Vec<(usize, RpcItem<'_>)> = vec![];
+    let mut endpoints = vec![];
+    let mut topics = vec![];
+    // The generated items of endpoints and topics live next to these
+    let root_types: Vec<String> = files
+        .iter()
+        .flat_map(|f| f.defns.iter())
+        .map(|d| d.qualified())
+        .filter(|name| !name.contains("::"))
+        .collect();
+    for (fidx, file) in files.iter().enumerate() {
+        let fail = |e| (fidx, Box::new(e));
+        let mut errors = vec![];
+        let items = file
+            .endpoints
+            .iter()
+            .map(|ep| {
+                let mut refs = vec![];
+                ep.req.refs(false, &mut refs);
+                ep.resp.refs(false, &mut refs);
+                (ep.item(), refs)
+            })
+            .chain(file.topics.iter().map(|tp| {
+                let mut refs = vec![];
+                tp.ty.refs(false, &mut refs);
+                (tp.item(), refs)
+            }));
+        for (item, refs) in items {
+            new_rpc_item_legal(fidx, &item, &seen, &root_types).map_err(fail)?;
+            for TyRef { name, span, .. } in refs {
+                if BUILTIN_TYPE_NAMES.contains(&name) || lookup(files, fidx, &[], name).is_some() {
+                    continue;
+                }
+                let visible = visible_names(files, fidx, &[]);
+                let candidates = visible
+                    .iter()
+                    .map(String::as_str)
+                    .chain(BUILTIN_TYPE_NAMES.iter().copied());
+                errors.push(Error::UnknownType {
+                    name: name.to_string(),
+                    defn: item.name.to_string(),
+                    span,
+                    defn_span: item.span,
+                    help: did_you_mean(name, candidates),
+                });
+            }
+            seen.push((fidx, item));
+        }
+        if !errors.is_empty() {
+            return Err(fail(Error::Unresolvable {
+                count: errors.len(),
+                errors,
+            }));
+        }
+
+        let mut rs = Resolver::new(files, fidx);
+        // Messages need a `Schema`, which recursive types don't have
+        let mut message = |item: RpcItem<'_>, ty| {
+            let ty = rs.resolve_ty(ty).map_err(fail)?;
+            match super::find_stub(&ty) {
+                Some(stub) => Err(fail(Error::RecursiveMessage {
+                    kind: item.kind,
+                    name: item.name.to_string(),
+                    ty: stub.name.clone(),
+                    span: item.span,
+                })),
+                None => Ok(ty),
+            }
+        };
+        for ep in file.endpoints.iter() {
+            endpoints.push(Endpoint {
+                name: ep.name.to_string(),
+                path: ep.path.to_string(),
+                req: message(ep.item(), &ep.req)?,
+                resp: message(ep.item(), &ep.resp)?,
+            });
+        }
+        for tp in file.topics.iter() {
+            topics.push(Topic {
+                name: tp.name.to_string(),
+                path: tp.path.to_string(),
+                ty: message(tp.item(), &tp.ty)?,
+                direction: tp.direction,
+            });
+        }
+    }
+    Ok((endpoints, topics))
+}
+
+/// Resolve the protocols of all files, given all resolved endpoints and
+/// topics.
+///
+/// Endpoints and topics are referred to by name, which are unique across
+/// all files. Must only be called once all types have been checked by
+/// [`resolve_types`].
+pub(crate) fn resolve_protocols(
+    files: &[UnresolvedFile<'_>],
+    endpoints: &[Endpoint],
+    topics: &[Topic],
+) -> FileResult<Vec<Protocol>> {
+    let mut seen: Vec<(usize, &UnresolvedProtocol<'_>)> = vec![];
+    let mut protocols = vec![];
+    // The modules generated for protocols live next to these
+    let root_modules: Vec<String> = files
+        .iter()
+        .flat_map(|f| f.defns.iter())
+        .filter_map(|d| Some(d.qualified().split_once("::")?.0.to_string()))
+        .collect();
+    for (fidx, file) in files.iter().enumerate() {
+        let fail = |e| (fidx, Box::new(e));
+        for proto in file.protocols.iter() {
+            new_protocol_legal(fidx, proto, &seen, &root_modules).map_err(fail)?;
+            seen.push((fidx, proto));
+
+            let mut errors = vec![];
+            let mut find = |kind: &'static str, names: &[&str], name: &str, span| {
+                let found = names.iter().position(|n| *n == name);
+                if found.is_none() {
+                    errors.push(Error::UnknownItem {
+                        kind,
+                        name: name.to_string(),
+                        span,
+                        help: did_you_mean(name, names.iter().copied()),
+                    });
+                }
+                found
+            };
+            let ep_names: Vec<&str> = endpoints.iter().map(|e| e.name.as_str()).collect();
+            let tp_names: Vec<&str> = topics.iter().map(|t| t.name.as_str()).collect();
+            let proto_eps: Vec<Endpoint> = proto
+                .endpoints
+                .iter()
+                .filter_map(|(name, span)| find("endpoint", &ep_names, name, *span))
+                .map(|idx| endpoints[idx].clone())
+                .collect();
+            let proto_tps: Vec<Topic> = proto
+                .topics
+                .iter()
+                .filter_map(|(name, span)| find("topic", &tp_names, name, *span))
+                .map(|idx| topics[idx].clone())
+                .collect();
+
+            let mut refs = vec![];
+            for ty in proto.types.iter() {
+                ty.refs(false, &mut refs);
+            }
+            for TyRef { name, span, .. } in refs {
+                if BUILTIN_TYPE_NAMES.contains(&name) || lookup(files, fidx, &[], name).is_some() {
+                    continue;
+                }
+                let visible = visible_names(files, fidx, &[]);
+                let candidates = visible
+                    .iter()
+                    .map(String::as_str)
+                    .chain(BUILTIN_TYPE_NAMES.iter().copied());
+                errors.push(Error::UnknownType {
+                    name: name.to_string(),
+                    defn: proto.name.to_string(),
+                    span,
+                    defn_span: proto.span,
+                    help: did_you_mean(name, candidates),
+                });
+            }
+            if !errors.is_empty() {
+                return Err(fail(Error::Unresolvable {
+                    count: errors.len(),
+                    errors,
+                }));
+            }
+
+            let mut rs = Resolver::new(files, fidx);
+            let mut roots = vec![];
+            for ep in proto_eps.iter() {
+                roots.push(ep.req.clone());
+                roots.push(ep.resp.clone());
+            }
+            for tp in proto_tps.iter() {
+                roots.push(tp.ty.clone());
+            }
+            for ty in proto.types.iter() {
+                roots.push(rs.resolve_ty(ty).map_err(fail)?);
+            }
+            let mut types = vec![];
+            for ty in roots.iter() {
+                used_types(ty, &mut types);
+            }
+
+            protocols.push(Protocol {
+                name: proto.name.to_string(),
+                endpoints: proto_eps,
+                topics: proto_tps,
+                types,
+            });
+        }
+    }
+    Ok(protocols)
+}
+
+/// Collect `ont` and every type it contains, without duplicates
+fn used_types(ont: &OwnedNamedType, out: &mut Vec<OwnedNamedType>) {
+    if out.contains(ont) {
+        return;
+    }
+    out.push(ont.clone());
+    match &ont.ty {
+        OwnedDataModelType::Option(t)
+        | OwnedDataModelType::NewtypeStruct(t)
+        | OwnedDataModelType::Seq(t) => used_types(t, out),
+        OwnedDataModelType::Tuple(ts) | OwnedDataModelType::TupleStruct(ts) => {
+            ts.iter().for_each(|t| used_types(t, out))
+        }
+        OwnedDataModelType::Map { key, val } => {
+            used_types(key, out);
+            used_types(val, out);
+        }
+        OwnedDataModelType::Struct(fields) => fields.iter().for_each(|f| used_types(&f.ty, out)),
+        OwnedDataModelType::Enum(variants) => {
+            for v in variants {
+                match &v.ty {
+                    OwnedDataModelVariant::UnitVariant => {}
+                    OwnedDataModelVariant::NewtypeVariant(t) => used_types(t, out),
+                    OwnedDataModelVariant::TupleVariant(ts) => {
+                        ts.iter().for_each(|t| used_types(t, out))
+                    }
+                    OwnedDataModelVariant::StructVariant(fields) => {
+                        fields.iter().for_each(|f| used_types(&f.ty, out))
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Check whether a protocol may be declared in `file`, given the protocols
+/// before it, and the modules at the root of the generated code.
+///
+/// Each protocol is generated as a module named after it, which must not be
+/// the name of any other module.
+fn new_protocol_legal(
+    file: usize,
+    proto: &UnresolvedProtocol<'_>,
+    known: &[(usize, &UnresolvedProtocol<'_>)],
+    modules: &[String],
 ) -> Result<(), Error> {
-    // This is potentially the worst way to do this. We loop over and over as
-    // long as we keep resolving SOMETHING.
-    while !unknown.is_empty() {
-        let mut progress = false;
-        let mut old_unks = core::mem::take(unknown);
-        for unk in old_unks.drain(..) {
-            if let Some(k) = unk.resolve(known)? {
-                known.push(k);
-                progress = true;
-            } else {
-                unknown.push(unk);
+    if !is_rust_ident(proto.name) {
+        return Err(Error::BadName {
+            name: proto.name.to_string(),
+            reason: "not a valid protocol name".into(),
+            span: proto.span,
+        });
+    }
+    let module = snake_case(proto.name);
+    let clash = if modules.contains(&module) {
+        Some("a module of types".to_string())
+    } else if module == BORROWED {
+        Some("the module of borrowed types".to_string())
+    } else {
+        known
+            .iter()
+            .find(|(_, k)| k.name != proto.name && snake_case(k.name) == module)
+            .map(|(_, k)| format!("the module of the protocol `{}`", k.name))
+    };
+    if let Some(clash) = clash {
+        return Err(Error::Malformed {
+            what: "protocol".into(),
+            reason: format!("the generated module `{module}` is already {clash}"),
+            span: proto.span,
+            help: Some("protocols are generated as a module named after them".into()),
+        });
+    }
+    match known.iter().find(|(_, k)| k.name == proto.name) {
+        None => Ok(()),
+        Some((f, first)) if *f == file => Err(Error::DuplicateMember {
+            kind: "protocol",
+            name: proto.name.to_string(),
+            span: proto.span,
+            first: first.span,
+        }),
+        Some(_) => Err(Error::Malformed {
+            what: "protocol".into(),
+            reason: format!("duplicate protocol `{}`", proto.name),
+            span: proto.span,
+            help: Some("it is already declared in another file".into()),
+        }),
+    }
+}
+
+/// The parts of an endpoint or topic that must be unique
+struct RpcItem<'a> {
+    kind: &'static str,
+    name: &'a str,
+    path: &'a str,
+    span: SourceSpan,
+}
+
+impl RpcItem<'_> {
+    /// The names of the types generated for this item: its marker type, and
+    /// the aliases of its messages, see `rust_std::single_token_refr`
+    fn generated(&self) -> Vec<String> {
+        let suffixes: &[&str] = match self.kind {
+            "endpoint" => &["", "Request", "Response"],
+            _ => &["", "Message"],
+        };
+        suffixes
+            .iter()
+            .map(|s| format!("{}{s}", self.name))
+            .collect()
+    }
+
+    /// The names of the constants generated for the keys of this item, see
+    /// `rust_std::generate_keys`
+    fn key_consts(&self) -> Vec<String> {
+        let suffixes: &[&str] = match self.kind {
+            "endpoint" => &["REQ_KEY", "RESP_KEY"],
+            _ => &["KEY"],
+        };
+        suffixes.iter().map(|s| key_const(self.name, s)).collect()
+    }
+}
+
+/// Check whether an endpoint or topic may be declared in `file`, given the
+/// ones before it, and the types at the root of the generated code.
+///
+/// Names must be unique across endpoints and topics, as they are used for
+/// the generated marker types. Paths must be unique within each kind. None
+/// of the generated types may have the name of another type, and no two
+/// items may generate key constants of the same name.
+fn new_rpc_item_legal(
+    file: usize,
+    item: &RpcItem<'_>,
+    known: &[(usize, RpcItem<'_>)],
+    types: &[String],
+) -> Result<(), Error> {
+    if !is_rust_ident(item.name) {
+        return Err(Error::BadName {
+            name: item.name.to_string(),
+            reason: format!("not a valid {} name", item.kind),
+            span: item.span,
+        });
+    }
+    let dupes = [
+        (
+            item.kind,
+            item.name,
+            known.iter().find(|(_, k)| k.name == item.name),
+        ),
+        (
+            "path",
+            item.path,
+            known
+                .iter()
+                .find(|(_, k)| k.kind == item.kind && k.path == item.path),
+        ),
+    ];
+    for (kind, name, first) in dupes {
+        match first {
+            None => {}
+            Some((f, first)) if *f == file => {
+                return Err(Error::DuplicateMember {
+                    kind,
+                    name: name.to_string(),
+                    span: item.span,
+                    first: first.span,
+                })
+            }
+            Some((_, first)) => {
+                return Err(Error::Malformed {
+                    what: item.kind.into(),
+                    reason: format!("duplicate {kind} `{name}`"),
+                    span: item.span,
+                    help: Some(format!(
+                        "the {} `{}` in another file already uses it",
+                        first.kind, first.name
+                    )),
+                })
             }
         }
-        if !progress {
-            todo!()
+    }
+
+    let generated = item.generated();
+    let clash = |reason: String| Error::Malformed {
+        what: item.kind.into(),
+        reason,
+        span: item.span,
+        help: Some(format!(
+            "the {} `{}` generates the types `{}`",
+            item.kind,
+            item.name,
+            generated.join("`, `")
+        )),
+    };
+    for name in generated.iter() {
+        if types.contains(name) {
+            return Err(clash(format!("the generated `{name}` is already a type")));
+        }
+        if let Some((_, k)) = known.iter().find(|(_, k)| k.generated().contains(name)) {
+            return Err(clash(format!(
+                "the generated `{name}` is already used by the {} `{}`",
+                k.kind, k.name
+            )));
+        }
+    }
+
+    let consts = item.key_consts();
+    for name in consts.iter() {
+        if let Some((_, k)) = known.iter().find(|(_, k)| k.key_consts().contains(name)) {
+            return Err(Error::Malformed {
+                what: item.kind.into(),
+                reason: format!(
+                    "the generated constant `{name}` is already used by the {} `{}`",
+                    k.kind, k.name
+                ),
+                span: item.span,
+                help: Some(format!(
+                    "the {} `{}` generates the key constants `{}`",
+                    item.kind,
+                    item.name,
+                    consts.join("`, `")
+                )),
+            });
         }
     }
     Ok(())
 }
+
+/// Make sure that every type referenced from the given file exists, and that
+/// there are no reference cycles that would make a type infinitely large.
+///
+/// Every definition that can't be resolved is reported: it either references
+/// a type that doesn't exist, is part of a disallowed cycle, or depends on
+/// one of the former.
+///
+/// Imported files must have been checked already.
+fn check_references(files: &[UnresolvedFile<'_>], file: usize) -> Result<(), Error> {
+    let defns = &files[file].defns;
+    let mut errors = vec![];
+
+    // The definitions in this file referenced by each definition
+    let local_refs = |scoped: &ScopedDefn<'_>| -> Vec<usize> {
+        scoped
+            .defn
+            .refs()
+            .iter()
+            .filter_map(|r| lookup(files, file, &scoped.module, r.name))
+            .filter_map(|(f, idx)| (f == file).then_some(idx))
+            .collect()
+    };
+
+    // First: references to names that are defined nowhere
+    let mut broken: Vec<usize> = vec![];
+    for (idx, scoped) in defns.iter().enumerate() {
+        let defn = &scoped.defn;
+        for TyRef { name, span, .. } in defn.refs() {
+            if BUILTIN_TYPE_NAMES.contains(&name)
+                || lookup(files, file, &scoped.module, name).is_some()
+            {
+                continue;
+            }
+            let visible = visible_names(files, file, &scoped.module);
+            let candidates = visible
+                .iter()
+                .map(String::as_str)
+                .chain(BUILTIN_TYPE_NAMES.iter().copied());
+            errors.push(Error::UnknownType {
+                name: name.to_string(),
+                defn: scoped.qualified(),
+                span,
+                defn_span: defn.span(),
+                help: did_you_mean(name, candidates),
+            });
+            if !broken.contains(&idx) {
+                broken.push(idx);
+            }
+        }
+    }
+
+    // Second: cycles that can't be broken. A type can only contain itself
+    // through an `option`, seq, or map, and at least one type in each cycle
+    // must be a struct or enum, otherwise we'd have infinitely nested aliases.
+    let direct = find_cycles(files, file, |_, r, _| !r.indirect);
+    let aliases = find_cycles(files, file, |from, _, to| {
+        !from.is_nominal() && !to.is_nominal()
+    });
+    let cycles = direct
+        .into_iter()
+        .map(|c| {
+            let help = "a type can't directly contain itself, use `option<...>`, \
+                `[...]`, or `map<...>` to break the cycle";
+            (c, help)
+        })
+        .chain(aliases.into_iter().map(|c| {
+            let help = "aliases can't refer to themselves, use a `struct` or \
+                `enum` to break the cycle";
+            (c, help)
+        }));
+    for (cycle, help) in cycles {
+        let path = cycle
+            .iter()
+            .map(|(defn, _)| defns[*defn].qualified())
+            .chain(cycle.first().map(|(defn, _)| defns[*defn].qualified()))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        let labels = cycle
+            .iter()
+            .zip(cycle.iter().cycle().skip(1))
+            .map(|((from, span), (to, _))| {
+                let from = defns[*from].qualified();
+                let to = defns[*to].qualified();
+                LabeledSpan::new_with_span(Some(format!("`{from}` refers to `{to}`")), *span)
+            })
+            .collect();
+        for (defn, _) in cycle.iter() {
+            if !broken.contains(defn) {
+                broken.push(*defn);
+            }
+        }
+        errors.push(Error::TypeCycle {
+            path,
+            labels,
+            help: help.to_string(),
+        });
+    }
+
+    // Finally: everything that relies on the above
+    let mut dependents: Vec<usize> = vec![];
+    loop {
+        let before = dependents.len();
+        for (idx, scoped) in defns.iter().enumerate() {
+            if broken.contains(&idx) || dependents.contains(&idx) {
+                continue;
+            }
+            if local_refs(scoped)
+                .iter()
+                .any(|r| broken.contains(r) || dependents.contains(r))
+            {
+                dependents.push(idx);
+            }
+        }
+        if dependents.len() == before {
+            break;
+        }
+    }
+    for (idx, scoped) in defns.iter().enumerate() {
+        if !dependents.contains(&idx) {
+            continue;
+        }
+        let mut deps: Vec<String> = vec![];
+        for TyRef { name, .. } in scoped.defn.refs() {
+            let dep = format!("`{name}`");
+            let failed = match lookup(files, file, &scoped.module, name) {
+                Some((f, r)) => f == file && (broken.contains(&r) || dependents.contains(&r)),
+                None => false,
+            };
+            if failed && !deps.contains(&dep) {
+                deps.push(dep);
+            }
+        }
+        errors.push(Error::Unresolved {
+            name: scoped.qualified(),
+            deps: deps.join(", "),
+            span: scoped.defn.span(),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Unresolvable {
+            count: broken.len() + dependents.len(),
+            errors,
+        })
+    }
+}
+
+/// Find reference cycles among the definitions of a file, only following
+/// the references accepted by `follow(from, reference, to)`.
+///
+/// Each cycle is returned as a list of `(definition index, reference span)`,
+/// where the span is the reference to the next definition in the cycle.
+/// References to other files can't be part of a cycle, as imports can't be
+/// cyclic.
+fn find_cycles<'a>(
+    files: &[UnresolvedFile<'a>],
+    file: usize,
+    follow: impl Fn(&UnresolvedTypeDefn<'a>, &TyRef<'a>, &UnresolvedTypeDefn<'a>) -> bool,
+) -> Vec<Vec<(usize, SourceSpan)>> {
+    let defns = &files[file].defns;
+    let edges: Vec<Vec<(usize, SourceSpan)>> = defns
+        .iter()
+        .map(|d| {
+            d.defn
+                .refs()
+                .into_iter()
+                .filter_map(|r| {
+                    let (f, idx) = lookup(files, file, &d.module, r.name)?;
+                    let local = f == file && follow(&d.defn, &r, &defns[idx].defn);
+                    local.then_some((idx, r.span))
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut cycles = vec![];
+    let mut in_cycle = vec![false; defns.len()];
+    for start in 0..defns.len() {
+        if in_cycle[start] {
+            continue;
+        }
+        // Depth first search for a path leading back to `start`, only
+        // visiting each node once.
+        let mut visited = vec![false; defns.len()];
+        let mut path: Vec<(usize, usize)> = vec![(start, 0)];
+        visited[start] = true;
+        while let Some((node, next_edge)) = path.last_mut() {
+            let Some((to, _)) = edges[*node].get(*next_edge) else {
+                path.pop();
+                continue;
+            };
+            *next_edge += 1;
+            if *to == start {
+                let cycle: Vec<(usize, SourceSpan)> = path
+                    .iter()
+                    .map(|(n, e)| (*n, edges[*n][*e - 1].1))
+                    .collect();
+                for (n, _) in cycle.iter() {
+                    in_cycle[*n] = true;
+                }
+                cycles.push(cycle);
+                break;
+            }
+            if !visited[*to] && !in_cycle[*to] {
+                visited[*to] = true;
+                path.push((*to, 0));
+            }
+        }
+    }
+    cycles
+}
+
+/// Suggest similarly named types for a name that doesn't exist
+fn did_you_mean<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_dist = (name.chars().count() / 3).max(1);
+    let mut close: Vec<(usize, &str)> = candidates
+        .filter_map(|c| {
+            let dist = if c.eq_ignore_ascii_case(name) {
+                0
+            } else {
+                edit_distance(name, c)
+            };
+            (dist <= max_dist).then_some((dist, c))
+        })
+        .collect();
+    close.sort();
+    close.dedup();
+    let close: Vec<String> = close
+        .iter()
+        .take(3)
+        .map(|(_, c)| format!("`{c}`"))
+        .collect();
+    match close.as_slice() {
+        [] => None,
+        [one] => Some(format!("did you mean {one}?")),
+        many => Some(format!("did you mean one of {}?", many.join(", "))),
+    }
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != *cb);
+            cur.push(sub.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
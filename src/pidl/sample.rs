@@ -0,0 +1,293 @@
+//! A sample value of a type, and its postcard encoding
+//!
+//! Samples are meant for documentation, to show what the encoding of a type
+//! looks like. Integers are chosen to show off varints and zigzag encoding,
+//! and strings, byte arrays, sequences and maps are as short as their
+//! `min=` bound allows, see [`super::Bounds`].
+
+use postcard_schema::schema::owned::{
+    OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue,
+};
+use serde::Serialize;
+
+use super::{is_array, BoundsScope, Pidl};
+
+/// A value of a type, written like a Rust expression, and its postcard
+/// encoding, see [`sample`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sample {
+    /// e.g. `Status { label: "abcde", uptime: 300 }`
+    pub value: String,
+    /// The postcard encoding of the value
+    pub bytes: Vec<u8>,
+}
+
+/// A sample value of `ty`, which is one of [`Pidl::types`], or the type of an
+/// endpoint or topic
+///
+/// Optional values and enum variants that would recurse are avoided, so
+/// there is a sample for most recursive types. There is none for types that
+/// can't avoid recursion, or that contain a schema.
+pub fn sample(p: &Pidl, ty: &OwnedNamedType) -> Option<Sample> {
+    let ty = p.aliased(ty);
+    let mut sampler = Sampler {
+        p,
+        within: vec![],
+        out: Sample {
+            value: String::new(),
+            bytes: vec![],
+        },
+    };
+    sampler.sample(ty, BoundsScope::new(&p.bounds)).ok()?;
+    Some(sampler.out)
+}
+
+/// A value that can't be sampled, which an enclosing option, sequence, map
+/// or enum may still avoid
+struct NoSample;
+
+struct Sampler<'a> {
+    p: &'a Pidl,
+    /// The user types being sampled, to catch recursion
+    within: Vec<&'a str>,
+    out: Sample,
+}
+
+impl<'a> Sampler<'a> {
+    fn sample(&mut self, ont: &'a OwnedNamedType, scope: BoundsScope<'_>) -> Result<(), NoSample> {
+        let scope = scope.enter(ont);
+        match &ont.ty {
+            OwnedDataModelType::Bool => self.push("true", &[1]),
+            OwnedDataModelType::U8 => self.push("7", &[7]),
+            OwnedDataModelType::I8 => self.push("-7", &(-7i8).to_le_bytes()),
+            OwnedDataModelType::U16
+            | OwnedDataModelType::U32
+            | OwnedDataModelType::U64
+            | OwnedDataModelType::U128
+            | OwnedDataModelType::Usize => self.push("300", &varint(300)),
+            OwnedDataModelType::I16
+            | OwnedDataModelType::I32
+            | OwnedDataModelType::I64
+            | OwnedDataModelType::I128
+            | OwnedDataModelType::Isize => self.push("-300", &varint(zigzag(-300))),
+            OwnedDataModelType::F32 => self.push("1.5", &1.5f32.to_le_bytes()),
+            OwnedDataModelType::F64 => self.push("1.5", &1.5f64.to_le_bytes()),
+            OwnedDataModelType::Char => self.push("'a'", &[1, b'a']),
+            OwnedDataModelType::String => {
+                let s: String = (0..length(scope, 5)).map(letter).collect();
+                self.push(&format!("{s:?}"), &varint(s.len() as u128));
+                self.out.bytes.extend(s.as_bytes());
+            }
+            OwnedDataModelType::ByteArray => {
+                let bytes: Vec<u8> = (0..length(scope, 3)).map(|i| (i + 1) as u8).collect();
+                self.push(&format!("{bytes:?}"), &varint(bytes.len() as u128));
+                self.out.bytes.extend(&bytes);
+            }
+            OwnedDataModelType::Option(inner) => {
+                let start = self.mark();
+                self.push("Some(", &[1]);
+                match self.sample(inner, scope) {
+                    Ok(()) => self.push(")", &[]),
+                    Err(NoSample) => {
+                        self.reset(start);
+                        self.push("None", &[0]);
+                    }
+                }
+            }
+            OwnedDataModelType::Unit => self.push("()", &[]),
+            OwnedDataModelType::UnitStruct => self.push(&ont.name, &[]),
+            OwnedDataModelType::Seq(item) => {
+                self.items(scope, "[", "]", |s| s.sample(item, scope.items()))?
+            }
+            OwnedDataModelType::Tuple(onts) if is_array(ont) => {
+                self.push("[", &[]);
+                self.tuple(onts, scope)?;
+                self.push("]", &[]);
+            }
+            OwnedDataModelType::Tuple(onts) => {
+                self.push("(", &[]);
+                self.tuple(onts, scope)?;
+                self.push(if onts.len() == 1 { ",)" } else { ")" }, &[]);
+            }
+            OwnedDataModelType::Map { key, val } => self.items(scope, "{", "}", |s| {
+                s.sample(key, scope.items())?;
+                s.push(": ", &[]);
+                s.sample(val, scope.items())
+            })?,
+            OwnedDataModelType::Schema => return Err(NoSample),
+            OwnedDataModelType::NewtypeStruct(_)
+            | OwnedDataModelType::TupleStruct(_)
+            | OwnedDataModelType::Struct(_)
+            | OwnedDataModelType::Enum(_) => {
+                if self.within.contains(&ont.name.as_str()) {
+                    return Err(NoSample);
+                }
+                self.within.push(&ont.name);
+                let sampled = self.sample_user_ty(ont);
+                self.within.pop();
+                sampled?
+            }
+        }
+        Ok(())
+    }
+
+    /// The contents of a user type, whose members have their own bounds
+    fn sample_user_ty(&mut self, ty: &'a OwnedNamedType) -> Result<(), NoSample> {
+        let name = &ty.name;
+        let scope = BoundsScope::new(&self.p.bounds);
+        match &ty.ty {
+            OwnedDataModelType::NewtypeStruct(ont) => {
+                self.push(&format!("{name}("), &[]);
+                self.sample(ont, scope.member(name))?;
+                self.push(")", &[]);
+            }
+            OwnedDataModelType::TupleStruct(onts) => {
+                self.push(&format!("{name}("), &[]);
+                self.tuple(onts, scope.member(name))?;
+                self.push(")", &[]);
+            }
+            OwnedDataModelType::Struct(fields) => {
+                self.push(name, &[]);
+                self.fields(name, fields)?;
+            }
+            OwnedDataModelType::Enum(variants) => {
+                // Prefer a variant with contents, as it shows more of the
+                // encoding, but fall back to any that doesn't recurse
+                let (unit, other): (Vec<_>, Vec<_>) = variants
+                    .iter()
+                    .enumerate()
+                    .partition(|(_, v)| v.ty == OwnedDataModelVariant::UnitVariant);
+                let start = self.mark();
+                for (idx, v) in other.into_iter().chain(unit) {
+                    let path = format!("{name}.{}", v.name);
+                    let scope = scope.member(&path);
+                    self.push(&format!("{name}::{}", v.name), &varint(idx as u128));
+                    let sampled = match &v.ty {
+                        OwnedDataModelVariant::UnitVariant => Ok(()),
+                        OwnedDataModelVariant::NewtypeVariant(ont) => {
+                            self.push("(", &[]);
+                            self.sample(ont, scope).map(|()| self.push(")", &[]))
+                        }
+                        OwnedDataModelVariant::TupleVariant(onts) => {
+                            self.push("(", &[]);
+                            self.tuple(onts, scope).map(|()| self.push(")", &[]))
+                        }
+                        OwnedDataModelVariant::StructVariant(fields) => self.fields(&path, fields),
+                    };
+                    match sampled {
+                        Ok(()) => return Ok(()),
+                        Err(NoSample) => self.reset(start),
+                    }
+                }
+                return Err(NoSample);
+            }
+            _ => unreachable!("only called for user types"),
+        }
+        Ok(())
+    }
+
+    /// The fields of a struct or struct variant, whose path is `owner`
+    fn fields(&mut self, owner: &str, fields: &'a [OwnedNamedValue]) -> Result<(), NoSample> {
+        let scope = BoundsScope::new(&self.p.bounds);
+        self.push(" {", &[]);
+        for (i, f) in fields.iter().enumerate() {
+            self.push(if i > 0 { ", " } else { " " }, &[]);
+            self.push(&format!("{}: ", f.name), &[]);
+            let path = format!("{owner}.{}", f.name);
+            self.sample(&f.ty, scope.member(&path))?;
+        }
+        self.push(if fields.is_empty() { "}" } else { " }" }, &[]);
+        Ok(())
+    }
+
+    /// The items of a tuple, separated by commas
+    fn tuple(
+        &mut self,
+        onts: &'a [OwnedNamedType],
+        scope: BoundsScope<'_>,
+    ) -> Result<(), NoSample> {
+        for (i, ont) in onts.iter().enumerate() {
+            if i > 0 {
+                self.push(", ", &[]);
+            }
+            self.sample(ont, scope)?;
+        }
+        Ok(())
+    }
+
+    /// The items of a sequence or map, as few as allowed, or none if the
+    /// items can't be sampled
+    fn items(
+        &mut self,
+        scope: BoundsScope<'_>,
+        open: &str,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<(), NoSample>,
+    ) -> Result<(), NoSample> {
+        let start = self.mark();
+        let mut count = length(scope, 1);
+        loop {
+            self.push(open, &varint(count as u128));
+            let sampled = (0..count).try_for_each(|i| {
+                if i > 0 {
+                    self.push(", ", &[]);
+                }
+                item(self)
+            });
+            match sampled {
+                Ok(()) => break,
+                Err(NoSample) if count > 0 && scope.get().min.unwrap_or(0) == 0 => {
+                    self.reset(start);
+                    count = 0;
+                }
+                Err(NoSample) => return Err(NoSample),
+            }
+        }
+        self.push(close, &[]);
+        Ok(())
+    }
+
+    fn push(&mut self, value: &str, bytes: &[u8]) {
+        self.out.value.push_str(value);
+        self.out.bytes.extend(bytes);
+    }
+
+    fn mark(&self) -> (usize, usize) {
+        (self.out.value.len(), self.out.bytes.len())
+    }
+
+    fn reset(&mut self, (value, bytes): (usize, usize)) {
+        self.out.value.truncate(value);
+        self.out.bytes.truncate(bytes);
+    }
+}
+
+/// The length closest to `preferred` that is within the bounds in effect
+fn length(scope: BoundsScope<'_>, preferred: usize) -> usize {
+    let bounds = scope.get();
+    preferred
+        .max(bounds.min.unwrap_or(0))
+        .min(bounds.max.unwrap_or(usize::MAX))
+}
+
+/// The `i`th letter of the alphabet, wrapping around
+fn letter(i: usize) -> char {
+    (b'a' + (i % 26) as u8) as char
+}
+
+fn varint(mut n: u128) -> Vec<u8> {
+    let mut out = vec![];
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            out.push(byte);
+            return out;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn zigzag(n: i128) -> u128 {
+    ((n << 1) ^ (n >> 127)) as u128
+}
@@ -0,0 +1,210 @@
+//! The largest size of a value of a type, when encoded with postcard
+//!
+//! This is meant for sizing receive buffers, so it is always an upper bound.
+//! Integers are counted as their largest varint, and strings, byte arrays,
+//! sequences and maps as their `max=` bound, see [`super::Bounds`].
+
+use core::fmt;
+
+use postcard_schema::schema::owned::{OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType};
+use serde::Serialize;
+
+use super::{BoundsScope, Pidl};
+
+/// The largest encoded size of a type, see [`max_size`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum MaxSize {
+    /// At most this many bytes
+    Bounded(usize),
+    /// There is no limit, for the given reason, e.g.
+    /// ``the sequence in `Status.readings` has no `max=` ``
+    Unbounded(String),
+}
+
+impl fmt::Display for MaxSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaxSize::Bounded(size) => write!(f, "{size}"),
+            MaxSize::Unbounded(reason) => write!(f, "unbounded, {reason}"),
+        }
+    }
+}
+
+/// The largest postcard encoding of a value of `ty`, which is one of
+/// [`Pidl::types`], or the type of an endpoint or topic
+///
+/// `usize` and `isize` are counted as they are on 64-bit targets. Recursive
+/// types, and types with any string, byte array, sequence or map without a
+/// `max=`, are unbounded.
+pub fn max_size(p: &Pidl, ty: &OwnedNamedType) -> MaxSize {
+    let ty = p.aliased(ty);
+    // Recursive types are found by their stubs, as the name of a type
+    // doesn't say whether it is the definition or a reference back to it
+    if let Some(stub) = super::find_stub(ty) {
+        return MaxSize::Unbounded(format!("`{}` is recursive", stub.name));
+    }
+    let mut sizer = Sizer { p };
+    match sizer.size(ty, BoundsScope::new(&p.bounds), &format!("`{}`", ty.name)) {
+        Ok(size) => MaxSize::Bounded(size),
+        Err(reason) => MaxSize::Unbounded(reason),
+    }
+}
+
+struct Sizer<'a> {
+    p: &'a Pidl,
+}
+
+impl<'a> Sizer<'a> {
+    /// The size of `ont`, which is part of the member described by `path`,
+    /// e.g. ``the items of `Status.readings` ``, for errors
+    fn size(
+        &mut self,
+        ont: &'a OwnedNamedType,
+        scope: BoundsScope<'_>,
+        path: &str,
+    ) -> Result<usize, String> {
+        let scope = scope.enter(ont);
+        let max = |kind: &str| {
+            scope
+                .get()
+                .max
+                .ok_or_else(|| format!("the {kind} in {path} has no `max=`"))
+        };
+        let size = match &ont.ty {
+            OwnedDataModelType::Bool | OwnedDataModelType::I8 | OwnedDataModelType::U8 => 1,
+            OwnedDataModelType::I16 | OwnedDataModelType::U16 => varint_max(16),
+            OwnedDataModelType::I32 | OwnedDataModelType::U32 => varint_max(32),
+            OwnedDataModelType::I64
+            | OwnedDataModelType::U64
+            | OwnedDataModelType::Isize
+            | OwnedDataModelType::Usize => varint_max(64),
+            OwnedDataModelType::I128 | OwnedDataModelType::U128 => varint_max(128),
+            OwnedDataModelType::F32 => 4,
+            OwnedDataModelType::F64 => 8,
+            // Encoded as a string of up to four bytes
+            OwnedDataModelType::Char => varint_len(4) + 4,
+            OwnedDataModelType::String => {
+                let max = max("string")?;
+                add(varint_len(max), max, path)?
+            }
+            OwnedDataModelType::ByteArray => {
+                let max = max("byte array")?;
+                add(varint_len(max), max, path)?
+            }
+            OwnedDataModelType::Option(ont) => add(1, self.size(ont, scope, path)?, path)?,
+            OwnedDataModelType::Unit | OwnedDataModelType::UnitStruct => 0,
+            OwnedDataModelType::Seq(ont) => {
+                let max = max("sequence")?;
+                let item = self.size(ont, scope.items(), &format!("the items of {path}"))?;
+                add(varint_len(max), mul(max, item, path)?, path)?
+            }
+            OwnedDataModelType::Tuple(onts) => self.sum(onts, scope, path)?,
+            OwnedDataModelType::Map { key, val } => {
+                let max = max("map")?;
+                let key = self.size(key, scope.items(), &format!("the keys of {path}"))?;
+                let val = self.size(val, scope.items(), &format!("the values of {path}"))?;
+                add(varint_len(max), mul(max, add(key, val, path)?, path)?, path)?
+            }
+            OwnedDataModelType::Schema => {
+                return Err(format!("the schema in {path} has no size limit"))
+            }
+            OwnedDataModelType::NewtypeStruct(_)
+            | OwnedDataModelType::TupleStruct(_)
+            | OwnedDataModelType::Struct(_)
+            | OwnedDataModelType::Enum(_) => self.size_user_ty(ont)?,
+        };
+        Ok(size)
+    }
+
+    /// The size of the contents of a user type, whose members have their own
+    /// bounds
+    fn size_user_ty(&mut self, ty: &'a OwnedNamedType) -> Result<usize, String> {
+        let name = &ty.name;
+        let scope = BoundsScope::new(&self.p.bounds);
+        match &ty.ty {
+            OwnedDataModelType::NewtypeStruct(ont) => {
+                self.size(ont, scope.member(name), &format!("`{name}`"))
+            }
+            OwnedDataModelType::TupleStruct(onts) => {
+                self.sum(onts, scope.member(name), &format!("`{name}`"))
+            }
+            OwnedDataModelType::Struct(onvs) => {
+                let mut size = 0;
+                for v in onvs {
+                    let path = format!("{name}.{}", v.name);
+                    let at = format!("`{path}`");
+                    let field = self.size(&v.ty, scope.member(&path), &at)?;
+                    size = add(size, field, &at)?;
+                }
+                Ok(size)
+            }
+            OwnedDataModelType::Enum(variants) => {
+                let mut largest = 0;
+                for (idx, v) in variants.iter().enumerate() {
+                    let path = format!("{name}.{}", v.name);
+                    let scope = scope.member(&path);
+                    let at = format!("`{path}`");
+                    let body = match &v.ty {
+                        OwnedDataModelVariant::UnitVariant => 0,
+                        OwnedDataModelVariant::NewtypeVariant(ont) => self.size(ont, scope, &at)?,
+                        OwnedDataModelVariant::TupleVariant(onts) => self.sum(onts, scope, &at)?,
+                        OwnedDataModelVariant::StructVariant(onvs) => {
+                            let mut size = 0;
+                            for f in onvs {
+                                let path = format!("{path}.{}", f.name);
+                                let at = format!("`{path}`");
+                                let field = self.size(&f.ty, scope.member(&path), &at)?;
+                                size = add(size, field, &at)?;
+                            }
+                            size
+                        }
+                    };
+                    // The discriminant is a varint of the variant's index
+                    largest = largest.max(add(varint_len(idx), body, &at)?);
+                }
+                Ok(largest)
+            }
+            _ => unreachable!("only called for user types"),
+        }
+    }
+
+    /// The total size of the items of a tuple
+    fn sum(
+        &mut self,
+        onts: &'a [OwnedNamedType],
+        scope: BoundsScope<'_>,
+        path: &str,
+    ) -> Result<usize, String> {
+        let mut size = 0;
+        for ont in onts {
+            size = add(size, self.size(ont, scope, path)?, path)?;
+        }
+        Ok(size)
+    }
+}
+
+/// Sizes can only overflow with absurdly large `max=` bounds, but they are
+/// still reported instead of panicking
+fn add(a: usize, b: usize, path: &str) -> Result<usize, String> {
+    a.checked_add(b).ok_or_else(|| too_large(path))
+}
+
+fn mul(count: usize, size: usize, path: &str) -> Result<usize, String> {
+    count.checked_mul(size).ok_or_else(|| too_large(path))
+}
+
+fn too_large(path: &str) -> String {
+    format!("the size of {path} is too large to count")
+}
+
+/// The number of bytes in the varint encoding of `n`
+fn varint_len(n: usize) -> usize {
+    let bits = usize::BITS - n.leading_zeros();
+    bits.div_ceil(7).max(1) as usize
+}
+
+/// The number of bytes in the largest varint of an integer with this many
+/// bits
+fn varint_max(bits: usize) -> usize {
+    bits.div_ceil(7)
+}
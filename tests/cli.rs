@@ -0,0 +1,92 @@
+//! The exit codes of the `pidl` command line tool
+
+use std::process::Command;
+
+const PIDL: &str = env!("CARGO_BIN_EXE_pidl");
+const TYPES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rust_std/types.kdl");
+
+/// Run `pidl`, returning its exit code
+fn pidl(args: &[&str]) -> i32 {
+    run(args).0
+}
+
+/// Run `pidl`, returning its exit code and what it printed
+fn run(args: &[&str]) -> (i32, String) {
+    let output = Command::new(PIDL).args(args).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (output.status.code().unwrap(), stdout)
+}
+
+/// Write a file for a test, returning its path
+fn file(name: &str, contents: &str) -> String {
+    let path = format!("{}/{name}", env!("CARGO_TARGET_TMPDIR"));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn check_fails_on_errors() {
+    assert_eq!(pidl(&["check", TYPES]), 0);
+    let bad = file("cli-bad.kdl", r#"types { struct "A" { x "Missing" } }"#);
+    assert_eq!(pidl(&["check", &bad]), 1);
+    assert_eq!(pidl(&["check", "tests/no-such-file.kdl"]), 1);
+}
+
+#[test]
+fn usage_errors_are_2() {
+    assert_eq!(pidl(&[]), 2);
+    assert_eq!(pidl(&["frobnicate"]), 2);
+    assert_eq!(pidl(&["gen", "--lang", "cobol", "--out", "x", TYPES]), 2);
+    assert_eq!(pidl(&["dump", "--format", "xml", TYPES]), 2);
+}
+
+#[test]
+fn gen_fails_when_it_cant_write() {
+    let out = concat!(env!("CARGO_TARGET_TMPDIR"), "/cli-gen");
+    assert_eq!(pidl(&["gen", "--lang", "rust-std", "--out", out, TYPES]), 0);
+    assert!(std::path::Path::new(out).join("types.rs").exists());
+    // A file where the directory should be
+    let out = file("cli-not-a-dir", "");
+    assert_eq!(
+        pidl(&["gen", "--lang", "rust-std", "--out", &out, TYPES]),
+        3
+    );
+}
+
+#[test]
+fn dump_prints_json() {
+    let (code, stdout) = run(&["dump", "--format", "json", TYPES]);
+    assert_eq!(code, 0);
+    let dump: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert!(dump["types"].is_array(), "{dump}");
+}
+
+#[test]
+fn borrowed_is_only_for_rust_std() {
+    let out = concat!(env!("CARGO_TARGET_TMPDIR"), "/cli-borrowed");
+    for lang in ["rust-heapless", "typst", "markdown"] {
+        let args = ["gen", "--lang", lang, "--borrowed", "--out", out, TYPES];
+        assert_eq!(pidl(&args), 2, "{lang}");
+    }
+    let args = [
+        "gen",
+        "--lang",
+        "rust-std",
+        "--borrowed",
+        "--out",
+        out,
+        TYPES,
+    ];
+    assert_eq!(pidl(&args), 0);
+}
+
+#[test]
+fn fmt_check_fails_on_unformatted_files() {
+    let formatted = "types {\n    alias \"A\" \"u8\"\n}\n";
+    let path = file("cli-fmt.kdl", "types {\n  alias A \"u8\" }\n");
+    assert_eq!(pidl(&["fmt", "--check", &path]), 1);
+    assert_eq!(pidl(&["fmt", &path]), 0);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), formatted);
+    assert_eq!(pidl(&["fmt", "--check", &path]), 0);
+    assert_eq!(pidl(&["fmt", "--check", TYPES]), 0);
+}
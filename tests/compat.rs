@@ -0,0 +1,127 @@
+//! Changes between two revisions, for the cases the compatibility checker
+//! exists for
+
+use postcard_idl::{
+    pidl::{compare, Change, Severity},
+    Pidl,
+};
+
+fn changes(old: &str, new: &str) -> Vec<(Severity, String, String)> {
+    let old = Pidl::parse_from_str(old).unwrap();
+    let new = Pidl::parse_from_str(new).unwrap();
+    compare(&old, &new)
+        .into_iter()
+        .map(|c: Change| (c.severity, c.path, c.message))
+        .collect()
+}
+
+fn change(severity: Severity, path: &str, message: &str) -> (Severity, String, String) {
+    (severity, path.into(), message.into())
+}
+
+#[test]
+fn appended_variants_are_compatible() {
+    let found = changes(
+        r#"types { enum "E" { A; B "u8" } }"#,
+        r#"types { enum "E" { A; B "u8"; C } }"#,
+    );
+    assert_eq!(
+        found,
+        [change(Severity::Compatible, "E", "variant `C` added")]
+    );
+
+    let found = changes(
+        r#"types { enum "E" { A; B "u8" } }"#,
+        r#"types { enum "E" { C; A; B "u8" } }"#,
+    );
+    assert_eq!(
+        found[0],
+        change(Severity::Breaking, "E", "variant `A` moved from 0 to 1")
+    );
+}
+
+#[test]
+fn reordered_fields_are_breaking() {
+    let found = changes(
+        r#"types { struct "S" { a "u8"; b "u16" } }"#,
+        r#"types { struct "S" { b "u16"; a "u8" } }"#,
+    );
+    assert_eq!(
+        found,
+        [
+            change(Severity::Breaking, "S", "field `a` moved from 0 to 1"),
+            change(Severity::Breaking, "S", "field `b` moved from 1 to 0"),
+        ]
+    );
+}
+
+#[test]
+fn integers_change_by_encoding() {
+    let found = changes(
+        r#"types { struct "S" { a "u16"; b "i32"; c "u8" } }"#,
+        r#"types { struct "S" { a "u32"; b "u32"; c "u16" } }"#,
+    );
+    assert_eq!(
+        found,
+        [
+            change(
+                Severity::Compatible,
+                "S.a",
+                "`u16` is now `u32`, which decodes the same varints"
+            ),
+            change(Severity::Breaking, "S.b", "`i32` is now `u32`"),
+            // `u8` is a raw byte, not a varint
+            change(Severity::Breaking, "S.c", "`u8` is now `u16`"),
+        ]
+    );
+}
+
+#[test]
+fn renames_are_source_breaking() {
+    let found = changes(
+        r#"types { struct "S" { name "string" }; struct "T" { s "S" } }"#,
+        r#"types { struct "S" { label "string" }; struct "T" { s "S" } }"#,
+    );
+    assert_eq!(
+        found,
+        [change(
+            Severity::SourceBreaking,
+            "S",
+            "field `name` renamed to `label`"
+        )]
+    );
+}
+
+#[test]
+fn rpc_keys_cover_the_whole_schema() {
+    let old = r#"
+        types { struct "S" { name "string" } }
+        endpoints { endpoint "Get" path="s/get" req="()" resp="S" }
+    "#;
+    let found = changes(old, &old.replace("name", "label"));
+    assert!(
+        found.contains(&change(
+            Severity::Breaking,
+            "Get",
+            "the response key changes, so old clients can't reach it"
+        )),
+        "{found:?}"
+    );
+}
+
+#[test]
+fn type_renames_keep_keys() {
+    let old = r#"
+        types { struct "S" { name "string" } }
+        endpoints { endpoint "Get" path="s/get" req="()" resp="S" }
+    "#;
+    let new = old.replace("\"S\"", "\"Status\"");
+    assert_eq!(
+        changes(old, &new),
+        [
+            change(Severity::SourceBreaking, "S", "type removed"),
+            change(Severity::Compatible, "Status", "type added"),
+            change(Severity::SourceBreaking, "Get.resp", "`S` is now `Status`"),
+        ]
+    );
+}
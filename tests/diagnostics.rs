@@ -0,0 +1,197 @@
+//! Errors in IDL files, and the spans they point at
+
+use miette::SourceSpan;
+use postcard_idl::{pidl::Error, Pidl};
+
+/// The source text a span points at
+fn at(src: &str, span: SourceSpan) -> &str {
+    &src[span.offset()..span.offset() + span.len()]
+}
+
+#[test]
+fn bad_type_strings_point_into_the_string() {
+    let src = r#"types { struct "A" { x "[u8" } }"#;
+    let err = Pidl::parse_from_str(src).unwrap_err();
+    let Error::BadTypeRefr { ty, reason, span } = &err else {
+        panic!("{err:?}");
+    };
+    assert_eq!(ty, "[u8");
+    assert_eq!(reason, "expected `]` or `;`");
+    // Right after `[u8`, where the `]` is missing
+    assert_eq!(span.offset(), src.find("[u8").unwrap() + 3);
+}
+
+#[test]
+fn duplicate_fields_point_at_both() {
+    let src = r#"types { struct "A" { x "u8"; x "u16" } }"#;
+    let err = Pidl::parse_from_str(src).unwrap_err();
+    let Error::DuplicateMember {
+        kind,
+        name,
+        span,
+        first,
+    } = &err
+    else {
+        panic!("{err:?}");
+    };
+    assert_eq!((*kind, name.as_str()), ("field", "x"));
+    assert_eq!(span.offset(), src.rfind("x \"").unwrap());
+    assert_eq!(first.offset(), src.find("x \"").unwrap());
+}
+
+#[test]
+fn unknown_types_point_at_the_name() {
+    let src = r#"types { struct "A" { x "option<Missing>" } }"#;
+    let err = Pidl::parse_from_str(src).unwrap_err();
+    let Error::Unresolvable { count: 1, errors } = &err else {
+        panic!("{err:?}");
+    };
+    let Error::UnknownType {
+        name,
+        defn,
+        span,
+        defn_span,
+        ..
+    } = &errors[0]
+    else {
+        panic!("{errors:?}");
+    };
+    assert_eq!((name.as_str(), defn.as_str()), ("Missing", "A"));
+    assert_eq!(at(src, *span), "Missing");
+    assert!(at(src, *defn_span).starts_with("struct \"A\""));
+}
+
+#[test]
+fn bad_input_is_an_error_not_a_panic() {
+    let bad = [
+        "",
+        r#"types { struct }"#,
+        r#"types { struct "A" { x } }"#,
+        r#"types { struct "A" { x 1 } }"#,
+        r#"types { enum "E" { A "u8" "u16" } }"#,
+        r#"types { alias "A" }"#,
+        r#"types { struct "a b" { x "u8" } }"#,
+        r#"types { struct "A" { x "map<u8>" } }"#,
+        r#"types { struct "A" { x "[u8; x]" } }"#,
+        r#"types { thing "A" }"#,
+        r#"types {}; types {}"#,
+        r#"stuff {}"#,
+    ];
+    for src in bad {
+        assert!(Pidl::parse_from_str(src).is_err(), "{src}");
+    }
+}
+
+#[test]
+fn errors_render_with_their_source() {
+    let src = r#"types { struct "A" { x "Missing" } }"#;
+    let err = Pidl::parse_from_str(src).unwrap_err();
+    let report = miette::Report::new(err).with_source_code(src);
+    let rendered = format!("{report:?}");
+    assert!(
+        rendered.contains("Unknown type `Missing`, referenced by `A`"),
+        "{rendered}"
+    );
+    assert!(rendered.contains("unknown type"), "{rendered}");
+}
+
+#[test]
+fn unknown_properties_are_errors() {
+    let cases = [
+        (
+            r#"types { struct "A" derives="Debug" { x "u8" } }"#,
+            "derives",
+            "did you mean `derive`?",
+        ),
+        (
+            r#"types { enum "E" max=4 { A } }"#,
+            "max",
+            "expected one of `derive`, `attr`, `doc`",
+        ),
+        (
+            r#"types { struct "A" { x "[u8]" mx=4 } }"#,
+            "mx",
+            "did you mean `max`?",
+        ),
+    ];
+    for (src, prop, expected) in cases {
+        let err = Pidl::parse_from_str(src).unwrap_err();
+        let Error::Malformed {
+            reason, span, help, ..
+        } = &err
+        else {
+            panic!("{err:?}");
+        };
+        assert_eq!(reason, &format!("unexpected property `{prop}`"));
+        assert!(at(src, *span).starts_with(prop), "{src}");
+        assert_eq!(help.as_deref(), Some(expected));
+    }
+}
+
+#[test]
+fn properties_are_found_by_name() {
+    // In any order, and with the bounds of a definition before its docs
+    let pidl = Pidl::parse_from_str(
+        r##"types {
+            alias "Name" "string" min=1 doc="A name" attr="#[non_exhaustive]" max=8 derive="Hash"
+        }"##,
+    )
+    .unwrap();
+    let bounds = &pidl.bounds["Name"];
+    assert_eq!((bounds.min, bounds.max), (Some(1), Some(8)));
+    assert_eq!(pidl.docs["Name"], "A name");
+    assert_eq!(pidl.meta["Name"].derives, ["Hash"]);
+    assert_eq!(pidl.meta["Name"].attrs, ["#[non_exhaustive]"]);
+}
+
+#[test]
+fn member_names_must_be_rust_identifiers() {
+    let cases = [
+        (
+            r#"types { struct "A" { type "u8" } }"#,
+            "type",
+            "`type` is a Rust keyword",
+        ),
+        (
+            r#"types { struct "A" { my-field "u8" } }"#,
+            "my-field",
+            "not a valid field name",
+        ),
+        (
+            r#"types { enum "E" { Self } }"#,
+            "Self",
+            "`Self` is a Rust keyword",
+        ),
+        (
+            r#"types { enum "E" { A; _ "u8" } }"#,
+            "_",
+            "not a valid variant name",
+        ),
+    ];
+    for (src, name, reason) in cases {
+        let err = Pidl::parse_from_str(src).unwrap_err();
+        let Error::BadName {
+            name: bad,
+            reason: why,
+            span,
+        } = &err
+        else {
+            panic!("{err:?}");
+        };
+        assert_eq!((bad.as_str(), why.as_str()), (name, reason));
+        assert_eq!(at(src, *span), name);
+    }
+    Pidl::parse_from_str(r#"types { struct "A" { _x "u8"; r2 "u8" } }"#).unwrap();
+}
+
+#[test]
+fn type_names_must_be_rust_identifiers() {
+    for name in ["match", "Self", "a-b", "1A", "_"] {
+        let src = format!(r#"types {{ struct "{name}" {{ x "u8" }} }}"#);
+        let err = Pidl::parse_from_str(&src).unwrap_err();
+        assert!(
+            matches!(&err, Error::BadName { name: bad, .. } if bad == name),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Documentation on definitions and their members
+
+use postcard_idl::{pidl::Error, Pidl};
+
+#[test]
+fn docs_are_kept_by_member_path() {
+    let pidl = Pidl::parse_from_str(
+        r#"
+        types {
+            // Not documentation
+            /// A color
+            ///
+            /// With 8 bits per channel
+            struct "Rgb8" {
+                /// Red
+                r "u8"
+                //// Not documentation either
+                g "u8" doc="Green"
+                b "u8"
+            }
+            enum "Shape" doc="Something to draw" {
+                Dot
+                /// A filled in circle
+                Circle {
+                    /// In pixels
+                    radius "u16"
+                }
+            }
+        }
+        "#,
+    )
+    .unwrap();
+    let docs: Vec<_> = pidl
+        .docs
+        .iter()
+        .map(|(path, doc)| (path.as_str(), doc.as_str()))
+        .collect();
+    assert_eq!(
+        docs,
+        [
+            ("Rgb8", "A color\n\nWith 8 bits per channel"),
+            ("Rgb8.g", "Green"),
+            ("Rgb8.r", "Red"),
+            ("Shape", "Something to draw"),
+            ("Shape.Circle", "A filled in circle"),
+            ("Shape.Circle.radius", "In pixels"),
+        ]
+    );
+}
+
+#[test]
+fn docs_are_written_once() {
+    let err = Pidl::parse_from_str(
+        r#"
+        types {
+            /// A number
+            alias "Number" "u32" doc="A number"
+        }
+        "#,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&err, Error::Malformed { what, .. } if what == "doc"),
+        "{err:?}"
+    );
+}
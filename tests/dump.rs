@@ -0,0 +1,112 @@
+//! The exported form of the resolved schema
+
+use postcard_idl::{
+    generate::dump::{generate_json, generate_yaml},
+    pidl::export::EXPORT_VERSION,
+    Pidl,
+};
+use serde_json::Value;
+
+const IDL: &str = r#"types {
+    alias "Name" "string" max=8
+    enum "Event" {
+        Idle
+        Named { name "Name"; at "u64" }
+    }
+}
+"#;
+
+#[test]
+fn export_has_indices_and_spans() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let json: Value = serde_json::from_str(&generate_json(&pidl)).unwrap();
+    assert_eq!(json["version"], EXPORT_VERSION);
+
+    let event = &json["types"][1];
+    assert_eq!(event["kind"], "enum");
+    let named = &event["variants"][1];
+    assert_eq!(named["name"], "Named");
+    assert_eq!(named["index"], 1);
+    assert_eq!(named["fields"][1]["index"], 1);
+
+    // Aliases are resolved, but keep their name
+    let name = &named["fields"][0];
+    assert_eq!(name["type"]["kind"], "string");
+    assert_eq!(name["type"]["alias"], "Name");
+
+    let span = &named["span"];
+    assert_eq!((&span["line"], &span["column"]), (&5.into(), &9.into()));
+    let offset = span["offset"].as_u64().unwrap() as usize;
+    assert!(IDL[offset..].starts_with("Named {"));
+}
+
+#[test]
+fn yaml_matches_json() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let yaml = generate_yaml(&pidl);
+    assert!(yaml.contains(&format!("version: {EXPORT_VERSION}\n")));
+    assert!(yaml.contains("alias: \"Name\"\n"));
+    assert!(yaml.contains("    kind: \"enum\"\n"));
+}
+
+#[test]
+fn aliases_of_user_types_refer_to_the_type() {
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "Point" { x "i32"; y "i32" }
+            alias "Position" "Point"
+            struct "Marker" { at "Position" }
+        }"#,
+    )
+    .unwrap();
+    let json: Value = serde_json::from_str(&generate_json(&pidl)).unwrap();
+
+    let position = &json["types"][1];
+    assert_eq!(position["name"], "Position");
+    assert_eq!(position["kind"], "alias");
+    assert_eq!(position["type"]["kind"], "ref");
+    assert_eq!(position["type"]["name"], "Point");
+
+    // The alias is the same type, so references are to the type itself
+    let at = &json["types"][2]["fields"][0]["type"];
+    assert_eq!(at["kind"], "ref");
+    assert_eq!(at["name"], "Point");
+    assert!(at.get("alias").is_none());
+}
+
+#[test]
+fn protocols_list_every_type_they_send() {
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "Status" { up "bool" }
+        }
+        endpoints { endpoint "Get" path="get" req="()" resp="option<Status>" }
+        topics { topic "Ticks" path="ticks" ty="u32" direction="out" }
+        protocol "Device" {
+            endpoints "Get"
+            topics "Ticks"
+        }"#,
+    )
+    .unwrap();
+    let json: Value = serde_json::from_str(&generate_json(&pidl)).unwrap();
+
+    let device = &json["protocols"][0];
+    assert_eq!(device["endpoints"], serde_json::json!(["Get"]));
+    assert_eq!(device["topics"], serde_json::json!(["Ticks"]));
+    let kinds: Vec<_> = device["types"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| (t["kind"].as_str().unwrap(), t["name"].as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            ("unit", None),
+            ("option", None),
+            ("ref", Some("Status")),
+            ("bool", None),
+            ("u32", None),
+        ]
+    );
+}
@@ -0,0 +1,112 @@
+//! Writing IDL from schemas, and reading it back
+
+use postcard_idl::{
+    pidl::{compare, emit::emit, schema_key, Severity},
+    Pidl,
+};
+use postcard_schema::Schema;
+use serde::Serialize;
+
+#[allow(dead_code)]
+#[derive(Serialize, Schema)]
+struct Reading {
+    id: u32,
+    values: Vec<i16>,
+    extra: Option<(u64, bool)>,
+    pair: [char; 2],
+    color: Rgb,
+}
+
+#[allow(dead_code)]
+#[derive(Serialize, Schema)]
+struct Rgb(u8, u8, u8);
+
+#[allow(dead_code)]
+#[derive(Serialize, Schema)]
+enum Event {
+    Idle,
+    Read(Reading),
+    Moved { x: i32, y: i32 },
+}
+
+#[test]
+fn rust_types_are_written_as_idl() {
+    let idl = emit(&[Event::SCHEMA.into()]).unwrap();
+    let expected = r#"types {
+    enum "Event" {
+        Idle
+        Read "Reading"
+        Moved {
+            x "i32"
+            y "i32"
+        }
+    }
+    struct "Reading" {
+        id     "u32"
+        values "[i16]"
+        extra  "option<(u64, bool)>"
+        pair   "[char; 2]"
+        color  "Rgb"
+    }
+    struct "Rgb" "(u8, u8, u8)"
+}
+"#;
+    assert_eq!(idl, expected);
+
+    // And it reads back as the same schema, apart from the names of builtin
+    // types, which keys don't include
+    let pidl = Pidl::parse_from_str(&idl).unwrap();
+    let event = pidl.types.iter().find(|t| t.name == "Event").unwrap();
+    assert_eq!(
+        schema_key("event", event),
+        schema_key("event", &Event::SCHEMA.into())
+    );
+}
+
+#[test]
+fn idl_round_trips() {
+    let src = include_str!("rust_std/types.kdl");
+    let old = Pidl::parse_from_str(src).unwrap();
+    let new = Pidl::parse_from_str(&emit(&old.types).unwrap()).unwrap();
+    // Length limits are lost, but nothing else changes
+    let changes = compare(&old, &new);
+    assert!(
+        changes.iter().all(|c| c.severity == Severity::Compatible),
+        "{changes:?}"
+    );
+    assert_eq!(old.types.len(), new.types.len());
+}
+
+#[test]
+fn shadowed_names_round_trip() {
+    let src = r#"types {
+    struct "Status" { a "u8" }
+    module "telemetry" {
+        struct "Status" { b "u16" }
+        struct "Reading" { s "super::Status"; t "Status" }
+    }
+}"#;
+    let old = Pidl::parse_from_str(src).unwrap();
+    let idl = emit(&old.types).unwrap();
+    assert!(idl.contains(r#"s "super::Status""#), "{idl}");
+    let new = Pidl::parse_from_str(&idl).unwrap();
+    assert_eq!(old.types, new.types);
+}
+
+#[test]
+fn members_must_be_rust_identifiers() {
+    use postcard_idl::pidl::emit::Error;
+    use postcard_schema::schema::owned::OwnedDataModelType;
+
+    // e.g. `r#type` in Rust, which the schema names `type`
+    let mut pidl = Pidl::parse_from_str(r#"types { struct "Item" { kind "u8" } }"#).unwrap();
+    let OwnedDataModelType::Struct(fields) = &mut pidl.types[0].ty else {
+        panic!();
+    };
+    fields[0].name = "type".into();
+    let err = emit(&pidl.types).unwrap_err();
+    assert!(
+        matches!(&err, Error::BadMember { owner, kind: "field", name } if owner == "Item" && name == "type"),
+        "{err:?}"
+    );
+}
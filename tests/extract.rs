@@ -0,0 +1,83 @@
+//! Extracting IDL from the Rust crate in `tests/extract`
+
+use std::path::Path;
+
+use postcard_idl::{pidl::extract::extract, Pidl};
+
+#[test]
+fn derived_types_are_extracted() {
+    let extracted = extract(Path::new("tests/extract")).unwrap();
+    let expected = r#"types {
+    struct "Rgb8" {
+        r "u8"
+        g "u8"
+        b "u8"
+    }
+    enum "Command" {
+        Stop
+        Say "string"
+        Blink "(Rgb8, u16)"
+        Send {
+            to      "string"
+            payload "[u8]"
+        }
+    }
+    enum "Power" {
+        On
+        Off
+    }
+    enum "Control" {
+        Loop
+        Stop
+    }
+    struct "Status" {
+        label    "string"
+        color    "Rgb8"
+        readings "[i16; 4]"
+        limits   "option<(u32, u32)>"
+    }
+}
+"#;
+    assert_eq!(extracted.idl, expected);
+    Pidl::parse_from_str(&extracted.idl).unwrap();
+}
+
+#[test]
+fn unsupported_types_are_skipped() {
+    let extracted = extract(Path::new("tests/extract")).unwrap();
+    let skipped: Vec<_> = extracted
+        .skipped
+        .iter()
+        .map(|s| (s.name.as_str(), s.line, s.reason.as_str()))
+        .collect();
+    assert_eq!(
+        skipped,
+        [
+            (
+                "Wrapper",
+                30,
+                "it is generic over `T`, and IDL types can't be"
+            ),
+            (
+                "UsesWrapper",
+                33,
+                "`Wrapper<u8>` is generic, and IDL types can't be"
+            ),
+            (
+                "Mode",
+                47,
+                "the variant `Debug` is skipped, which changes the index of `Active`"
+            ),
+            (
+                "Item",
+                62,
+                "its field `type` is a Rust keyword or not an identifier, which IDL names can't be"
+            ),
+            (
+                "Tagged",
+                16,
+                "`#[serde(flatten)]` changes the encoding, and has no IDL equivalent"
+            ),
+        ]
+    );
+}
@@ -0,0 +1,70 @@
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+pub mod telemetry;
+
+#[derive(Debug, Serialize, Deserialize, Schema)]
+pub struct Rgb8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub enum Command {
+    Stop,
+    Say(String),
+    Blink(Rgb8, u16),
+    Send {
+        to: String,
+        #[serde(rename = "payload")]
+        data: Vec<u8>,
+    },
+}
+
+/// No `Schema`, so not extracted
+#[derive(Serialize, Deserialize)]
+pub struct Private(u32);
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct Wrapper<T>(T);
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct UsesWrapper {
+    pub inner: Wrapper<u8>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub enum Power {
+    On,
+    Off,
+    /// Never sent, so the others keep their index
+    #[serde(skip)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub enum Mode {
+    Idle,
+    #[serde(skip)]
+    Debug,
+    Active,
+}
+
+/// Raw identifiers are written without the `r#`
+#[derive(Serialize, Deserialize, Schema)]
+pub enum Control {
+    r#Loop,
+    Stop,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct Item {
+    pub r#type: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    #[derive(serde::Serialize, serde::Deserialize, postcard_schema::Schema)]
+    struct OnlyInTests;
+}
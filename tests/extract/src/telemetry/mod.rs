@@ -0,0 +1,19 @@
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "schema", derive(Schema))]
+#[derive(Serialize, Deserialize)]
+pub struct Status {
+    pub label: heapless::String<32>,
+    pub color: crate::Rgb8,
+    pub readings: [i16; 4],
+    pub limits: Option<(u32, u32)>,
+    #[serde(skip)]
+    pub cached: bool,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct Tagged {
+    #[serde(flatten)]
+    pub status: Status,
+}
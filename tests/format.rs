@@ -0,0 +1,74 @@
+//! The canonical formatting of IDL files, `pidl fmt`
+
+use postcard_idl::pidl::format_str;
+
+#[test]
+fn documents_are_formatted_canonically() {
+    let src = r##"// Kept
+types   {
+  struct "Reading" {
+ at u64 // when
+    "millivolts"   "u16"
+
+
+    note #"string"#
+}
+/// Docs too
+  alias    Name   "string"  max=16
+}
+"##;
+    let expected = r#"// Kept
+types {
+    struct "Reading" {
+        at         "u64" // when
+        millivolts "u16"
+
+        note "string"
+    }
+    /// Docs too
+    alias "Name" "string" max=16
+}
+"#;
+    assert_eq!(format_str(src).unwrap(), expected);
+}
+
+#[test]
+fn import_names_are_bare() {
+    let src = "use   \"../common.kdl\"  \"as\"  \"common\"\nuse \"units.kdl\"\n";
+    let expected = "use \"../common.kdl\" as common\nuse \"units.kdl\"\n";
+    assert_eq!(format_str(src).unwrap(), expected);
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    let files = [
+        "tests/rust_std/types.kdl",
+        "tests/rust_heapless/types.kdl",
+        "tests/imports/main.kdl",
+        "tests/imports/common/header.kdl",
+    ];
+    for file in files {
+        let src = std::fs::read_to_string(file).unwrap();
+        let once = format_str(&src).unwrap();
+        assert_eq!(format_str(&once).unwrap(), once, "{file}");
+        // The checked in files are formatted already
+        assert_eq!(once, src, "{file}");
+    }
+}
+
+#[test]
+fn comments_after_blocks_are_kept() {
+    // `kdl` can't parse a node after `} // comment`, so these go on the next
+    // line. Newer versions of `kdl` drop these comments, so it is pinned.
+    let src = "types {\n    struct \"A\" {\n        a \"u8\"\n    } // after A\n} // after types\n";
+    let expected =
+        "types {\n    struct \"A\" {\n        a \"u8\"\n    }\n    // after A\n}\n// after types\n";
+    let once = format_str(src).unwrap();
+    assert_eq!(once, expected);
+    assert_eq!(format_str(&once).unwrap(), once);
+}
+
+#[test]
+fn invalid_kdl_is_an_error() {
+    assert!(format_str("types {").is_err());
+}
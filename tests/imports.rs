@@ -0,0 +1,93 @@
+//! Projects of several files, which import each other with `use`
+
+use postcard_idl::{
+    generate::rust_std::{generate_rust_std, Options},
+    pidl::Error,
+    Pidl,
+};
+
+fn path(name: &str) -> String {
+    format!("{}/tests/imports/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+#[test]
+fn imported_types_are_referenced_by_alias() {
+    let pidl = Pidl::parse_from_file(path("main.kdl")).unwrap();
+    let names: Vec<_> = pidl.types.iter().map(|t| t.name.as_str()).collect();
+    // Imports come first, and a file imported twice is loaded once, under
+    // the shortest of its paths
+    assert_eq!(names, ["units::Millivolts", "common::Header", "Packet"]);
+    let header = pidl
+        .types
+        .iter()
+        .find(|t| t.name == "common::Header")
+        .unwrap();
+    assert_eq!(pidl.endpoints[0].resp, *header);
+}
+
+#[test]
+fn import_cycles_are_errors() {
+    let err = Pidl::parse_from_file(path("cycle_a.kdl")).unwrap_err();
+    let Error::InFile { path, errors, .. } = &err else {
+        panic!("{err:?}");
+    };
+    assert!(path.ends_with("cycle_b.kdl"), "{path}");
+    let Error::ImportCycle { path, .. } = &errors[0] else {
+        panic!("{errors:?}");
+    };
+    let files: Vec<_> = path
+        .split(" -> ")
+        .map(|p| p.rsplit('/').next().unwrap())
+        .collect();
+    assert_eq!(files, ["cycle_a.kdl", "cycle_b.kdl", "cycle_a.kdl"]);
+}
+
+#[test]
+fn missing_imports_are_errors() {
+    let err = Pidl::parse_from_file(path("missing.kdl")).unwrap_err();
+    let Error::InFile { path, errors, .. } = &err else {
+        panic!("{err:?}");
+    };
+    assert!(path.ends_with("missing.kdl"), "{path}");
+    let Error::Io { path, span, .. } = &errors[0] else {
+        panic!("{errors:?}");
+    };
+    assert!(path.ends_with("nowhere.kdl"), "{path}");
+    assert!(span.is_some());
+}
+
+#[test]
+fn imports_need_a_file() {
+    let err = Pidl::parse_from_str(r#"use "units.kdl"; types { alias "A" "u8" }"#).unwrap_err();
+    assert!(matches!(err, Error::Malformed { .. }), "{err:?}");
+}
+
+#[test]
+fn imported_types_are_named_by_their_path() {
+    let pidl = Pidl::parse_from_file(path("two_headers.kdl")).unwrap();
+    let names: Vec<_> = pidl.types.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, ["a::Header", "b::Header", "Packet"]);
+
+    // Like modules, each import gets a module of its own
+    let generated = generate_rust_std(&pidl, &Options::default()).to_string();
+    assert!(generated.contains("pub mod a {"), "{generated}");
+    assert!(generated.contains("pub mod b {"), "{generated}");
+    assert!(generated.contains("pub a: a::Header,"), "{generated}");
+    assert!(generated.contains("pub b: b::Header,"), "{generated}");
+}
+
+#[test]
+fn imports_and_modules_have_different_names() {
+    let err = Pidl::parse_from_file(path("module_clash.kdl")).unwrap_err();
+    let Error::InFile { path, errors, .. } = &err else {
+        panic!("{err:?}");
+    };
+    assert!(path.ends_with("module_clash.kdl"), "{path}");
+    let Error::BadName { name, reason, .. } = &errors[0] else {
+        panic!("{errors:?}");
+    };
+    assert_eq!(
+        (name.as_str(), reason.as_str()),
+        ("units", "a module with this name already exists")
+    );
+}
@@ -0,0 +1,127 @@
+//! The Markdown protocol reference
+
+use postcard_idl::{generate::markdown::generate_markdown, Pidl};
+
+const IDL: &str = r#"types {
+    alias "Name" "string" min=6 max=8
+    /// Something that happened
+    enum "Event" {
+        Idle
+        Named { name "Name"; at "i64" }
+    }
+    struct "Log" {
+        events "[Event]" max=4
+        /// Written by `pidl`, with | and *
+        note "option<Log>"
+    }
+}
+topics {
+    topic "Events" path="event/v1" ty="Event" direction="out"
+}
+"#;
+
+#[test]
+fn types_are_linked_to_their_anchors() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let doc = generate_markdown(&pidl);
+
+    assert!(doc.contains("<a id=\"type-Event\"></a>\n\n### `Event`\n\nSomething that happened\n"));
+    assert!(doc.contains("| 1 | `Named` | the fields below |  |"));
+    assert!(
+        doc.contains("| `name` | [`Name`](#type-Name) | varint length in bytes, then UTF-8 |  |")
+    );
+    assert!(doc.contains("| `events` | `[`[`Event`](#type-Event)`]` |"));
+    assert!(doc.contains("| `note` | `option<`[`Log`](#type-Log)`>` |"));
+    assert!(doc.contains("Written by `pidl`, with \\| and \\*"));
+    assert!(doc.contains("<a id=\"topic-Events\"></a>"));
+    assert!(doc.contains("are [`Event`](#type-Event), and are sent from the server to the client"));
+}
+
+#[test]
+fn types_have_sample_encodings() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let doc = generate_markdown(&pidl);
+
+    // The variant with contents is preferred, and strings respect `min=`
+    assert!(doc.contains(
+        "Sample: `Event::Named { name: \"abcdef\", at: -300 }`, which is encoded as:\n\n\
+         ```text\n01 06 61 62 63 64 65 66 d7 04\n```"
+    ));
+    // Recursion is avoided
+    assert!(doc.contains(
+        "Sample: `Log { events: [Event::Named { name: \"abcdef\", at: -300 }], note: None }`"
+    ));
+}
+
+#[test]
+fn aliases_of_user_types_link_to_the_type() {
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "Log" {
+                /// The newest first
+                events "[u32]" max=4
+            }
+            /// Another name for a log
+            alias "History" "Log"
+            struct "Device" {
+                history "History"
+            }
+        }"#,
+    )
+    .unwrap();
+    let doc = generate_markdown(&pidl);
+
+    assert!(doc.contains(
+        "### `History`\n\nAnother name for a log\n\nThe same type as [`Log`](#type-Log).\n"
+    ));
+    assert!(doc.contains("| `history` | [`Log`](#type-Log) |"));
+    assert!(doc.contains("| `events` | `[u32]` | varint length, then each item | Length at most 4. The newest first |"));
+}
+
+#[test]
+fn anchors_are_unique() {
+    // Endpoints and topics can't share the names of types, but everything
+    // else can, and names may only differ in case
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "Status" { up "bool" }
+            struct "STATUS" { up "bool" }
+        }
+        endpoints {
+            endpoint "Reading" path="reading/get" req="()" resp="Status"
+        }
+        topics {
+            topic "READING" path="reading" ty="STATUS" direction="out"
+        }
+        protocol "Status" {
+            endpoints "Reading"
+        }
+        protocol "Reading" {
+            topics "READING"
+        }"#,
+    )
+    .unwrap();
+    let doc = generate_markdown(&pidl);
+
+    for id in [
+        "type-Status",
+        "type-STATUS",
+        "endpoint-Reading",
+        "topic-READING",
+        "protocol-Status",
+        "protocol-Reading",
+    ] {
+        assert_eq!(doc.matches(&format!("<a id=\"{id}\">")).count(), 1, "{id}");
+    }
+    assert!(doc.contains("- Endpoints: [`Reading`](#endpoint-Reading)"));
+}
+
+#[test]
+fn tuples_and_arrays_are_written_as_declared() {
+    let pidl =
+        Pidl::parse_from_str(r#"types { struct "Pairs" { tuple "(u8, u8)"; array "[u8; 2]" } }"#)
+            .unwrap();
+    let doc = generate_markdown(&pidl);
+    assert!(doc.contains("| `tuple` | `(u8, u8)` |"), "{doc}");
+    assert!(doc.contains("| `array` | `[u8; 2]` |"), "{doc}");
+}
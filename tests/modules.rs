@@ -0,0 +1,92 @@
+//! Type definitions in `module` blocks
+
+use postcard_idl::{
+    generate::rust_std::{generate_rust_std, Options},
+    pidl::Error,
+    Pidl,
+};
+
+const IDL: &str = r#"types {
+    struct "Status" { up "bool" }
+    module "power" {
+        struct "Status" { millivolts "u16" }
+        struct "Report" { power "Status"; system "super::Status" }
+        module "battery" {
+            struct "Cell" { status "Status" }
+        }
+    }
+    module "radio" {
+        struct "Status" { rssi "i8" }
+        struct "Report" { radio "Status"; cell "power::battery::Cell" }
+    }
+}
+"#;
+
+#[test]
+fn modules_have_their_own_names() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let names: Vec<_> = pidl.types.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "Status",
+            "power::Status",
+            "power::Report",
+            "power::battery::Cell",
+            "radio::Status",
+            "radio::Report",
+        ]
+    );
+}
+
+#[test]
+fn names_are_found_in_enclosing_modules() {
+    use postcard_schema::schema::owned::OwnedDataModelType;
+
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let field_tys = |name: &str| {
+        let ty = pidl.types.iter().find(|t| t.name == name).unwrap();
+        let OwnedDataModelType::Struct(fields) = &ty.ty else {
+            panic!("{ty:?}");
+        };
+        fields.iter().map(|f| f.ty.name.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(field_tys("power::Report"), ["power::Status", "Status"]);
+    // The innermost module first
+    assert_eq!(field_tys("power::battery::Cell"), ["power::Status"]);
+    assert_eq!(
+        field_tys("radio::Report"),
+        ["radio::Status", "power::battery::Cell"]
+    );
+}
+
+#[test]
+fn names_are_unique_within_a_module() {
+    let err = Pidl::parse_from_str(
+        r#"types {
+            module "power" { struct "Status" { up "bool" } }
+            module "power" { struct "Other" { up "bool" } }
+        }"#,
+    )
+    .unwrap_err();
+    assert!(
+        matches!(&err, Error::DuplicateMember { kind: "module", name, .. } if name == "power"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn modules_are_generated_as_rust_modules() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let generated = generate_rust_std(&pidl, &Options::default()).to_string();
+    for expected in [
+        "pub mod power {",
+        "pub mod battery {",
+        "pub power: Status,",
+        "pub system: super::Status,",
+        "pub status: super::Status,",
+        "pub cell: super::power::battery::Cell,",
+    ] {
+        assert!(generated.contains(expected), "{expected}\n{generated}");
+    }
+}
@@ -0,0 +1,213 @@
+//! Resolving type references, and reporting the ones that can't be
+
+use postcard_idl::{pidl::Error, Pidl};
+
+/// The errors of a document that can't be resolved
+fn unresolvable(src: &str) -> Vec<Error> {
+    match Pidl::parse_from_str(src).unwrap_err() {
+        Error::Unresolvable { errors, .. } => errors,
+        err => panic!("{err:?}"),
+    }
+}
+
+#[test]
+fn every_unknown_type_is_reported() {
+    let errors = unresolvable(
+        r#"types {
+            struct "A" { x "Missing" }
+            struct "B" { y "[Absent]"; z "A" }
+        }"#,
+    );
+    let names: Vec<_> = errors
+        .iter()
+        .map(|e| match e {
+            Error::UnknownType { name, defn, .. } => (name.as_str(), defn.as_str()),
+            e => panic!("{e:?}"),
+        })
+        .collect();
+    assert_eq!(names, [("Missing", "A"), ("Absent", "B")]);
+}
+
+#[test]
+fn unknown_types_suggest_close_names() {
+    let errors = unresolvable(
+        r#"types {
+            struct "Status" { up "bool" }
+            struct "A" { s "Stats"; n "u33"; b "BOOL" }
+        }"#,
+    );
+    let helps: Vec<_> = errors
+        .iter()
+        .map(|e| match e {
+            Error::UnknownType { name, help, .. } => (name.as_str(), help.as_deref()),
+            e => panic!("{e:?}"),
+        })
+        .collect();
+    assert_eq!(
+        helps,
+        [
+            ("Stats", Some("did you mean `Status`?")),
+            ("u33", Some("did you mean `u32`?")),
+            ("BOOL", Some("did you mean `bool`?")),
+        ]
+    );
+}
+
+#[test]
+fn cycles_are_reported_as_cycles() {
+    let errors = unresolvable(
+        r#"types {
+            struct "A" { b "B" }
+            struct "B" { a "(u8, A)" }
+        }"#,
+    );
+    let Error::TypeCycle { path, labels, .. } = &errors[0] else {
+        panic!("{errors:?}");
+    };
+    assert_eq!(path, "A -> B -> A");
+    assert_eq!(labels.len(), 2);
+    assert!(errors
+        .iter()
+        .all(|e| !matches!(e, Error::UnknownType { .. })));
+}
+
+#[test]
+fn recursive_references_are_stubs() {
+    use postcard_schema::schema::owned::{OwnedDataModelType, OwnedNamedType};
+
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "Node" { value "u32"; children "[Node]" }
+            enum "Expr" { Lit "i64"; Neg "Box"; All "[Expr]" }
+            struct "Box" { inner "option<Expr>" }
+        }"#,
+    )
+    .unwrap();
+    let find = |name: &str| pidl.types.iter().find(|t| t.name == name).unwrap();
+
+    // The back reference has the name of the type, and no body
+    let OwnedDataModelType::Struct(fields) = &find("Node").ty else {
+        panic!();
+    };
+    let OwnedDataModelType::Seq(child) = &fields[1].ty.ty else {
+        panic!();
+    };
+    let stub = OwnedNamedType {
+        name: "Node".into(),
+        ty: OwnedDataModelType::Struct(vec![]),
+    };
+    assert_eq!(**child, stub);
+
+    // Through other types, the first type on the way back is the stub
+    let OwnedDataModelType::Struct(fields) = &find("Box").ty else {
+        panic!();
+    };
+    let OwnedDataModelType::Option(expr) = &fields[0].ty.ty else {
+        panic!();
+    };
+    let OwnedDataModelType::Enum(variants) = &expr.ty else {
+        panic!("{expr:?}");
+    };
+    assert_eq!(variants.len(), 3);
+    assert!(format!("{variants:?}").contains("name: \"Box\", ty: Struct([])"));
+}
+
+#[test]
+fn recursion_needs_indirection() {
+    let errors = unresolvable(
+        r#"types {
+            struct "Node" { next "Node" }
+        }"#,
+    );
+    let Error::TypeCycle { path, help, .. } = &errors[0] else {
+        panic!("{errors:?}");
+    };
+    assert_eq!(path, "Node -> Node");
+    assert!(help.contains("option<...>"), "{help}");
+}
+
+#[test]
+fn newtype_and_tuple_structs_keep_their_names() {
+    use postcard_schema::schema::owned::{OwnedDataModelType, OwnedDataModelVariant};
+
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "Id" "u32"
+            struct "Point" "(i32, i32)"
+            alias "DeviceId" "Id"
+            struct "Device" { id "DeviceId"; at "Point" }
+            enum "Event" { Moved "Point"; Removed "Id" }
+        }"#,
+    )
+    .unwrap();
+    let find = |name: &str| pidl.types.iter().find(|t| t.name == name).unwrap();
+    let id = find("Id");
+    let point = find("Point");
+    assert!(matches!(id.ty, OwnedDataModelType::NewtypeStruct(_)));
+    assert!(matches!(&point.ty, OwnedDataModelType::TupleStruct(items) if items.len() == 2));
+
+    let OwnedDataModelType::Struct(fields) = &find("Device").ty else {
+        panic!();
+    };
+    assert_eq!(fields[0].ty, *id);
+    assert_eq!(fields[1].ty, *point);
+    let OwnedDataModelType::Enum(variants) = &find("Event").ty else {
+        panic!();
+    };
+    assert_eq!(
+        variants[0].ty,
+        OwnedDataModelVariant::NewtypeVariant(Box::new(point.clone()))
+    );
+    assert_eq!(
+        variants[1].ty,
+        OwnedDataModelVariant::NewtypeVariant(Box::new(id.clone()))
+    );
+}
+
+#[test]
+fn shared_types_resolve_the_same_everywhere() {
+    // Each level uses the next twice, so there are 2^12 paths to `L12`
+    let mut src = String::from("types {\n");
+    for i in 0..12 {
+        let next = i + 1;
+        src += &format!("struct \"L{i}\" {{ a \"L{next}\"; b \"(L{next}, u8)\" }}\n");
+    }
+    src += "struct \"L12\" { x \"u8\" }\n}";
+    let pidl = Pidl::parse_from_str(&src).unwrap();
+    let find = |name: &str| pidl.types.iter().find(|t| t.name == name).unwrap();
+    assert!(format!("{:?}", find("L0")).contains(&format!("{:?}", find("L11"))));
+}
+
+#[test]
+fn stubs_depend_on_where_the_type_is_used() {
+    use postcard_schema::schema::owned::OwnedDataModelType;
+
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "A" { b "B"; c "C" }
+            struct "B" { d "[D]" }
+            struct "C" { d "D" }
+            struct "D" { b "option<B>" }
+        }"#,
+    )
+    .unwrap();
+    let find = |name: &str| pidl.types.iter().find(|t| t.name == name).unwrap();
+    let OwnedDataModelType::Struct(fields) = &find("A").ty else {
+        panic!();
+    };
+    // Within `b`, `D` refers back to `B`, but within `c` it doesn't
+    let within_b = format!("{:?}", fields[0].ty);
+    let within_c = format!("{:?}", fields[1].ty);
+    assert!(
+        within_b.contains("name: \"B\", ty: Struct([])"),
+        "{within_b}"
+    );
+    assert!(
+        !within_c.contains("name: \"B\", ty: Struct([])"),
+        "{within_c}"
+    );
+    assert!(
+        within_c.contains("name: \"D\", ty: Struct([])"),
+        "{within_c}"
+    );
+}
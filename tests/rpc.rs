@@ -0,0 +1,377 @@
+//! Endpoints, topics and protocols
+//!
+//! The tables generated for `tests/rpc/rpc.kdl` are checked in and compiled
+//! as part of this test, run with `PIDL_BLESS=1` to update them.
+
+use postcard_idl::{
+    generate::rust_std::{generate_rust_std, Options},
+    pidl::Error,
+    Pidl,
+};
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+#[path = "rpc/rpc.rs"]
+mod rpc;
+
+const INPUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rpc/rpc.kdl");
+const OUTPUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rpc/rpc.rs");
+
+#[test]
+fn generated_is_current() {
+    let pidl = Pidl::parse_from_file(INPUT).unwrap();
+    let generated = generate_rust_std(&pidl, &Options::default()).to_string();
+    if std::env::var_os("PIDL_BLESS").is_some() {
+        std::fs::write(OUTPUT, &generated).unwrap();
+    }
+    let current = std::fs::read_to_string(OUTPUT).unwrap();
+    assert!(
+        generated == current,
+        "`tests/rpc/rpc.rs` is out of date, run with `PIDL_BLESS=1` to update it"
+    );
+}
+
+#[test]
+fn generated_tables_match_the_idl() {
+    use postcard_rpc::{Endpoint, Topic};
+
+    assert_eq!(<rpc::GetStatus as Endpoint>::PATH, "status/get");
+    assert_eq!(<rpc::Log as Topic>::PATH, "log");
+    assert_eq!(<rpc::boot::Reboot as Endpoint>::PATH, "boot/reboot");
+    assert_eq!(
+        <rpc::Sample as Endpoint>::RESP_KEY.to_bytes(),
+        rpc::SAMPLE_RESP_KEY
+    );
+    let paths: Vec<_> = rpc::ENDPOINT_LIST.endpoints.iter().map(|e| e.0).collect();
+    assert!(paths.contains(&"status/name"), "{paths:?}");
+    let paths: Vec<_> = rpc::TOPICS_OUT_LIST.topics.iter().map(|t| t.0).collect();
+    assert!(paths.contains(&"status/v1"), "{paths:?}");
+    assert!(!paths.contains(&"heartbeat"), "{paths:?}");
+    assert_eq!(
+        rpc::boot::TOPICS_OUT_LIST.topics.last().unwrap().0,
+        "heartbeat"
+    );
+}
+
+const RPC: &str = r#"types {
+    struct "Status" { up "bool"; name "string" }
+}
+endpoints {
+    endpoint "GetStatus" path="status/get" req="()" resp="Status"
+    endpoint "Rename" path="status/name" req="string" resp="option<Status>"
+}
+topics {
+    topic "Log" path="log" ty="string" direction="in"
+    topic "StatusEvents" path="status/v1" ty="Status" direction="out"
+}
+"#;
+
+fn generated(src: &str) -> String {
+    let pidl = Pidl::parse_from_str(src).unwrap();
+    generate_rust_std(&pidl, &Options::default()).to_string()
+}
+
+const TYPES: &str = r#"types {
+    enum "Tree" {
+        Leaf "u32"
+        Node "[Tree]"
+    }
+    struct "Forest" {
+        trees "[Tree]"
+    }
+}
+"#;
+
+#[test]
+fn recursive_messages_are_errors() {
+    let rpc = [
+        r#"endpoints { endpoint "Plant" path="plant" req="Tree" resp="()" }"#,
+        r#"endpoints { endpoint "Survey" path="survey" req="()" resp="option<Forest>" }"#,
+        r#"topics { topic "Growth" path="growth" ty="[Tree]" direction="out" }"#,
+    ];
+    for rpc in rpc {
+        let src = format!("{TYPES}{rpc}");
+        let err = Pidl::parse_from_str(&src).unwrap_err();
+        let Error::RecursiveMessage { ty, span, .. } = &err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(ty, "Tree");
+        // The whole endpoint or topic is labelled
+        let node = rpc.split_once("{ ").unwrap().1;
+        assert_eq!(span.offset(), src.find(node).unwrap());
+    }
+}
+
+#[test]
+fn endpoints_have_resolved_messages() {
+    let pidl = Pidl::parse_from_str(RPC).unwrap();
+    let status = pidl.types.iter().find(|t| t.name == "Status").unwrap();
+    let names: Vec<_> = pidl
+        .endpoints
+        .iter()
+        .map(|ep| (ep.name.as_str(), ep.path.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        [("GetStatus", "status/get"), ("Rename", "status/name")]
+    );
+    assert_eq!(pidl.endpoints[0].req.name, "()");
+    assert_eq!(pidl.endpoints[0].resp, *status);
+    assert_eq!(pidl.endpoints[1].resp.name, "Option<Status>");
+
+    // Messages that aren't a single token get an alias
+    let generated = generated(RPC);
+    assert!(generated.contains("pub type RenameResponse = Option<Status>;"));
+    assert!(generated.contains(
+        r#"endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy | RequestTy | ResponseTy     | Path          |
+    | ---------- | --------- | -------------- | ------------- |
+    | GetStatus  | ()        | Status         | "status/get"  |
+    | Rename     | String    | RenameResponse | "status/name" |
+}"#
+    ));
+}
+
+#[test]
+fn endpoints_need_every_property() {
+    let err = Pidl::parse_from_str(
+        r#"types { alias "A" "u8" }
+        endpoints { endpoint "E" path="e" req="A" }"#,
+    )
+    .unwrap_err();
+    let Error::Malformed { what, reason, .. } = &err else {
+        panic!("{err:?}");
+    };
+    assert_eq!(what, "endpoint");
+    assert_eq!(reason, "endpoint needs `path`, `req`, and `resp`");
+}
+
+#[test]
+fn topics_have_a_direction() {
+    use postcard_idl::pidl::TopicDirection;
+
+    let pidl = Pidl::parse_from_str(RPC).unwrap();
+    let topics: Vec<_> = pidl
+        .topics
+        .iter()
+        .map(|tp| (tp.name.as_str(), tp.ty.name.as_str(), tp.direction))
+        .collect();
+    assert_eq!(
+        topics,
+        [
+            ("Log", "str", TopicDirection::ToServer),
+            ("StatusEvents", "Status", TopicDirection::ToClient),
+        ]
+    );
+
+    // A table for each direction
+    let generated = generated(RPC);
+    assert!(generated.contains(
+        r#"topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy | MessageTy | Path  |
+    | ------- | --------- | ----- |
+    | Log     | String    | "log" |
+}"#
+    ));
+    assert!(generated.contains(
+        r#"topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy      | MessageTy | Path        |
+    | ------------ | --------- | ----------- |
+    | StatusEvents | Status    | "status/v1" |
+}"#
+    ));
+}
+
+#[test]
+fn topic_directions_are_in_or_out() {
+    let err = Pidl::parse_from_str(
+        r#"types { alias "A" "u8" }
+        topics { topic "T" path="t" ty="A" direction="up" }"#,
+    )
+    .unwrap_err();
+    let Error::Malformed { reason, help, .. } = &err else {
+        panic!("{err:?}");
+    };
+    assert_eq!(reason, "unknown direction");
+    assert_eq!(
+        help.as_deref(),
+        Some("use `in` for client to server, or `out` for server to client")
+    );
+}
+
+#[test]
+fn protocols_group_endpoints_and_topics() {
+    let src = format!(
+        r#"{RPC}
+protocol "FirmwareV2" {{
+    endpoints "GetStatus"
+    topics "Log"
+}}"#
+    );
+    let pidl = Pidl::parse_from_str(&src).unwrap();
+    let proto = &pidl.protocols[0];
+    assert_eq!(proto.name, "FirmwareV2");
+    assert_eq!(proto.endpoints, [pidl.endpoints[0].clone()]);
+    assert_eq!(proto.topics, [pidl.topics[0].clone()]);
+    let types: Vec<_> = proto.types.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(types, ["()", "Status", "bool", "str"]);
+
+    // The protocol gets a module, and the rest stays at the top level
+    let generated = generated(&src);
+    let (top, module) = generated
+        .split_once("/// The `FirmwareV2` protocol\npub mod firmware_v2 {\n")
+        .unwrap();
+    assert!(top.contains("| Rename     | String    | RenameResponse | \"status/name\" |"));
+    assert!(top.contains("| StatusEvents | Status    | \"status/v1\" |"));
+    assert!(!top.contains("| GetStatus "));
+    assert!(module.contains("    | GetStatus  | ()        | Status     | \"status/get\" |"));
+    assert!(module.contains("    | Log     | String    | \"log\" |"));
+}
+
+#[test]
+fn protocols_refer_to_known_endpoints() {
+    let src = format!("{RPC}protocol \"P\" {{ endpoints \"GetStatuss\" }}");
+    let err = Pidl::parse_from_str(&src).unwrap_err();
+    let Error::Unresolvable { errors, .. } = &err else {
+        panic!("{err:?}");
+    };
+    let Error::UnknownItem {
+        kind, name, help, ..
+    } = &errors[0]
+    else {
+        panic!("{errors:?}");
+    };
+    assert_eq!((*kind, name.as_str()), ("endpoint", "GetStatuss"));
+    assert_eq!(help.as_deref(), Some("did you mean `GetStatus`?"));
+}
+
+#[test]
+fn keys_match_derived_schemas() {
+    use postcard_schema::{key::Key, Schema};
+
+    /// The same as `Status` in the IDL
+    #[derive(Schema)]
+    #[allow(dead_code)]
+    struct Status {
+        up: bool,
+        name: String,
+    }
+
+    let pidl = Pidl::parse_from_str(RPC).unwrap();
+    let [get, rename] = &pidl.endpoints[..] else {
+        panic!();
+    };
+    assert_eq!(get.req_key(), Key::for_path::<()>("status/get"));
+    assert_eq!(get.resp_key(), Key::for_path::<Status>("status/get"));
+    assert_eq!(rename.req_key(), Key::for_path::<String>("status/name"));
+    let key = Key::for_path::<Option<Status>>("status/name");
+    assert_eq!(rename.resp_key(), key);
+    let [log, events] = &pidl.topics[..] else {
+        panic!();
+    };
+    assert_eq!(log.key(), Key::for_path::<String>("log"));
+    assert_eq!(events.key(), Key::for_path::<Status>("status/v1"));
+
+    // The generated constants are the same keys
+    let bytes = key
+        .to_bytes()
+        .iter()
+        .map(|b| format!("0x{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let konst = format!("pub const RENAME_RESP_KEY: [u8; 8] = [{bytes}];");
+    assert!(generated(RPC).contains(&konst), "{konst}");
+}
+
+#[test]
+fn generated_names_are_unique() {
+    let cases = [
+        (
+            r#"types { struct "GetStatusResponse" { a "u8" } }
+            endpoints { endpoint "GetStatus" path="status" req="()" resp="(u8, u16)" }"#,
+            "the generated `GetStatusResponse` is already a type",
+        ),
+        (
+            r#"types { struct "Status" { a "u8" } }
+            topics { topic "Status" path="status" ty="u8" direction="out" }"#,
+            "the generated `Status` is already a type",
+        ),
+        (
+            r#"types { alias "A" "u8" }
+            endpoints {
+                endpoint "Get" path="get" req="()" resp="u8"
+                endpoint "GetResponse" path="get/resp" req="()" resp="u8"
+            }"#,
+            "the generated `GetResponse` is already used by the endpoint `Get`",
+        ),
+        (
+            r#"types { alias "A" "u8" }
+            endpoints { endpoint "GetStatus" path="status" req="()" resp="u8" }
+            topics { topic "GetStatusReq" path="status" ty="u8" direction="out" }"#,
+            "the generated constant `GET_STATUS_REQ_KEY` is already used by the endpoint \
+             `GetStatus`",
+        ),
+    ];
+    for (src, expected) in cases {
+        let err = Pidl::parse_from_str(src).unwrap_err();
+        let Error::Malformed { reason, .. } = &err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(reason, expected);
+    }
+
+    // Types in modules are out of the way
+    Pidl::parse_from_str(
+        r#"types { module "m" { struct "GetStatusResponse" { a "u8" } } }
+        endpoints { endpoint "GetStatus" path="status" req="()" resp="(u8, u16)" }"#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn protocol_modules_are_unique() {
+    let cases = [
+        (
+            r#"types { module "boot" { alias "A" "u8" } }"#,
+            "Boot",
+            "the generated module `boot` is already a module of types",
+        ),
+        (
+            r#"types { alias "A" "u8" }"#,
+            "Borrowed",
+            "the generated module `borrowed` is already the module of borrowed types",
+        ),
+    ];
+    for (types, name, expected) in cases {
+        let src = format!(
+            r#"{types}
+            endpoints {{ endpoint "Get" path="get" req="()" resp="u8" }}
+            protocol "{name}" {{ endpoints "Get" }}"#
+        );
+        let err = Pidl::parse_from_str(&src).unwrap_err();
+        let Error::Malformed { reason, .. } = &err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(reason, expected);
+    }
+
+    let err = Pidl::parse_from_str(
+        r#"types { alias "A" "u8" }
+        endpoints { endpoint "Get" path="get" req="()" resp="u8" }
+        protocol "BootV2" { endpoints "Get" }
+        protocol "Boot_v2" { endpoints "Get" }"#,
+    )
+    .unwrap_err();
+    let Error::Malformed { reason, .. } = &err else {
+        panic!("{err:?}");
+    };
+    assert_eq!(
+        reason,
+        "the generated module `boot_v2` is already the module of the protocol `BootV2`"
+    );
+}
@@ -0,0 +1,94 @@
+// @generated by pidl, do not edit
+// source hash: d6d948fe365bca63
+
+use postcard_rpc::{endpoints, topics};
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+pub type RenameResponse = Option<Status>;
+pub type SampleResponse = (u8, [i16; 4]);
+pub type LogMessage = log::Line;
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct Status {
+    pub up: bool,
+    pub name: String,
+}
+
+pub mod log {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Schema)]
+    pub struct Line {
+        pub level: u8,
+        pub text: String,
+    }
+}
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy | RequestTy | ResponseTy     | Path          |
+    | ---------- | --------- | -------------- | ------------- |
+    | GetStatus  | ()        | Status         | "status/get"  |
+    | Rename     | String    | RenameResponse | "status/name" |
+    | Sample     | u8        | SampleResponse | "sample"      |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy | MessageTy  | Path  |
+    | ------- | ---------- | ----- |
+    | Log     | LogMessage | "log" |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy      | MessageTy | Path        |
+    | ------------ | --------- | ----------- |
+    | StatusEvents | Status    | "status/v1" |
+}
+
+/// The `Boot` protocol
+pub mod boot {
+    use super::*;
+
+    endpoints! {
+        list = ENDPOINT_LIST;
+        | EndpointTy | RequestTy | ResponseTy | Path          |
+        | ---------- | --------- | ---------- | ------------- |
+        | Reboot     | ()        | ()         | "boot/reboot" |
+    }
+
+    topics! {
+        list = TOPICS_OUT_LIST;
+        direction = postcard_rpc::TopicDirection::ToClient;
+        | TopicTy   | MessageTy | Path        |
+        | --------- | --------- | ----------- |
+        | Heartbeat | u32       | "heartbeat" |
+    }
+}
+
+/// Key of the `GetStatus` request, at "status/get"
+pub const GET_STATUS_REQ_KEY: [u8; 8] = [0x81, 0xF8, 0x98, 0x0D, 0xCE, 0x15, 0x8E, 0xEF];
+/// Key of the `GetStatus` response, at "status/get"
+pub const GET_STATUS_RESP_KEY: [u8; 8] = [0x03, 0xB4, 0xCF, 0x5B, 0xCF, 0xE7, 0xAA, 0x5C];
+/// Key of the `Rename` request, at "status/name"
+pub const RENAME_REQ_KEY: [u8; 8] = [0x52, 0x7C, 0xCC, 0xB7, 0x3B, 0x8A, 0xCC, 0x74];
+/// Key of the `Rename` response, at "status/name"
+pub const RENAME_RESP_KEY: [u8; 8] = [0x5D, 0x03, 0xB1, 0x8D, 0xCD, 0xB1, 0x43, 0x33];
+/// Key of the `Sample` request, at "sample"
+pub const SAMPLE_REQ_KEY: [u8; 8] = [0xCE, 0x54, 0x49, 0x3B, 0x6A, 0x10, 0xB2, 0xE3];
+/// Key of the `Sample` response, at "sample"
+pub const SAMPLE_RESP_KEY: [u8; 8] = [0x3C, 0x66, 0x6A, 0x79, 0xD2, 0x7A, 0x92, 0x39];
+/// Key of the `Reboot` request, at "boot/reboot"
+pub const REBOOT_REQ_KEY: [u8; 8] = [0xD8, 0x8F, 0x97, 0x10, 0x3C, 0x79, 0xCB, 0xEF];
+/// Key of the `Reboot` response, at "boot/reboot"
+pub const REBOOT_RESP_KEY: [u8; 8] = [0xD8, 0x8F, 0x97, 0x10, 0x3C, 0x79, 0xCB, 0xEF];
+/// Key of the `Log` message, at "log"
+pub const LOG_KEY: [u8; 8] = [0xA7, 0xA0, 0x36, 0x1B, 0x81, 0xE1, 0x5F, 0xB2];
+/// Key of the `StatusEvents` message, at "status/v1"
+pub const STATUS_EVENTS_KEY: [u8; 8] = [0x5C, 0x6A, 0x4F, 0x7A, 0x42, 0x68, 0xE7, 0xB6];
+/// Key of the `Heartbeat` message, at "heartbeat"
+pub const HEARTBEAT_KEY: [u8; 8] = [0x0C, 0x90, 0xE7, 0x59, 0xE9, 0x81, 0xED, 0x34];
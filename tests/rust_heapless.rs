@@ -0,0 +1,163 @@
+//! The output of the `rust-heapless` generator, for
+//! `tests/rust_heapless/types.kdl`
+//!
+//! Like `tests/rust_std.rs`, the generated file is checked in and compiled as
+//! part of this test, run with `PIDL_BLESS=1` to update it.
+
+use postcard_idl::{
+    generate::rust_heapless::{generate_rust_heapless, Error, Options},
+    Pidl,
+};
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+#[path = "rust_heapless/types.rs"]
+mod types;
+
+const INPUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rust_heapless/types.kdl");
+const OUTPUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rust_heapless/types.rs");
+
+#[test]
+fn generated_is_current() {
+    let pidl = Pidl::parse_from_file(INPUT).unwrap();
+    let generated = generate_rust_heapless(&pidl, &Options::default())
+        .unwrap()
+        .to_string();
+    if std::env::var_os("PIDL_BLESS").is_some() {
+        std::fs::write(OUTPUT, &generated).unwrap();
+    }
+    let current = std::fs::read_to_string(OUTPUT).unwrap();
+    assert!(
+        generated == current,
+        "`tests/rust_heapless/types.rs` is out of date, run with `PIDL_BLESS=1` to update it"
+    );
+}
+
+#[test]
+fn generated_types_have_schemas() {
+    use postcard_schema::{key::Key, schema::owned::OwnedNamedType, Schema};
+
+    let pidl = Pidl::parse_from_file(INPUT).unwrap();
+    let find = |name: &str| pidl.types.iter().find(|t| t.name == name).unwrap();
+    // `heapless` types have their own names, which keys don't depend on
+    let key = |ont: &OwnedNamedType| Key::for_owned_schema_path("test", ont);
+    let derived = [
+        ("Message", types::Message::SCHEMA),
+        ("Command", types::Command::SCHEMA),
+        ("telemetry::Status", types::telemetry::Status::SCHEMA),
+    ];
+    for (name, schema) in derived {
+        assert_eq!(key(&schema.into()), key(find(name)), "{name}");
+    }
+}
+
+#[test]
+fn generated_types_round_trip() {
+    let mut counts = heapless::FnvIndexMap::new();
+    counts.insert("a".try_into().unwrap(), 1).unwrap();
+    let lookup = types::Lookup { counts };
+    let bytes = postcard::to_stdvec(&lookup).unwrap();
+    assert_eq!(
+        postcard::from_bytes::<types::Lookup>(&bytes).unwrap(),
+        lookup
+    );
+
+    let command = types::Command::Send {
+        to: "device".try_into().unwrap(),
+        data: heapless::Vec::from_slice(&[1, 2, 3]).unwrap(),
+    };
+    let bytes = postcard::to_stdvec(&command).unwrap();
+    assert_eq!(
+        bytes,
+        [3, 6, b'd', b'e', b'v', b'i', b'c', b'e', 3, 1, 2, 3]
+    );
+    assert_eq!(
+        postcard::from_bytes::<types::Command>(&bytes).unwrap(),
+        command
+    );
+}
+
+#[test]
+fn within_bounds_checks_minimums() {
+    let command = types::Command::Send {
+        to: "device".try_into().unwrap(),
+        data: heapless::Vec::new(),
+    };
+    assert!(!command.within_bounds());
+    assert!(types::Command::Say("".try_into().unwrap()).within_bounds());
+
+    let mut counts = heapless::FnvIndexMap::new();
+    counts.insert("".try_into().unwrap(), 1).unwrap();
+    assert!(!types::Lookup { counts }.within_bounds());
+}
+
+#[test]
+fn missing_capacities_are_errors() {
+    let generate = |src: &str| {
+        let pidl = Pidl::parse_from_str(src).unwrap();
+        generate_rust_heapless(&pidl, &Options::default())
+    };
+
+    let err = generate(r#"types { struct "S" { tags "[string]" max=4 } }"#).unwrap_err();
+    assert!(
+        matches!(&err, Error::MissingCapacity { kind: "string", location } if location == "field `tags` of `S`"),
+        "{err:?}"
+    );
+
+    let err = generate(r#"types { alias "Tag" "string" max=8; struct "S" { tags "[Tag]" } }"#)
+        .unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            Error::MissingCapacity {
+                kind: "sequence",
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+
+    let err = generate(r#"types { struct "List" { tail "option<List>" } }"#).unwrap_err();
+    assert!(
+        matches!(&err, Error::Recursive { name } if name == "List"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn messages_with_maps_are_errors() {
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            alias "Name" "string" max=8
+            struct "Lookup" { counts "map<Name, u32>" max=4 }
+        }
+        endpoints {
+            endpoint "GetCounts" path="counts" req="()" resp="option<Lookup>"
+        }"#,
+    )
+    .unwrap();
+    let err = generate_rust_heapless(&pidl, &Options::default()).unwrap_err();
+    assert!(
+        matches!(&err, Error::MapInMessage { location } if location == "the response of endpoint `GetCounts`"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn schemas_are_errors() {
+    use postcard_schema::schema::owned::{OwnedDataModelType, OwnedNamedType};
+
+    let mut pidl = Pidl::parse_from_str(r#"types { struct "Described" { ty "()" } }"#).unwrap();
+    let OwnedDataModelType::Struct(fields) = &mut pidl.types[0].ty else {
+        panic!();
+    };
+    fields[0].ty = OwnedNamedType {
+        name: "OwnedNamedType".into(),
+        ty: OwnedDataModelType::Schema,
+    };
+    let err = generate_rust_heapless(&pidl, &Options::default()).unwrap_err();
+    assert!(
+        matches!(&err, Error::Schema { location } if location == "field `ty` of `Described`"),
+        "{err:?}"
+    );
+}
@@ -0,0 +1,87 @@
+// @generated by pidl, do not edit
+// source hash: d0816def6e667181
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+/// Names are never empty
+pub type Name = heapless::String<16>;
+pub type MaybeName = Option<heapless::String<8>>;
+pub type Samples = heapless::Vec<i16, 32>;
+pub type Pair = (u8, heapless::String<4>);
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Rgb8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq)]
+pub struct Frame(pub heapless::Vec<u8, 64>);
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Who sent it
+    pub name: heapless::String<16>,
+    pub nick: heapless::String<4>,
+    pub payload: heapless::Vec<u8, 128>,
+    pub tags: heapless::Vec<heapless::String<16>, 4>,
+    pub note: Option<heapless::String<32>>,
+    pub both: [heapless::String<8>; 2],
+    pub color: Rgb8,
+}
+
+impl Message {
+    /// Are all lengths within the limits set in the IDL?
+    pub fn within_bounds(&self) -> bool {
+        !self.name.is_empty()
+            && !self.payload.is_empty()
+            && self.tags.iter().all(|x0| !x0.is_empty())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Lookup {
+    pub counts: heapless::FnvIndexMap<heapless::String<16>, u32, 8>,
+}
+
+impl Lookup {
+    /// Are all lengths within the limits set in the IDL?
+    pub fn within_bounds(&self) -> bool {
+        self.counts.len() <= 8 && self.counts.keys().all(|x0| !x0.is_empty())
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Default)]
+pub enum Command {
+    #[default]
+    Stop,
+    Say(heapless::String<64>),
+    Blink(Rgb8, heapless::Vec<u8, 4>),
+    Send {
+        to: heapless::String<16>,
+        data: heapless::Vec<u8, 16>,
+    },
+}
+
+impl Command {
+    /// Are all lengths within the limits set in the IDL?
+    pub fn within_bounds(&self) -> bool {
+        match self {
+            Self::Send { to, data } => !to.is_empty() && !data.is_empty(),
+            _ => true,
+        }
+    }
+}
+
+pub mod telemetry {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq)]
+    pub struct Status {
+        pub label: heapless::String<32>,
+        pub color: super::Rgb8,
+        pub uptime: u64,
+    }
+}
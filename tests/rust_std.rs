@@ -0,0 +1,201 @@
+//! The output of the `rust-std` generator, for `tests/rust_std/types.kdl`
+//!
+//! The generated file is checked in and compiled as part of this test, run
+//! with `PIDL_BLESS=1` to update it after changing the generator.
+
+use postcard_idl::{
+    generate::rust_std::{generate_rust_std, Options},
+    Pidl,
+};
+
+#[allow(dead_code, unused_imports)]
+#[rustfmt::skip]
+#[path = "rust_std/types.rs"]
+mod types;
+
+const INPUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rust_std/types.kdl");
+const OUTPUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/rust_std/types.rs");
+
+#[test]
+fn generated_is_current() {
+    let pidl = Pidl::parse_from_file(INPUT).unwrap();
+    let options = Options {
+        borrowed: true,
+        ..Options::default()
+    };
+    let generated = generate_rust_std(&pidl, &options).to_string();
+    if std::env::var_os("PIDL_BLESS").is_some() {
+        std::fs::write(OUTPUT, &generated).unwrap();
+    }
+    let current = std::fs::read_to_string(OUTPUT).unwrap();
+    assert!(
+        generated == current,
+        "`tests/rust_std/types.rs` is out of date, run with `PIDL_BLESS=1` to update it"
+    );
+}
+
+#[test]
+fn generated_types_have_schemas() {
+    use postcard_schema::{schema::owned::OwnedNamedType, Schema};
+
+    let pidl = Pidl::parse_from_file(INPUT).unwrap();
+    let find = |name: &str| pidl.types.iter().find(|t| t.name == name).unwrap();
+    let derived = OwnedNamedType::from(types::Example::SCHEMA);
+    assert_eq!(&derived, find("Example"));
+}
+
+#[test]
+fn aliases_of_user_types_are_the_same_type() {
+    use postcard_schema::Schema;
+
+    let pidl = Pidl::parse_from_file(INPUT).unwrap();
+    assert_eq!(pidl.aliases["AlterTupleStruct"], "TupleStruct");
+    let alias: types::AlterTupleStruct = types::TupleStruct(1, 2);
+    assert_eq!(alias, types::TupleStruct(1, 2));
+    assert_eq!(types::AlterTupleStruct::SCHEMA, types::TupleStruct::SCHEMA);
+}
+
+#[test]
+fn newtype_and_tuple_structs_have_fields() {
+    let tuple = types::TupleStruct(-1, 2);
+    assert_eq!((tuple.0, tuple.1), (-1, 2));
+    assert!(types::NewTypeStruct(true).0);
+    let bytes = postcard::to_stdvec(&tuple).unwrap();
+    assert_eq!(bytes, [1, 4]);
+}
+
+#[test]
+fn enums_default_to_their_first_unit_variant() {
+    assert_eq!(types::Example::default(), types::Example::Empty);
+
+    // Without a unit variant, there is nothing to mark `#[default]`
+    let pidl = Pidl::parse_from_str(
+        r#"types { enum "Reading" derive="Default" { Volts "u16"; Amps "u16" } }"#,
+    )
+    .unwrap();
+    let out = generate_rust_std(&pidl, &Options::default());
+    assert_eq!(
+        out.warnings,
+        ["`Default` is not derived for `Reading`, it has no unit variant to be the default"]
+    );
+    assert!(!out.to_string().contains("Default"));
+}
+
+#[test]
+fn schemas_are_owned_named_types() {
+    use postcard_schema::schema::owned::{OwnedDataModelType, OwnedNamedType};
+
+    // IDL files can't have schemas, but documents built from types can
+    let mut pidl = Pidl::parse_from_str(r#"types { struct "Described" { ty "()" } }"#).unwrap();
+    let OwnedDataModelType::Struct(fields) = &mut pidl.types[0].ty else {
+        panic!();
+    };
+    fields[0].ty = OwnedNamedType {
+        name: "OwnedNamedType".into(),
+        ty: OwnedDataModelType::Schema,
+    };
+    let generated = generate_rust_std(&pidl, &Options::default()).to_string();
+    assert!(
+        generated.contains("use postcard_schema::schema::owned::OwnedNamedType;"),
+        "{generated}"
+    );
+    assert!(generated.contains("pub ty: OwnedNamedType,"), "{generated}");
+}
+
+#[test]
+fn generated_types_have_derives() {
+    let color = types::Rgb8::default();
+    assert_eq!(color.clone(), color);
+    assert_eq!(format!("{color:?}"), "Rgb8 { r: 0, g: 0, b: 0 }");
+}
+
+#[test]
+fn borrowed_types_convert() {
+    use std::collections::HashMap;
+
+    let owned = types::Thread {
+        message: types::Message {
+            name: "hello".into(),
+            payload: vec![1, 2, 3],
+            tags: vec!["a".into(), "b".into()],
+            counts: HashMap::from([("c".into(), 4)]),
+            note: Some("note".into()),
+            pair: ("d".into(), 5),
+            both: ["e".into(), "f".into()],
+            color: types::Rgb8::default(),
+        },
+        replies: vec![],
+        parent: None,
+    };
+    let bytes = postcard::to_stdvec(&owned).unwrap();
+
+    // The borrowed version has the same layout, and borrows from the input
+    let borrowed: types::borrowed::Thread<'_> = postcard::from_bytes(&bytes).unwrap();
+    assert_eq!(borrowed.message.name, "hello");
+    assert_eq!(borrowed.message.payload, [1, 2, 3]);
+    assert_eq!(postcard::to_stdvec(&borrowed.to_owned()).unwrap(), bytes);
+
+    let reborrowed = types::borrowed::Thread::from(&owned);
+    let owned = types::Thread::from(reborrowed);
+    assert_eq!(postcard::to_stdvec(&owned).unwrap(), bytes);
+}
+
+#[test]
+fn within_bounds_checks_lengths() {
+    let command = types::Command::Send {
+        to: "device".into(),
+        data: vec![],
+    };
+    assert!(!command.within_bounds());
+    assert!(types::Command::Say("hello".into()).within_bounds());
+    assert!(!types::Command::Say("!".repeat(65)).within_bounds());
+
+    // Checked through other types
+    let status = types::telemetry::Status {
+        label: "x".repeat(33),
+        color: types::Rgb8::default(),
+        uptime: 0,
+    };
+    let reading = types::telemetry::power::Reading {
+        status,
+        millivolts: 3300,
+    };
+    assert!(!reading.within_bounds());
+
+    // Checked through aliases
+    let grove: types::Grove = types::Tree::Node(vec![types::Tree::Leaf(1); 5]);
+    assert!(!grove.within_bounds());
+}
+
+#[test]
+fn samples_decode_as_generated_types() {
+    use postcard_idl::pidl::sample;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    fn round_trip<T: Serialize + DeserializeOwned>(pidl: &Pidl, name: &str) {
+        let ty = pidl.types.iter().find(|t| t.name == name).unwrap();
+        let sample = sample(pidl, ty).unwrap();
+        let value: T = postcard::from_bytes(&sample.bytes).unwrap();
+        assert_eq!(postcard::to_stdvec(&value).unwrap(), sample.bytes, "{name}");
+    }
+
+    let pidl = Pidl::parse_from_file(INPUT).unwrap();
+    round_trip::<types::SameNumbers>(&pidl, "SameNumbers");
+    round_trip::<types::Lookup>(&pidl, "Lookup");
+    round_trip::<types::Example>(&pidl, "Example");
+    round_trip::<types::AdHocTypes>(&pidl, "AdHocTypes");
+    round_trip::<types::Command>(&pidl, "Command");
+    round_trip::<types::Thread>(&pidl, "Thread");
+    round_trip::<types::List>(&pidl, "List");
+    round_trip::<types::telemetry::power::Reading>(&pidl, "telemetry::power::Reading");
+}
+
+#[test]
+fn tuples_and_arrays_are_written_as_declared() {
+    let pidl =
+        Pidl::parse_from_str(r#"types { struct "Pairs" { tuple "(u8, u8)"; array "[u8; 2]" } }"#)
+            .unwrap();
+    let generated = generate_rust_std(&pidl, &Options::default()).to_string();
+    assert!(generated.contains("pub tuple: (u8, u8),"), "{generated}");
+    assert!(generated.contains("pub array: [u8; 2],"), "{generated}");
+}
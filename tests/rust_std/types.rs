@@ -0,0 +1,469 @@
+// @generated by pidl, do not edit
+// source hash: 9217ba5a4262c5ce
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub type GoodNumber = u32;
+pub type MaybeNumber = Option<f64>;
+pub type ManyNumbers = (u8, u16, u32);
+pub type SameNumbers = [i16; 8];
+pub type SomeNumbers = Vec<i16>;
+pub type Lookup = HashMap<String, u32>;
+pub type AlterTupleStruct = TupleStruct;
+/// Names are never empty
+pub type Name = String;
+pub type Grove = Tree;
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct UnitStruct;
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct NewTypeStruct(pub bool);
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq)]
+pub struct TupleStruct(pub i32, pub i32);
+
+/// A color, with 8 bits per channel
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Rgb8 {
+    /// Red
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone, PartialEq, Default)]
+#[allow(clippy::large_enum_variant)]
+pub enum Example {
+    #[default]
+    Empty,
+    Color(Rgb8),
+    Pair(u32, bool),
+    Fields {
+        bar: u64,
+        tricky: TupleStruct,
+    },
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct AdHocTypes {
+    pub options: Option<u64>,
+    pub seqs: Vec<u8>,
+    pub tuples: (bool, bool, u8),
+    pub arrays: [u16; 4],
+    pub maps: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub struct Message {
+    pub name: String,
+    pub payload: Vec<u8>,
+    pub tags: Vec<String>,
+    pub counts: HashMap<String, u32>,
+    pub note: Option<String>,
+    pub pair: (String, u8),
+    pub both: [String; 2],
+    pub color: Rgb8,
+}
+
+impl Message {
+    /// Are all lengths within the limits set in the IDL?
+    pub fn within_bounds(&self) -> bool {
+        (1..=16).contains(&self.name.len())
+            && self.tags.len() <= 8 && self.tags.iter().all(|x0| (1..=16).contains(&x0.len()))
+            && self.counts.len() <= 4
+            && self.note.iter().all(|x0| !x0.is_empty())
+            && self.pair.0.len() <= 8
+    }
+}
+
+/// Something to do
+#[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+pub enum Command {
+    Stop,
+    /// Show some text
+    ///
+    /// Long text is cut off
+    Say(String),
+    Blink(Rgb8, String),
+    Send {
+        /// Who to send to
+        to: String,
+        data: Vec<u8>,
+    },
+}
+
+impl Command {
+    /// Are all lengths within the limits set in the IDL?
+    pub fn within_bounds(&self) -> bool {
+        match self {
+            Self::Say(v0) => v0.len() <= 64,
+            Self::Send { data, .. } => (1..=16).contains(&data.len()),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Tree {
+    Leaf(u32),
+    Node(Vec<Tree>),
+}
+
+impl Tree {
+    /// Are all lengths within the limits set in the IDL?
+    pub fn within_bounds(&self) -> bool {
+        match self {
+            Self::Node(v0) => v0.len() <= 4 && v0.iter().all(|x0| x0.within_bounds()),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct List {
+    pub head: u32,
+    pub tail: Option<Box<List>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Thread {
+    pub message: Message,
+    pub replies: Vec<Thread>,
+    pub parent: Option<Box<Thread>>,
+}
+
+impl Thread {
+    /// Are all lengths within the limits set in the IDL?
+    pub fn within_bounds(&self) -> bool {
+        self.message.within_bounds()
+            && self.replies.iter().all(|x0| x0.within_bounds())
+            && self.parent.iter().all(|x0| x0.within_bounds())
+    }
+}
+
+pub mod telemetry {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+    #[non_exhaustive]
+    pub struct Status {
+        pub label: String,
+        pub color: super::Rgb8,
+        pub uptime: u64,
+    }
+
+    impl Status {
+        /// Are all lengths within the limits set in the IDL?
+        pub fn within_bounds(&self) -> bool {
+            self.label.len() <= 32
+        }
+    }
+
+    pub mod power {
+        use super::*;
+
+        #[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+        #[non_exhaustive]
+        pub struct Reading {
+            pub status: super::Status,
+            pub millivolts: u16,
+        }
+
+        impl Reading {
+            /// Are all lengths within the limits set in the IDL?
+            pub fn within_bounds(&self) -> bool {
+                self.status.within_bounds()
+            }
+        }
+    }
+}
+
+pub mod borrowed {
+    use super::*;
+
+    pub use super::GoodNumber;
+    pub use super::MaybeNumber;
+    pub use super::ManyNumbers;
+    pub use super::SameNumbers;
+    pub use super::SomeNumbers;
+    pub type Lookup<'a> = HashMap<&'a str, u32>;
+    pub use super::UnitStruct;
+    pub use super::NewTypeStruct;
+    pub use super::TupleStruct;
+    pub use super::AlterTupleStruct;
+    pub use super::Rgb8;
+    pub use super::Example;
+    /// Names are never empty
+    pub type Name<'a> = &'a str;
+    pub use super::Tree;
+    pub use super::Grove;
+    pub use super::List;
+
+    #[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+    pub struct AdHocTypes<'a> {
+        pub options: Option<u64>,
+        #[serde(borrow)]
+        pub seqs: &'a [u8],
+        pub tuples: (bool, bool, u8),
+        pub arrays: [u16; 4],
+        #[serde(borrow)]
+        pub maps: HashMap<&'a str, &'a str>,
+    }
+
+    impl<'a> From<&'a super::AdHocTypes> for AdHocTypes<'a> {
+        fn from(value: &'a super::AdHocTypes) -> Self {
+            Self {
+                options: value.options,
+                seqs: value.seqs.as_slice(),
+                tuples: value.tuples,
+                arrays: value.arrays,
+                maps: value.maps.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect(),
+            }
+        }
+    }
+
+    impl From<AdHocTypes<'_>> for super::AdHocTypes {
+        fn from(value: AdHocTypes<'_>) -> Self {
+            Self {
+                options: value.options,
+                seqs: value.seqs.to_vec(),
+                tuples: value.tuples,
+                arrays: value.arrays,
+                maps: value.maps.into_iter().map(|(k, v)| (String::from(k), String::from(v))).collect(),
+            }
+        }
+    }
+
+    impl AdHocTypes<'_> {
+        /// Copy this into the owned version of the type
+        pub fn to_owned(&self) -> super::AdHocTypes {
+            self.clone().into()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+    pub struct Message<'a> {
+        #[serde(borrow)]
+        pub name: &'a str,
+        #[serde(borrow)]
+        pub payload: &'a [u8],
+        #[serde(borrow)]
+        pub tags: Vec<&'a str>,
+        #[serde(borrow)]
+        pub counts: HashMap<&'a str, u32>,
+        #[serde(borrow)]
+        pub note: Option<&'a str>,
+        #[serde(borrow)]
+        pub pair: (&'a str, u8),
+        #[serde(borrow)]
+        pub both: [&'a str; 2],
+        pub color: Rgb8,
+    }
+
+    impl<'a> From<&'a super::Message> for Message<'a> {
+        fn from(value: &'a super::Message) -> Self {
+            Self {
+                name: value.name.as_str(),
+                payload: value.payload.as_slice(),
+                tags: value.tags.iter().map(String::as_str).collect(),
+                counts: value.counts.iter().map(|(k, v)| (k.as_str(), *v)).collect(),
+                note: value.note.as_deref(),
+                pair: (value.pair.0.as_str(), value.pair.1),
+                both: value.both.each_ref().map(String::as_str),
+                color: value.color.clone(),
+            }
+        }
+    }
+
+    impl From<Message<'_>> for super::Message {
+        fn from(value: Message<'_>) -> Self {
+            Self {
+                name: String::from(value.name),
+                payload: value.payload.to_vec(),
+                tags: value.tags.into_iter().map(String::from).collect(),
+                counts: value.counts.into_iter().map(|(k, v)| (String::from(k), v)).collect(),
+                note: value.note.map(String::from),
+                pair: (String::from(value.pair.0), value.pair.1),
+                both: value.both.map(String::from),
+                color: value.color,
+            }
+        }
+    }
+
+    impl Message<'_> {
+        /// Copy this into the owned version of the type
+        pub fn to_owned(&self) -> super::Message {
+            self.clone().into()
+        }
+    }
+
+    /// Something to do
+    #[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+    pub enum Command<'a> {
+        Stop,
+        /// Show some text
+        ///
+        /// Long text is cut off
+        Say(#[serde(borrow)] &'a str),
+        Blink(Rgb8, #[serde(borrow)] &'a str),
+        Send {
+            /// Who to send to
+            #[serde(borrow)]
+            to: &'a str,
+            #[serde(borrow)]
+            data: &'a [u8],
+        },
+    }
+
+    impl<'a> From<&'a super::Command> for Command<'a> {
+        fn from(value: &'a super::Command) -> Self {
+            match value {
+                super::Command::Stop => Self::Stop,
+                super::Command::Say(v0) => Self::Say(v0.as_str()),
+                super::Command::Blink(v0, v1) => Self::Blink(v0.clone(), v1.as_str()),
+                super::Command::Send { to, data } => Self::Send {
+                    to: to.as_str(),
+                    data: data.as_slice(),
+                },
+            }
+        }
+    }
+
+    impl From<Command<'_>> for super::Command {
+        fn from(value: Command<'_>) -> Self {
+            match value {
+                Command::Stop => Self::Stop,
+                Command::Say(v0) => Self::Say(String::from(v0)),
+                Command::Blink(v0, v1) => Self::Blink(v0, String::from(v1)),
+                Command::Send { to, data } => Self::Send {
+                    to: String::from(to),
+                    data: data.to_vec(),
+                },
+            }
+        }
+    }
+
+    impl Command<'_> {
+        /// Copy this into the owned version of the type
+        pub fn to_owned(&self) -> super::Command {
+            self.clone().into()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Thread<'a> {
+        #[serde(borrow)]
+        pub message: Message<'a>,
+        #[serde(borrow)]
+        pub replies: Vec<Thread<'a>>,
+        #[serde(borrow)]
+        pub parent: Option<Box<Thread<'a>>>,
+    }
+
+    impl<'a> From<&'a super::Thread> for Thread<'a> {
+        fn from(value: &'a super::Thread) -> Self {
+            Self {
+                message: Message::from(&value.message),
+                replies: value.replies.iter().map(Thread::from).collect(),
+                parent: value.parent.as_ref().map(|v| Box::new(Thread::from(&**v))),
+            }
+        }
+    }
+
+    impl From<Thread<'_>> for super::Thread {
+        fn from(value: Thread<'_>) -> Self {
+            Self {
+                message: super::Message::from(value.message),
+                replies: value.replies.into_iter().map(super::Thread::from).collect(),
+                parent: value.parent.map(|v| Box::new(super::Thread::from(*v))),
+            }
+        }
+    }
+
+    impl Thread<'_> {
+        /// Copy this into the owned version of the type
+        pub fn to_owned(&self) -> super::Thread {
+            self.clone().into()
+        }
+    }
+
+    pub mod telemetry {
+        use super::*;
+
+        #[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+        #[non_exhaustive]
+        pub struct Status<'a> {
+            #[serde(borrow)]
+            pub label: &'a str,
+            pub color: super::Rgb8,
+            pub uptime: u64,
+        }
+
+        impl<'a> From<&'a super::super::telemetry::Status> for Status<'a> {
+            fn from(value: &'a super::super::telemetry::Status) -> Self {
+                Self {
+                    label: value.label.as_str(),
+                    color: value.color.clone(),
+                    uptime: value.uptime,
+                }
+            }
+        }
+
+        impl From<Status<'_>> for super::super::telemetry::Status {
+            fn from(value: Status<'_>) -> Self {
+                Self {
+                    label: String::from(value.label),
+                    color: value.color,
+                    uptime: value.uptime,
+                }
+            }
+        }
+
+        impl Status<'_> {
+            /// Copy this into the owned version of the type
+            pub fn to_owned(&self) -> super::super::telemetry::Status {
+                self.clone().into()
+            }
+        }
+
+        pub mod power {
+            use super::*;
+
+            #[derive(Serialize, Deserialize, Schema, Debug, Clone)]
+            #[non_exhaustive]
+            pub struct Reading<'a> {
+                #[serde(borrow)]
+                pub status: super::Status<'a>,
+                pub millivolts: u16,
+            }
+
+            impl<'a> From<&'a super::super::super::telemetry::power::Reading> for Reading<'a> {
+                fn from(value: &'a super::super::super::telemetry::power::Reading) -> Self {
+                    Self {
+                        status: super::Status::from(&value.status),
+                        millivolts: value.millivolts,
+                    }
+                }
+            }
+
+            impl From<Reading<'_>> for super::super::super::telemetry::power::Reading {
+                fn from(value: Reading<'_>) -> Self {
+                    Self {
+                        status: super::super::super::telemetry::Status::from(value.status),
+                        millivolts: value.millivolts,
+                    }
+                }
+            }
+
+            impl Reading<'_> {
+                /// Copy this into the owned version of the type
+                pub fn to_owned(&self) -> super::super::super::telemetry::power::Reading {
+                    self.clone().into()
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Largest encoded sizes, checked against what postcard actually writes
+
+use postcard_idl::{
+    pidl::{max_size, MaxSize},
+    Pidl,
+};
+use serde::Serialize;
+
+const IDL: &str = r#"
+types {
+    alias "Name" "string" max=8
+    struct "Reading" {
+        id     "u32"
+        name   "Name"
+        values "[i16]" max=3
+        extra  "option<(u64, bool)>"
+        pair   "[char; 2]"
+    }
+    enum "Event" {
+        Idle
+        Read "Reading"
+        Tags "[Name]" max=2
+    }
+    struct "Log" {
+        entries "[Event]"
+    }
+    struct "List" {
+        tail "option<List>"
+    }
+    alias "Entry" "Reading"
+    alias "Chain" "List"
+    struct "Forest" {
+        trees "[Tree]" max=2
+    }
+    enum "Tree" {
+        Leaf "u8"
+        Node "[Tree]"
+    }
+}
+"#;
+
+#[derive(Serialize)]
+struct Reading {
+    id: u32,
+    name: String,
+    values: Vec<i16>,
+    extra: Option<(u64, bool)>,
+    pair: [char; 2],
+}
+
+fn size(pidl: &Pidl, name: &str) -> MaxSize {
+    let ty = pidl.types.iter().find(|t| t.name == name).unwrap();
+    max_size(pidl, ty)
+}
+
+#[test]
+fn sizes_are_worst_cases() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let largest = Reading {
+        id: u32::MAX,
+        name: "x".repeat(8),
+        values: vec![i16::MIN; 3],
+        extra: Some((u64::MAX, true)),
+        pair: ['\u{10FFFF}'; 2],
+    };
+    let encoded = postcard::to_stdvec(&largest).unwrap();
+    assert_eq!(size(&pidl, "Reading"), MaxSize::Bounded(encoded.len()));
+
+    assert_eq!(size(&pidl, "Name"), MaxSize::Bounded(9));
+    // The discriminant, and the largest variant
+    assert_eq!(size(&pidl, "Event"), MaxSize::Bounded(1 + encoded.len()));
+    // Aliases have the limits of the type they stand for
+    assert_eq!(size(&pidl, "Entry"), MaxSize::Bounded(encoded.len()));
+}
+
+#[test]
+fn unbounded_sizes_have_reasons() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    assert_eq!(
+        size(&pidl, "Log"),
+        MaxSize::Unbounded("the sequence in `Log.entries` has no `max=`".into())
+    );
+    assert_eq!(
+        size(&pidl, "List"),
+        MaxSize::Unbounded("`List` is recursive".into())
+    );
+    // Recursion is found before any missing limits, through aliases and
+    // other types
+    assert_eq!(
+        size(&pidl, "Chain"),
+        MaxSize::Unbounded("`List` is recursive".into())
+    );
+    for name in ["Forest", "Tree"] {
+        assert_eq!(
+            size(&pidl, name),
+            MaxSize::Unbounded("`Tree` is recursive".into())
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! The Typst protocol document
+
+use postcard_idl::{generate::typst::generate_typst, Pidl};
+
+const IDL: &str = r#"types {
+    alias "Name" "string" max=8
+    /// Something that happened
+    enum "Event" {
+        Idle
+        /// Someone said hello
+        Named { name "Name"; at "i64" }
+    }
+}
+endpoints {
+    endpoint "GetEvent" path="event/get" req="()" resp="Event"
+}
+topics {
+    topic "Events" path="event/v1" ty="Event" direction="out"
+}
+protocol "Device" {
+    endpoints "GetEvent"
+    topics "Events"
+}
+"#;
+
+#[test]
+fn document_covers_everything() {
+    let pidl = Pidl::parse_from_str(IDL).unwrap();
+    let doc = generate_typst(&pidl);
+
+    assert!(doc.contains("== `Event` <type-Event>\n\nSomething that happened\n"));
+    assert!(doc.contains("[0], [`Idle`], [nothing], [],"));
+    assert!(doc.contains("[1], [`Named`], [the fields below], [Someone said hello],"));
+    assert!(doc.contains("=== `Event::Named`"));
+    assert!(doc.contains("[`at`], [`i64`], [zigzag varint], [],"));
+    assert!(doc.contains("[`name`], [#link(<type-Name>)[`Name`]], [varint length in bytes"));
+    assert!(doc.contains("It stands for `string`"));
+    assert!(doc.contains("Lengths are limited to at most 8."));
+
+    let key = |key| format!("`{}`", hex(key));
+    let ep = &pidl.endpoints[0];
+    assert!(doc.contains("== `GetEvent` <endpoint-GetEvent>"));
+    assert!(doc.contains(&key(ep.req_key())));
+    assert!(doc.contains(&key(ep.resp_key())));
+    let tp = &pidl.topics[0];
+    assert!(doc.contains("== `Events` <topic-Events>"));
+    assert!(doc.contains(
+        "Messages on `event/v1` are #link(<type-Event>)[`Event`], and are sent from the server \
+         to the client."
+    ));
+    assert!(doc.contains(&key(tp.key())));
+    assert!(doc.contains("== `Device` <protocol-Device>"));
+    assert!(doc.contains("- Endpoints: #link(<endpoint-GetEvent>)[`GetEvent`]"));
+}
+
+#[test]
+fn text_is_escaped() {
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            /// Costs $5 #now, see <https://example.com>, `a*b` stays raw
+            alias "Price" "u32"
+        }"#,
+    )
+    .unwrap();
+    let doc = generate_typst(&pidl);
+    assert!(doc.contains(r"Costs \$5 \#now, see \<https:\/\/example.com\>, `a*b` stays raw"));
+}
+
+fn hex(key: postcard_schema::key::Key) -> String {
+    key.to_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[test]
+fn labels_are_unique() {
+    // Endpoints and topics can't share the names of types, but everything
+    // else can, and names may only differ in case
+    let pidl = Pidl::parse_from_str(
+        r#"types {
+            struct "Status" { up "bool" }
+            struct "STATUS" { up "bool" }
+        }
+        endpoints {
+            endpoint "Reading" path="reading/get" req="()" resp="Status"
+        }
+        topics {
+            topic "READING" path="reading" ty="STATUS" direction="out"
+        }
+        protocol "Status" {
+            endpoints "Reading"
+        }"#,
+    )
+    .unwrap();
+    let doc = generate_typst(&pidl);
+
+    for label in [
+        "<type-Status>",
+        "<type-STATUS>",
+        "<endpoint-Reading>",
+        "<topic-READING>",
+        "<protocol-Status>",
+    ] {
+        assert_eq!(doc.matches(&format!(" {label}")).count(), 1, "{label}");
+    }
+}